@@ -0,0 +1,656 @@
+//! Command-line parsing: the global options, the option table of every
+//! subcommand, and the help text generated from them.
+
+use crate::error::{Error, Result};
+
+/// An option accepted by a command.
+#[derive(Debug)]
+pub struct OptSpec {
+    pub short: Option<char>,
+    pub long: &'static str,
+    /// Placeholder for the option's argument, or `None` for a boolean flag.
+    pub value: Option<&'static str>,
+    pub help: &'static str,
+}
+
+/// Everything the parser and the help output need to know about a command.
+#[derive(Debug)]
+pub struct CommandSpec {
+    pub name: &'static str,
+    pub about: &'static str,
+    /// Usage lines, without the leading `xit`.
+    pub usage: &'static [&'static str],
+    pub options: &'static [OptSpec],
+}
+
+const fn flag(short: Option<char>, long: &'static str, help: &'static str) -> OptSpec {
+    OptSpec {
+        short,
+        long,
+        value: None,
+        help,
+    }
+}
+
+const fn option(
+    short: Option<char>,
+    long: &'static str,
+    value: &'static str,
+    help: &'static str,
+) -> OptSpec {
+    OptSpec {
+        short,
+        long,
+        value: Some(value),
+        help,
+    }
+}
+
+const HELP: OptSpec = flag(Some('h'), "help", "Show this help");
+
+/// Options accepted before the command name.
+pub const GLOBAL_OPTIONS: &[OptSpec] = &[
+    option(
+        Some('C'),
+        "directory",
+        "path",
+        "Run as if xit was started in <path>",
+    ),
+    option(
+        None,
+        "xit-dir",
+        "path",
+        "Use <path> as the .xit directory (overrides XIT_DIR)",
+    ),
+    option(
+        None,
+        "work-tree",
+        "path",
+        "Use <path> as the working tree (overrides XIT_WORK_TREE)",
+    ),
+    flag(None, "version", "Print the xit version"),
+    HELP,
+];
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "init",
+        about: "Create an empty repository",
+        usage: &["init [<directory>]"],
+        options: &[HELP],
+    },
+    CommandSpec {
+        name: "setup",
+        about: "Set your global user name and email",
+        usage: &["setup [--name <name> --email <email>]"],
+        options: &[
+            option(None, "name", "name", "User name to record in commits"),
+            option(None, "email", "email", "Email to record in commits"),
+            HELP,
+        ],
+    },
+    CommandSpec {
+        name: "add",
+        about: "Add file contents to the index",
+        usage: &["add <pathspec>..."],
+        options: &[HELP],
+    },
+    CommandSpec {
+        name: "commit",
+        about: "Record the staged changes",
+        usage: &["commit -m <message> [-m <message>...]"],
+        options: &[
+            option(
+                Some('m'),
+                "message",
+                "message",
+                "Commit message; several -m options become separate paragraphs",
+            ),
+            HELP,
+        ],
+    },
+    CommandSpec {
+        name: "status",
+        about: "Show the working tree status",
+        usage: &["status"],
+        options: &[HELP],
+    },
+    CommandSpec {
+        name: "diff",
+        about: "Show changes between the working tree, the index and commits",
+        usage: &[
+            "diff [<commit>]",
+            "diff --cached [<commit>]",
+            "diff <commit> <commit>",
+        ],
+        options: &[
+            flag(
+                None,
+                "cached",
+                "Compare the index with HEAD or the given commit",
+            ),
+            flag(None, "staged", "Synonym for --cached"),
+            option(
+                Some('U'),
+                "unified",
+                "n",
+                "Show <n> lines of context (default 3)",
+            ),
+            option(
+                None,
+                "diff-algorithm",
+                "algorithm",
+                "myers, minimal, patience or histogram (default: diff.algorithm or myers)",
+            ),
+            flag(None, "minimal", "Same as --diff-algorithm=minimal"),
+            flag(None, "patience", "Same as --diff-algorithm=patience"),
+            flag(None, "histogram", "Same as --diff-algorithm=histogram"),
+            flag(None, "no-color", "Do not color the output"),
+            HELP,
+        ],
+    },
+    CommandSpec {
+        name: "log",
+        about: "Show commit history",
+        usage: &["log [<options>] [<revision>...] [[--] <path>...]"],
+        options: &[
+            flag(None, "oneline", "Show each commit on one line"),
+            option(
+                Some('n'),
+                "max-count",
+                "number",
+                "Show at most <number> commits",
+            ),
+            option(
+                None,
+                "format",
+                "format",
+                "oneline, short, medium, full or a template such as '%h %an %s'",
+            ),
+            option(None, "pretty", "format", "Synonym for --format"),
+            option(
+                None,
+                "author",
+                "pattern",
+                "Only commits whose author contains <pattern>",
+            ),
+            option(
+                None,
+                "since",
+                "date",
+                "Only commits newer than <date> (e.g. 2024-01-31, '2 weeks ago')",
+            ),
+            option(None, "after", "date", "Synonym for --since"),
+            option(None, "until", "date", "Only commits older than <date>"),
+            option(None, "before", "date", "Synonym for --until"),
+            flag(None, "reverse", "Show the oldest commits first"),
+            flag(
+                None,
+                "first-parent",
+                "Only follow the first parent of merge commits",
+            ),
+            flag(
+                None,
+                "graph",
+                "Draw the branch and merge history next to the commits",
+            ),
+            flag(
+                None,
+                "decorate",
+                "Show the branches and tags pointing at each commit",
+            ),
+            flag(None, "no-decorate", "Do not show branches and tags"),
+            flag(None, "no-color", "Do not color the output"),
+            HELP,
+        ],
+    },
+    CommandSpec {
+        name: "branch",
+        about: "List, create, rename or delete branches",
+        usage: &[
+            "branch [-v]",
+            "branch [-f] <name> [<start-point>]",
+            "branch (-d | -D) <name>...",
+            "branch (-m | -M) [<old-name>] <new-name>",
+        ],
+        options: &[
+            flag(
+                Some('v'),
+                "verbose",
+                "Show the commit at the tip of each branch",
+            ),
+            flag(
+                Some('d'),
+                "delete",
+                "Delete branches that are merged into HEAD",
+            ),
+            flag(
+                Some('D'),
+                "delete-force",
+                "Delete branches even if they are not merged",
+            ),
+            flag(Some('m'), "move", "Rename a branch"),
+            flag(
+                Some('M'),
+                "move-force",
+                "Rename a branch even if the new name exists",
+            ),
+            flag(
+                Some('f'),
+                "force",
+                "Move an existing branch, or force -d and -m",
+            ),
+            flag(None, "no-color", "Do not color the output"),
+            HELP,
+        ],
+    },
+    CommandSpec {
+        name: "tag",
+        about: "List, create or delete tags",
+        usage: &[
+            "tag [-l] [<pattern>...]",
+            "tag [-f] [-a] [-m <message>] <name> [<revision>]",
+            "tag -d <name>...",
+        ],
+        options: &[
+            flag(
+                Some('l'),
+                "list",
+                "List the tags matching the patterns (e.g. 'v1.*')",
+            ),
+            flag(
+                Some('a'),
+                "annotate",
+                "Create an annotated tag object (needs -m)",
+            ),
+            option(
+                Some('m'),
+                "message",
+                "message",
+                "Message of an annotated tag; several -m become paragraphs",
+            ),
+            flag(Some('f'), "force", "Replace an existing tag"),
+            flag(Some('d'), "delete", "Delete tags"),
+            HELP,
+        ],
+    },
+    CommandSpec {
+        name: "show",
+        about: "Show commits, tags, trees and file contents",
+        usage: &["show [<options>] [<revision>...]"],
+        options: &[
+            flag(
+                Some('s'),
+                "no-patch",
+                "Do not show the changes made by commits",
+            ),
+            flag(None, "no-color", "Do not color the output"),
+            HELP,
+        ],
+    },
+    CommandSpec {
+        name: "switch",
+        about: "Switch to another branch",
+        usage: &[
+            "switch [-f] <branch>",
+            "switch [-f] -c <new-branch> [<start-point>]",
+            "switch [-f] --detach <commit>",
+        ],
+        options: &[
+            option(
+                Some('c'),
+                "create",
+                "new-branch",
+                "Create <new-branch> at <start-point> (HEAD by default) and switch to it",
+            ),
+            flag(
+                Some('d'),
+                "detach",
+                "Check out a commit without being on any branch",
+            ),
+            flag(
+                Some('f'),
+                "force",
+                "Throw away local changes that are in the way",
+            ),
+            HELP,
+        ],
+    },
+    CommandSpec {
+        name: "checkout",
+        about: "Switch to another branch or check out a commit",
+        usage: &[
+            "checkout [-f] <branch>",
+            "checkout [-f] [--detach] <commit>",
+            "checkout [-f] -b <new-branch> [<start-point>]",
+        ],
+        options: &[
+            option(
+                Some('b'),
+                "branch",
+                "new-branch",
+                "Create <new-branch> at <start-point> (HEAD by default) and switch to it",
+            ),
+            flag(
+                Some('d'),
+                "detach",
+                "Check out a commit even if it is the tip of a branch",
+            ),
+            flag(
+                Some('f'),
+                "force",
+                "Throw away local changes that are in the way",
+            ),
+            HELP,
+        ],
+    },
+    CommandSpec {
+        name: "rev-parse",
+        about: "Print the object ids named by revisions",
+        usage: &["rev-parse [--verify] [--short] <revision>..."],
+        options: &[
+            flag(
+                None,
+                "verify",
+                "Require exactly one revision that names an existing object",
+            ),
+            flag(None, "short", "Abbreviate the ids to 7 hex digits"),
+            HELP,
+        ],
+    },
+    CommandSpec {
+        name: "reflog",
+        about: "Show where a ref has pointed",
+        usage: &["reflog [show] [-n <number>] [<ref>]"],
+        options: &[
+            option(
+                Some('n'),
+                "max-count",
+                "number",
+                "Show at most <number> entries",
+            ),
+            flag(None, "no-color", "Do not color the output"),
+            HELP,
+        ],
+    },
+    CommandSpec {
+        name: "pack-refs",
+        about: "Move loose refs into the packed-refs file",
+        usage: &["pack-refs [--all] [--no-prune]"],
+        options: &[
+            flag(
+                None,
+                "all",
+                "Pack branches and other refs too, not only tags",
+            ),
+            flag(None, "no-prune", "Keep the loose ref files"),
+            HELP,
+        ],
+    },
+    CommandSpec {
+        name: "help",
+        about: "Show help for xit or one of its commands",
+        usage: &["help [<command>]"],
+        options: &[HELP],
+    },
+];
+
+/// Looks up a command by name.
+pub fn find_command(name: &str) -> Option<&'static CommandSpec> {
+    COMMANDS.iter().find(|spec| spec.name == name)
+}
+
+/// The options and positional arguments given to a command.
+#[derive(Debug, Default)]
+pub struct ParsedArgs {
+    /// Options in command-line order, keyed by their long name.
+    options: Vec<(&'static str, Option<String>)>,
+    pub positionals: Vec<String>,
+    /// Number of positionals given before a `--` separator, if there was one.
+    pub separator: Option<usize>,
+}
+
+impl ParsedArgs {
+    /// Whether the option was given at least once.
+    pub fn flag(&self, long: &str) -> bool {
+        self.options.iter().any(|(name, _)| *name == long)
+    }
+
+    /// The value of the last occurrence of the option.
+    pub fn value(&self, long: &str) -> Option<&str> {
+        self.values(long).pop()
+    }
+
+    /// The values of every occurrence of the option, in order.
+    pub fn values(&self, long: &str) -> Vec<&str> {
+        self.options
+            .iter()
+            .filter(|(name, _)| *name == long)
+            .filter_map(|(_, value)| value.as_deref())
+            .collect()
+    }
+}
+
+/// Parses the global options, stopping at the first argument that is not one
+/// (normally the command name). Returns the options and the remaining arguments.
+pub fn parse_global(args: &[String]) -> Result<(ParsedArgs, &[String])> {
+    let mut parsed = ParsedArgs::default();
+    let mut index = 0;
+    while index < args.len() && args[index].starts_with('-') && args[index] != "-" {
+        if args[index] == "--" {
+            index += 1;
+            break;
+        }
+        index = parse_option(GLOBAL_OPTIONS, args, index, &mut parsed, &global_usage())?;
+    }
+    Ok((parsed, &args[index..]))
+}
+
+/// Parses the arguments that follow a command name. Options may be mixed with
+/// positional arguments; everything after `--` is positional.
+pub fn parse(spec: &CommandSpec, args: &[String]) -> Result<ParsedArgs> {
+    let mut parsed = ParsedArgs::default();
+    let mut index = 0;
+    while index < args.len() {
+        let arg = &args[index];
+        if arg == "--" {
+            parsed.separator = Some(parsed.positionals.len());
+            parsed.positionals.extend_from_slice(&args[index + 1..]);
+            break;
+        }
+        if arg.starts_with('-') && arg != "-" {
+            index = parse_option(spec.options, args, index, &mut parsed, &usage(spec))?;
+        } else {
+            parsed.positionals.push(arg.clone());
+            index += 1;
+        }
+    }
+    Ok(parsed)
+}
+
+/// Parses the option at `args[index]` and returns the index of the next argument.
+fn parse_option(
+    options: &'static [OptSpec],
+    args: &[String],
+    index: usize,
+    parsed: &mut ParsedArgs,
+    usage: &str,
+) -> Result<usize> {
+    let arg = &args[index];
+    let mut next = index + 1;
+
+    if let Some(long) = arg.strip_prefix("--") {
+        let (name, inline) = match long.split_once('=') {
+            Some((name, value)) => (name, Some(value.to_string())),
+            None => (long, None),
+        };
+        let spec = options
+            .iter()
+            .find(|spec| spec.long == name)
+            .ok_or_else(|| usage_error(&format!("unknown option '--{}'", name), usage))?;
+        let value = match (spec.value, inline) {
+            (None, Some(_)) => {
+                return Err(usage_error(
+                    &format!("option '--{}' takes no value", name),
+                    usage,
+                ));
+            }
+            (None, None) => None,
+            (Some(_), Some(value)) => Some(value),
+            (Some(_), None) => {
+                let value = args.get(next).ok_or_else(|| {
+                    usage_error(&format!("option '--{}' requires a value", name), usage)
+                })?;
+                next += 1;
+                Some(value.clone())
+            }
+        };
+        parsed.options.push((spec.long, value));
+        return Ok(next);
+    }
+
+    // A cluster of short options such as `-ab` or `-mmessage`.
+    let cluster = &arg[1..];
+    for (offset, short) in cluster.char_indices() {
+        let spec = options
+            .iter()
+            .find(|spec| spec.short == Some(short))
+            .ok_or_else(|| usage_error(&format!("unknown option '-{}'", short), usage))?;
+        if spec.value.is_none() {
+            parsed.options.push((spec.long, None));
+            continue;
+        }
+        let attached = &cluster[offset + short.len_utf8()..];
+        let value = if !attached.is_empty() {
+            attached.to_string()
+        } else {
+            let value = args.get(next).ok_or_else(|| {
+                usage_error(&format!("option '-{}' requires a value", short), usage)
+            })?;
+            next += 1;
+            value.clone()
+        };
+        parsed.options.push((spec.long, Some(value)));
+        break;
+    }
+    Ok(next)
+}
+
+/// A usage error: the problem followed by the relevant usage text.
+pub fn usage_error(message: &str, usage: &str) -> Error {
+    Error::Usage(format!("error: {}\n{}", message, usage))
+}
+
+/// The usage lines of a command.
+pub fn usage(spec: &CommandSpec) -> String {
+    let mut text = String::new();
+    for (i, line) in spec.usage.iter().enumerate() {
+        let prefix = if i == 0 { "usage:" } else { "   or:" };
+        text.push_str(&format!("{} xit {}\n", prefix, line));
+    }
+    text.trim_end().to_string()
+}
+
+/// The usage line shown when no command could be run.
+pub fn global_usage() -> String {
+    "usage: xit [-C <path>] [--xit-dir=<path>] [--work-tree=<path>] <command> [<args>]".to_string()
+}
+
+/// The full help text of a command.
+pub fn help(spec: &CommandSpec) -> String {
+    format!(
+        "{}\n\n{}\n\nOptions:\n{}",
+        usage(spec),
+        spec.about,
+        describe_options(spec.options)
+    )
+}
+
+/// The help text listing every command.
+pub fn global_help() -> String {
+    let width = COMMANDS
+        .iter()
+        .map(|spec| spec.name.len())
+        .max()
+        .unwrap_or(0);
+    let commands: Vec<String> = COMMANDS
+        .iter()
+        .map(|spec| format!("    {:width$}  {}", spec.name, spec.about, width = width))
+        .collect();
+    format!(
+        "{}\n\nCommands:\n{}\n\nGlobal options:\n{}\n\nSee 'xit help <command>' for the options of a command.",
+        global_usage(),
+        commands.join("\n"),
+        describe_options(GLOBAL_OPTIONS)
+    )
+}
+
+fn describe_options(options: &[OptSpec]) -> String {
+    let names: Vec<String> = options
+        .iter()
+        .map(|spec| {
+            let mut name = match spec.short {
+                Some(short) => format!("-{}, --{}", short, spec.long),
+                None => format!("    --{}", spec.long),
+            };
+            if let Some(value) = spec.value {
+                name.push_str(&format!(" <{}>", value));
+            }
+            name
+        })
+        .collect();
+    let width = names.iter().map(String::len).max().unwrap_or(0);
+    names
+        .iter()
+        .zip(options)
+        .map(|(name, spec)| format!("    {:width$}  {}", name, spec.help, width = width))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_options_anywhere() {
+        let commit = find_command("commit").unwrap();
+        let parsed = parse(
+            commit,
+            &args(&["-m", "Subject", "--message=Body", "-mTrailer"]),
+        )
+        .unwrap();
+        assert_eq!(parsed.values("message"), vec!["Subject", "Body", "Trailer"]);
+        assert_eq!(parsed.value("message"), Some("Trailer"));
+        assert!(!parsed.flag("help"));
+
+        let add = find_command("add").unwrap();
+        let parsed = parse(add, &args(&["a.txt", "-h", "--", "-b.txt"])).unwrap();
+        assert!(parsed.flag("help"));
+        assert_eq!(parsed.positionals, args(&["a.txt", "-b.txt"]));
+        assert_eq!(parsed.separator, Some(1));
+    }
+
+    #[test]
+    fn test_parse_errors_are_usage_errors() {
+        let commit = find_command("commit").unwrap();
+        for bad in [&["--bogus"][..], &["-m"], &["--help=yes"], &["-x"]] {
+            let err = parse(commit, &args(bad)).unwrap_err();
+            assert!(matches!(err, Error::Usage(_)), "{:?}", bad);
+            assert_eq!(err.exit_code(), 2);
+        }
+    }
+
+    #[test]
+    fn test_parse_global_stops_at_command() {
+        let argv = args(&["-C", "dir", "--xit-dir=.git", "commit", "-m", "x"]);
+        let (global, rest) = parse_global(&argv).unwrap();
+        assert_eq!(global.value("directory"), Some("dir"));
+        assert_eq!(global.value("xit-dir"), Some(".git"));
+        assert_eq!(rest, &argv[3..]);
+    }
+}
@@ -0,0 +1,121 @@
+//! Histogram diff, as in JGit and git: like patience it anchors on rare lines,
+//! but instead of requiring uniqueness it picks the longest common region around
+//! the line with the fewest occurrences, which copes better with repeated lines.
+
+use super::{Edit, myers};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::ops::Range;
+
+/// Elements occurring more often than this are never used as anchors.
+const MAX_CHAIN_LENGTH: usize = 64;
+
+/// Computes an edit script turning `old` into `new`.
+pub fn diff<T: Eq + Hash>(old: &[T], new: &[T]) -> Vec<Edit> {
+    let mut edits = Vec::new();
+    diff_range(old, new, 0..old.len(), 0..new.len(), &mut edits);
+    edits
+}
+
+fn diff_range<T: Eq + Hash>(
+    old: &[T],
+    new: &[T],
+    old_range: Range<usize>,
+    new_range: Range<usize>,
+    edits: &mut Vec<Edit>,
+) {
+    if old_range.is_empty() || new_range.is_empty() {
+        edits.extend(old_range.map(Edit::Delete));
+        edits.extend(new_range.map(Edit::Insert));
+        return;
+    }
+
+    match longest_region(old, new, old_range.clone(), new_range.clone()) {
+        Some((old_region, new_region)) => {
+            diff_range(
+                old,
+                new,
+                old_range.start..old_region.start,
+                new_range.start..new_region.start,
+                edits,
+            );
+            edits.extend(
+                old_region
+                    .clone()
+                    .zip(new_region.clone())
+                    .map(|(o, n)| Edit::Equal(o, n)),
+            );
+            diff_range(
+                old,
+                new,
+                old_region.end..old_range.end,
+                new_region.end..new_range.end,
+                edits,
+            );
+        }
+        None => super::push_shifted(
+            edits,
+            myers::diff(&old[old_range.clone()], &new[new_range.clone()], false),
+            old_range.start,
+            new_range.start,
+        ),
+    }
+}
+
+/// The common region to anchor on: built around the element with the fewest
+/// occurrences in `old`, preferring longer regions among equally rare ones.
+fn longest_region<T: Eq + Hash>(
+    old: &[T],
+    new: &[T],
+    old_range: Range<usize>,
+    new_range: Range<usize>,
+) -> Option<(Range<usize>, Range<usize>)> {
+    let mut occurrences: HashMap<&T, Vec<usize>> = HashMap::new();
+    for i in old_range.clone() {
+        occurrences.entry(&old[i]).or_default().push(i);
+    }
+
+    // (occurrence count, region length, old region, new region)
+    let mut best: Option<(usize, usize, Range<usize>, Range<usize>)> = None;
+    let mut j = new_range.start;
+    while j < new_range.end {
+        let mut next = j + 1;
+        if let Some(positions) = occurrences.get(&new[j])
+            && positions.len() <= MAX_CHAIN_LENGTH
+        {
+            for &i in positions {
+                let (mut start_o, mut start_n) = (i, j);
+                while start_o > old_range.start
+                    && start_n > new_range.start
+                    && old[start_o - 1] == new[start_n - 1]
+                {
+                    start_o -= 1;
+                    start_n -= 1;
+                }
+                let (mut end_o, mut end_n) = (i + 1, j + 1);
+                while end_o < old_range.end && end_n < new_range.end && old[end_o] == new[end_n] {
+                    end_o += 1;
+                    end_n += 1;
+                }
+
+                let count = (start_o..end_o)
+                    .map(|k| occurrences[&old[k]].len())
+                    .min()
+                    .unwrap_or(usize::MAX);
+                let length = end_o - start_o;
+                let better = match &best {
+                    None => true,
+                    Some((best_count, best_length, _, _)) => {
+                        count < *best_count || (count == *best_count && length > *best_length)
+                    }
+                };
+                if better {
+                    best = Some((count, length, start_o..end_o, start_n..end_n));
+                    next = next.max(end_n);
+                }
+            }
+        }
+        j = next;
+    }
+    best.map(|(_, _, old_region, new_region)| (old_region, new_region))
+}
@@ -0,0 +1,264 @@
+//! Line-based diffing: the algorithms computing an edit script between two
+//! sequences, and the grouping of that script into unified-diff hunks.
+//!
+//! The algorithms work on any slice of comparable elements, so callers other
+//! than `xit diff` (merging, blame) can diff lines, words or tokens alike.
+
+pub mod histogram;
+pub mod myers;
+pub mod patience;
+pub mod unified;
+
+use crate::error::{Error, Result};
+use std::fmt;
+use std::hash::Hash;
+use std::str::FromStr;
+
+/// One step of an edit script. Indices refer to the old and new sequences.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edit {
+    /// The old element at the first index equals the new element at the second.
+    Equal(usize, usize),
+    /// The old element at this index was removed.
+    Delete(usize),
+    /// The new element at this index was added.
+    Insert(usize),
+}
+
+/// The algorithm used to compute an edit script.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Algorithm {
+    /// Myers' algorithm, giving up on a minimal result for very different inputs.
+    #[default]
+    Myers,
+    /// Myers' algorithm, always searching for the smallest possible diff.
+    Minimal,
+    /// Anchors on lines that are unique on both sides.
+    Patience,
+    /// Anchors on the rarest common lines; git's recommended choice for code.
+    Histogram,
+}
+
+impl Algorithm {
+    pub const ALL: [Algorithm; 4] = [
+        Algorithm::Myers,
+        Algorithm::Minimal,
+        Algorithm::Patience,
+        Algorithm::Histogram,
+    ];
+
+    /// The name used on the command line and in the config.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Algorithm::Myers => "myers",
+            Algorithm::Minimal => "minimal",
+            Algorithm::Patience => "patience",
+            Algorithm::Histogram => "histogram",
+        }
+    }
+}
+
+impl FromStr for Algorithm {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Algorithm> {
+        Algorithm::ALL
+            .into_iter()
+            .find(|algorithm| algorithm.as_str().eq_ignore_ascii_case(name))
+            .ok_or_else(|| {
+                Error::InvalidInput(format!(
+                    "Unknown diff algorithm '{}' (expected myers, minimal, patience or histogram)",
+                    name
+                ))
+            })
+    }
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Computes an edit script turning `old` into `new` with the default algorithm.
+pub fn diff<T: Eq + Hash>(old: &[T], new: &[T]) -> Vec<Edit> {
+    diff_with(Algorithm::default(), old, new)
+}
+
+/// Computes an edit script turning `old` into `new` with the given algorithm.
+pub fn diff_with<T: Eq + Hash>(algorithm: Algorithm, old: &[T], new: &[T]) -> Vec<Edit> {
+    // Common prefixes and suffixes never change the result, so only the middle
+    // is handed to the algorithm.
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut edits: Vec<Edit> = (0..prefix).map(|i| Edit::Equal(i, i)).collect();
+    let (old_tail, new_tail) = (old.len() - suffix, new.len() - suffix);
+    let (old_middle, new_middle) = (&old[prefix..old_tail], &new[prefix..new_tail]);
+    let middle = match algorithm {
+        Algorithm::Myers => myers::diff(old_middle, new_middle, false),
+        Algorithm::Minimal => myers::diff(old_middle, new_middle, true),
+        Algorithm::Patience => patience::diff(old_middle, new_middle),
+        Algorithm::Histogram => histogram::diff(old_middle, new_middle),
+    };
+    push_shifted(&mut edits, middle, prefix, prefix);
+    edits.extend((0..suffix).map(|i| Edit::Equal(old_tail + i, new_tail + i)));
+    edits
+}
+
+/// Appends edits computed on sub-slices starting at `old_start` and `new_start`.
+fn push_shifted(edits: &mut Vec<Edit>, part: Vec<Edit>, old_start: usize, new_start: usize) {
+    edits.extend(part.into_iter().map(|edit| match edit {
+        Edit::Equal(o, n) => Edit::Equal(o + old_start, n + new_start),
+        Edit::Delete(o) => Edit::Delete(o + old_start),
+        Edit::Insert(n) => Edit::Insert(n + new_start),
+    }));
+}
+
+/// Splits text into lines, each keeping its trailing `\n` (the last line may lack one).
+pub fn split_lines(text: &[u8]) -> Vec<&[u8]> {
+    text.split_inclusive(|&b| b == b'\n').collect()
+}
+
+/// Whether the content looks binary, using git's heuristic of a NUL byte in
+/// the first 8000 bytes.
+pub fn is_binary(content: &[u8]) -> bool {
+    content.iter().take(8000).any(|&b| b == 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Applies an edit script to `old`, taking inserted elements from `new`.
+    fn apply<T: Clone>(old: &[T], new: &[T], edits: &[Edit]) -> Vec<T> {
+        edits
+            .iter()
+            .filter_map(|edit| match *edit {
+                Edit::Equal(o, _) => Some(old[o].clone()),
+                Edit::Delete(_) => None,
+                Edit::Insert(n) => Some(new[n].clone()),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_diff_produces_valid_minimal_script() {
+        let old: Vec<char> = "ABCABBA".chars().collect();
+        let new: Vec<char> = "CBABAC".chars().collect();
+        let edits = diff(&old, &new);
+        assert_eq!(apply(&old, &new, &edits), new);
+        // The shortest edit script for this classic example has 5 changes.
+        let changes = edits
+            .iter()
+            .filter(|edit| !matches!(edit, Edit::Equal(..)))
+            .count();
+        assert_eq!(changes, 5);
+
+        assert_eq!(diff::<char>(&[], &[]), vec![]);
+        assert_eq!(diff(&['a'], &[]), vec![Edit::Delete(0)]);
+        assert_eq!(diff(&[], &['a']), vec![Edit::Insert(0)]);
+    }
+
+    #[test]
+    fn test_every_algorithm_produces_valid_script() {
+        let cases = [
+            ("ABCABBA", "CBABAC"),
+            ("abcdefg", "gfedcba"),
+            ("aaaaabbbbb", "bbbbbaaaaa"),
+            ("the quick brown fox", "a quick brown dog jumps"),
+            ("", "xyz"),
+            ("xyz", ""),
+        ];
+        for algorithm in Algorithm::ALL {
+            for (old, new) in cases {
+                let old: Vec<char> = old.chars().collect();
+                let new: Vec<char> = new.chars().collect();
+                let edits = diff_with(algorithm, &old, &new);
+                assert_eq!(apply(&old, &new, &edits), new, "{}", algorithm);
+                assert_eq!(
+                    edits
+                        .iter()
+                        .filter(|edit| !matches!(edit, Edit::Insert(_)))
+                        .count(),
+                    old.len()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_patience_keeps_moved_function_together() {
+        let old = ["fn a() {", "  one", "}", "", "fn b() {", "  two", "}"];
+        let new = ["fn b() {", "  two", "}", "", "fn a() {", "  one", "}"];
+        for algorithm in [Algorithm::Patience, Algorithm::Histogram] {
+            let edits = diff_with(algorithm, &old, &new);
+            assert_eq!(apply(&old, &new, &edits), new);
+            // One whole function is kept and the other moved, never split up.
+            let kept: Vec<usize> = edits
+                .iter()
+                .filter_map(|edit| match edit {
+                    Edit::Equal(o, _) => Some(*o),
+                    _ => None,
+                })
+                .collect();
+            assert!(kept == [0, 1, 2] || kept == [4, 5, 6], "{}", algorithm);
+        }
+        assert_eq!(
+            "Histogram".parse::<Algorithm>().unwrap(),
+            Algorithm::Histogram
+        );
+        assert!("fast".parse::<Algorithm>().is_err());
+    }
+
+    #[test]
+    fn test_myers_gives_up_on_expensive_inputs() {
+        let old: Vec<u32> = (0..2000).collect();
+        // Every element is on both sides, so none is left out of the search.
+        let new: Vec<u32> = (0..2000).map(|i| i ^ 1).collect();
+        let fast = diff_with(Algorithm::Myers, &old, &new);
+        let minimal = diff_with(Algorithm::Minimal, &old, &new);
+        assert_eq!(apply(&old, &new, &fast), new);
+        assert_eq!(apply(&old, &new, &minimal), new);
+        assert!(fast.len() >= minimal.len());
+    }
+
+    #[test]
+    fn test_myers_on_large_inputs() {
+        // 10⁵ lines with 2000 changes. Keeping a copy of the search state
+        // for every edit, as this used to, takes gigabytes here; now memory is
+        // linear and the minimal script is found too.
+        let old: Vec<u32> = (0..100_000).collect();
+        let new: Vec<u32> = (0..100_000)
+            .map(|i| if i % 100 == 0 { i + 1 } else { i })
+            .collect();
+        for algorithm in [Algorithm::Myers, Algorithm::Minimal] {
+            let edits = diff_with(algorithm, &old, &new);
+            assert_eq!(apply(&old, &new, &edits), new);
+            let changes = edits
+                .iter()
+                .filter(|edit| !matches!(edit, Edit::Equal(..)))
+                .count();
+            assert_eq!(changes, 2000, "{}", algorithm);
+        }
+
+        // With nothing in common no search is needed at all.
+        let new: Vec<u32> = (100_000..200_000).collect();
+        let edits = diff_with(Algorithm::Myers, &old, &new);
+        assert_eq!(apply(&old, &new, &edits), new);
+        assert_eq!(edits.len(), 200_000);
+    }
+
+    #[test]
+    fn test_split_lines_keeps_terminators() {
+        assert_eq!(split_lines(b"a\nb"), vec![&b"a\n"[..], &b"b"[..]]);
+        assert!(split_lines(b"").is_empty());
+        assert!(is_binary(b"abc\0def"));
+        assert!(!is_binary(b"plain text\n"));
+    }
+}
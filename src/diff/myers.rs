@@ -0,0 +1,186 @@
+//! Myers' O(ND) difference algorithm, which finds a shortest edit script.
+//!
+//! This is the linear space variant from the same paper: the middle of a
+//! shortest path is found by searching from both ends at once, and the parts
+//! before and after it are solved the same way. Memory is O(N+M) whatever the
+//! edit distance, which matters for large, very different files.
+
+use super::Edit;
+use std::collections::HashSet;
+use std::hash::Hash;
+
+/// Below this many edits the search always runs to completion.
+const MIN_COST_LIMIT: usize = 256;
+
+/// Computes an edit script turning `old` into `new`.
+///
+/// When `minimal` is false each search for a middle point gives up once the
+/// edit distance exceeds roughly the square root of the input size (like git's
+/// default), and splits at the furthest point reached instead. This bounds the
+/// time on very different inputs at the cost of a longer script.
+pub fn diff<T: Eq + Hash>(old: &[T], new: &[T], minimal: bool) -> Vec<Edit> {
+    // Like git, leave out the elements found on one side only: they can only
+    // be deleted or inserted, so the search just lines up the others. This
+    // never changes the shortest script, and a rewritten file costs nothing.
+    let in_old: HashSet<&T> = old.iter().collect();
+    let in_new: HashSet<&T> = new.iter().collect();
+    let old_kept: Vec<usize> = (0..old.len())
+        .filter(|&o| in_new.contains(&old[o]))
+        .collect();
+    let new_kept: Vec<usize> = (0..new.len())
+        .filter(|&n| in_old.contains(&new[n]))
+        .collect();
+    let old_search: Vec<&T> = old_kept.iter().map(|&o| &old[o]).collect();
+    let new_search: Vec<&T> = new_kept.iter().map(|&n| &new[n]).collect();
+
+    let max = old_search.len() + new_search.len();
+    let cost_limit = if minimal {
+        max
+    } else {
+        (max.isqrt() * 4).max(MIN_COST_LIMIT)
+    };
+    let mut changes = Changes {
+        old: vec![false; old_search.len()],
+        new: vec![false; new_search.len()],
+    };
+    solve(&old_search, &new_search, (0, 0), cost_limit, &mut changes);
+
+    let mut deleted = vec![true; old.len()];
+    for (&o, &changed) in old_kept.iter().zip(&changes.old) {
+        deleted[o] = changed;
+    }
+    let mut inserted = vec![true; new.len()];
+    for (&n, &changed) in new_kept.iter().zip(&changes.new) {
+        inserted[n] = changed;
+    }
+    // The elements left on both sides pair up in order. Within a changed
+    // region deletions come first, as git shows them.
+    let mut edits = Vec::with_capacity(old.len() + new.len());
+    let (mut o, mut n) = (0, 0);
+    while o < old.len() || n < new.len() {
+        if o < old.len() && deleted[o] {
+            edits.push(Edit::Delete(o));
+            o += 1;
+        } else if n < new.len() && inserted[n] {
+            edits.push(Edit::Insert(n));
+            n += 1;
+        } else {
+            edits.push(Edit::Equal(o, n));
+            o += 1;
+            n += 1;
+        }
+    }
+    edits
+}
+
+/// Which elements of each side the search found deleted or inserted.
+struct Changes {
+    old: Vec<bool>,
+    new: Vec<bool>,
+}
+
+/// Marks the changes turning `old` into `new`, slices that start at `start`
+/// in the whole inputs.
+fn solve<T: PartialEq>(
+    old: &[T],
+    new: &[T],
+    start: (usize, usize),
+    cost_limit: usize,
+    changes: &mut Changes,
+) {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let (old, new) = (&old[prefix..], &new[prefix..]);
+    let suffix = old
+        .iter()
+        .rev()
+        .zip(new.iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (old, new) = (&old[..old.len() - suffix], &new[..new.len() - suffix]);
+    let (o, n) = (start.0 + prefix, start.1 + prefix);
+
+    if old.is_empty() || new.is_empty() {
+        changes.old[o..o + old.len()].fill(true);
+        changes.new[n..n + new.len()].fill(true);
+    } else {
+        let (x, y) = middle(old, new, cost_limit);
+        solve(&old[..x], &new[..y], (o, n), cost_limit, changes);
+        solve(&old[x..], &new[y..], (o + x, n + y), cost_limit, changes);
+    }
+}
+
+/// Finds a point on a shortest path through `old` and `new`, which have no
+/// common prefix or suffix and are both non-empty, strictly between its ends.
+/// Past `cost_limit` edits from either end the point is only on a short path.
+fn middle<T: PartialEq>(old: &[T], new: &[T], cost_limit: usize) -> (usize, usize) {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    // Forward, `forward[k]` is the furthest x reached from the start on
+    // diagonal k = x - y; backward, `backward[k]` is the smallest x reached
+    // from the end. Backward diagonals are centred on `delta`, the end's one.
+    let delta = n - m;
+    let last = ((n + m + 1) / 2).min(cost_limit as isize);
+    let offset = delta.abs() + last + 2;
+    let mut forward = vec![0isize; 2 * offset as usize + 1];
+    let mut backward = vec![0isize; 2 * offset as usize + 1];
+    let at = |k: isize| (k + offset) as usize;
+    forward[at(1)] = 0;
+    backward[at(delta - 1)] = n;
+
+    for d in 0..=last {
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && forward[at(k - 1)] < forward[at(k + 1)]) {
+                forward[at(k + 1)]
+            } else {
+                forward[at(k - 1)] + 1
+            };
+            let start = (x, x - k);
+            while x < n && x - k < m && old[x as usize] == new[(x - k) as usize] {
+                x += 1;
+            }
+            forward[at(k)] = x;
+            // With an odd delta the paths meet after a forward step.
+            if delta % 2 != 0 && (k - delta).abs() < d && x >= backward[at(k)] {
+                return (start.0 as usize, start.1 as usize);
+            }
+        }
+        for k in (delta - d..=delta + d).step_by(2) {
+            let mut x = if k == delta + d
+                || (k != delta - d && backward[at(k - 1)] < backward[at(k + 1)])
+            {
+                backward[at(k - 1)]
+            } else {
+                backward[at(k + 1)] - 1
+            };
+            let start = (x, x - k);
+            while x > 0 && x - k > 0 && old[x as usize - 1] == new[(x - k) as usize - 1] {
+                x -= 1;
+            }
+            backward[at(k)] = x;
+            if delta % 2 == 0 && k.abs() <= d && x <= forward[at(k)] {
+                return (start.0 as usize, start.1 as usize);
+            }
+        }
+
+        if d as usize >= cost_limit {
+            // Give up: split at the point that got furthest from either end.
+            let ahead = (-d..=d)
+                .step_by(2)
+                .map(|k| (forward[at(k)], forward[at(k)] - k))
+                .filter(|&(x, y)| x <= n && (0..=m).contains(&y))
+                .max_by_key(|&(x, y)| x + y);
+            let behind = (delta - d..=delta + d)
+                .step_by(2)
+                .map(|k| (backward[at(k)], backward[at(k)] - k))
+                .filter(|&(x, y)| x >= 0 && (0..=m).contains(&y))
+                .min_by_key(|&(x, y)| x + y);
+            let point = match (ahead, behind) {
+                (Some(a), Some(b)) if n + m - (b.0 + b.1) > a.0 + a.1 => b,
+                (Some(a), _) => a,
+                (None, Some(b)) => b,
+                (None, None) => unreachable!("the start is always on diagonal 0"),
+            };
+            return (point.0 as usize, point.1 as usize);
+        }
+    }
+    unreachable!("the paths meet within (N+M+1)/2 steps")
+}
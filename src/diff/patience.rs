@@ -0,0 +1,112 @@
+//! Patience diff: lines that occur exactly once on both sides are matched first
+//! (keeping the longest run that appears in the same order), and the gaps between
+//! them are diffed recursively. This keeps moved blocks and function boundaries
+//! intact where Myers tends to interleave unrelated lines.
+
+use super::{Edit, myers};
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// Computes an edit script turning `old` into `new`.
+pub fn diff<T: Eq + Hash>(old: &[T], new: &[T]) -> Vec<Edit> {
+    let mut edits = Vec::new();
+    diff_range(old, new, 0..old.len(), 0..new.len(), &mut edits);
+    edits
+}
+
+fn diff_range<T: Eq + Hash>(
+    old: &[T],
+    new: &[T],
+    old_range: std::ops::Range<usize>,
+    new_range: std::ops::Range<usize>,
+    edits: &mut Vec<Edit>,
+) {
+    let (mut o, mut n) = (old_range.start, new_range.start);
+    let (mut old_end, mut new_end) = (old_range.end, new_range.end);
+
+    while o < old_end && n < new_end && old[o] == new[n] {
+        edits.push(Edit::Equal(o, n));
+        o += 1;
+        n += 1;
+    }
+    let mut suffix = Vec::new();
+    while o < old_end && n < new_end && old[old_end - 1] == new[new_end - 1] {
+        old_end -= 1;
+        new_end -= 1;
+        suffix.push(Edit::Equal(old_end, new_end));
+    }
+
+    let anchors = unique_common(old, new, o..old_end, n..new_end);
+    if anchors.is_empty() {
+        super::push_shifted(
+            edits,
+            myers::diff(&old[o..old_end], &new[n..new_end], false),
+            o,
+            n,
+        );
+    } else {
+        for (anchor_old, anchor_new) in anchors {
+            diff_range(old, new, o..anchor_old, n..anchor_new, edits);
+            edits.push(Edit::Equal(anchor_old, anchor_new));
+            o = anchor_old + 1;
+            n = anchor_new + 1;
+        }
+        diff_range(old, new, o..old_end, n..new_end, edits);
+    }
+    edits.extend(suffix.into_iter().rev());
+}
+
+/// Pairs of positions of elements that are unique in both ranges, reduced to the
+/// longest sequence that is increasing on both sides.
+fn unique_common<T: Eq + Hash>(
+    old: &[T],
+    new: &[T],
+    old_range: std::ops::Range<usize>,
+    new_range: std::ops::Range<usize>,
+) -> Vec<(usize, usize)> {
+    // For each element: occurrences in old, in new, and its position in each.
+    let mut counts: HashMap<&T, (usize, usize, usize, usize)> = HashMap::new();
+    for i in old_range {
+        let entry = counts.entry(&old[i]).or_default();
+        entry.0 += 1;
+        entry.2 = i;
+    }
+    for j in new_range {
+        let entry = counts.entry(&new[j]).or_default();
+        entry.1 += 1;
+        entry.3 = j;
+    }
+    let mut pairs: Vec<(usize, usize)> = counts
+        .into_values()
+        .filter(|&(in_old, in_new, _, _)| in_old == 1 && in_new == 1)
+        .map(|(_, _, i, j)| (i, j))
+        .collect();
+    pairs.sort_unstable();
+    longest_increasing(&pairs)
+}
+
+/// Patience sorting: the longest subsequence of `pairs` (sorted by old position)
+/// whose new positions are increasing.
+fn longest_increasing(pairs: &[(usize, usize)]) -> Vec<(usize, usize)> {
+    // tails[k] is the index in `pairs` ending the best run of length k + 1.
+    let mut tails: Vec<usize> = Vec::new();
+    let mut previous: Vec<Option<usize>> = vec![None; pairs.len()];
+    for (index, &(_, new_pos)) in pairs.iter().enumerate() {
+        let length = tails.partition_point(|&tail| pairs[tail].1 < new_pos);
+        previous[index] = length.checked_sub(1).map(|k| tails[k]);
+        if length == tails.len() {
+            tails.push(index);
+        } else {
+            tails[length] = index;
+        }
+    }
+
+    let mut result = Vec::new();
+    let mut cursor = tails.last().copied();
+    while let Some(index) = cursor {
+        result.push(pairs[index]);
+        cursor = previous[index];
+    }
+    result.reverse();
+    result
+}
@@ -0,0 +1,119 @@
+//! Grouping of an edit script into the hunks of a unified diff.
+
+use super::Edit;
+
+/// A run of changes together with the unchanged lines surrounding it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    /// 1-based first line in the old file (0 when the hunk covers no old line).
+    pub old_start: usize,
+    pub old_len: usize,
+    /// 1-based first line in the new file (0 when the hunk covers no new line).
+    pub new_start: usize,
+    pub new_len: usize,
+    pub edits: Vec<Edit>,
+}
+
+impl Hunk {
+    /// The `@@ -a,b +c,d @@` line introducing the hunk.
+    pub fn header(&self) -> String {
+        format!(
+            "@@ -{} +{} @@",
+            range(self.old_start, self.old_len),
+            range(self.new_start, self.new_len)
+        )
+    }
+}
+
+fn range(start: usize, len: usize) -> String {
+    if len == 1 {
+        start.to_string()
+    } else {
+        format!("{},{}", start, len)
+    }
+}
+
+/// Splits an edit script into hunks with `context` unchanged lines around each
+/// change. Changes separated by at most `2 * context` unchanged lines share a hunk.
+pub fn hunks(edits: &[Edit], context: usize) -> Vec<Hunk> {
+    let changes: Vec<usize> = edits
+        .iter()
+        .enumerate()
+        .filter(|(_, edit)| !matches!(edit, Edit::Equal(..)))
+        .map(|(i, _)| i)
+        .collect();
+
+    let mut hunks = Vec::new();
+    let mut i = 0;
+    while i < changes.len() {
+        let start = changes[i].saturating_sub(context);
+        let mut last = changes[i];
+        while i + 1 < changes.len() && changes[i + 1] - last <= 2 * context + 1 {
+            i += 1;
+            last = changes[i];
+        }
+        let end = (last + context + 1).min(edits.len());
+        hunks.push(make_hunk(edits, start, end));
+        i += 1;
+    }
+    hunks
+}
+
+fn make_hunk(edits: &[Edit], start: usize, end: usize) -> Hunk {
+    // Position in both files just before the hunk, counted from the script prefix.
+    let (mut old_pos, mut new_pos) = (0, 0);
+    for edit in &edits[..start] {
+        match edit {
+            Edit::Equal(..) => {
+                old_pos += 1;
+                new_pos += 1;
+            }
+            Edit::Delete(_) => old_pos += 1,
+            Edit::Insert(_) => new_pos += 1,
+        }
+    }
+
+    let slice = edits[start..end].to_vec();
+    let old_len = slice
+        .iter()
+        .filter(|edit| !matches!(edit, Edit::Insert(_)))
+        .count();
+    let new_len = slice
+        .iter()
+        .filter(|edit| !matches!(edit, Edit::Delete(_)))
+        .count();
+    Hunk {
+        old_start: if old_len == 0 { old_pos } else { old_pos + 1 },
+        old_len,
+        new_start: if new_len == 0 { new_pos } else { new_pos + 1 },
+        new_len,
+        edits: slice,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::diff;
+
+    #[test]
+    fn test_hunks_merge_nearby_changes() {
+        let old: Vec<String> = (1..=20).map(|i| i.to_string()).collect();
+        let mut new = old.clone();
+        new[1] = "two".to_string();
+        new[5] = "six".to_string();
+        new[17] = "eighteen".to_string();
+
+        let hunks = hunks(&diff(&old, &new), 3);
+        assert_eq!(hunks.len(), 2);
+        assert_eq!(hunks[0].header(), "@@ -1,9 +1,9 @@");
+        assert_eq!(hunks[1].header(), "@@ -15,6 +15,6 @@");
+    }
+
+    #[test]
+    fn test_hunk_for_new_file() {
+        let new = ["a", "b"];
+        let hunks = hunks(&diff(&[], &new), 3);
+        assert_eq!(hunks[0].header(), "@@ -0,0 +1,2 @@");
+    }
+}
@@ -0,0 +1,110 @@
+use crate::objects::id::ObjectId;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// The error type returned by every fallible function in the library.
+#[derive(Debug)]
+pub enum Error {
+    /// No `.xit` directory was found at or above the given path.
+    NotARepository(PathBuf),
+    /// The object is not present in the object database.
+    ObjectNotFound(ObjectId),
+    /// An object, the index or another repository file could not be parsed.
+    CorruptObject(String),
+    /// A reference name is invalid, missing, or points to something unexpected.
+    InvalidRef(String),
+    /// Another process holds the lock on the index.
+    IndexLocked(PathBuf),
+    /// The operation would overwrite something that already exists or has changed.
+    Conflict(String),
+    /// The user configuration is missing or invalid.
+    Config(String),
+    /// An argument passed to the library is invalid (bad path, empty message, ...).
+    InvalidInput(String),
+    /// The command line itself is malformed: unknown command, missing argument, ...
+    Usage(String),
+    /// An underlying filesystem error.
+    Io(io::Error),
+}
+
+/// A specialized `Result` for library operations.
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// The process exit code `run_command` reports for this error. Usage errors
+    /// exit with 2, runtime failures with 1 or a more specific code.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Io(_) => 1,
+            Error::Usage(_) => 2,
+            Error::InvalidInput(_) => 3,
+            Error::NotARepository(_) => 4,
+            Error::ObjectNotFound(_) => 5,
+            Error::CorruptObject(_) => 6,
+            Error::InvalidRef(_) => 7,
+            Error::IndexLocked(_) => 8,
+            Error::Conflict(_) => 9,
+            Error::Config(_) => 10,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NotARepository(path) => write!(
+                f,
+                "Not a xit repository (or any of the parent directories): {}",
+                path.display()
+            ),
+            Error::ObjectNotFound(id) => write!(f, "Object {} not found", id),
+            Error::CorruptObject(message) => write!(f, "Corrupt object: {}", message),
+            Error::InvalidRef(message) => write!(f, "{}", message),
+            Error::IndexLocked(path) => write!(
+                f,
+                "Unable to lock the index: '{}' exists. Another xit process seems to be running.",
+                path.display()
+            ),
+            Error::Conflict(message) => write!(f, "{}", message),
+            Error::Config(message) => write!(f, "{}", message),
+            Error::InvalidInput(message) => write!(f, "{}", message),
+            Error::Usage(message) => write!(f, "{}", message),
+            Error::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::repo::Repository;
+
+    #[test]
+    fn test_errors_are_distinguishable() {
+        let missing = std::env::temp_dir().join("xit_test_error_not_a_repo");
+        let _ = std::fs::remove_dir_all(&missing);
+
+        let err = Repository::open(&missing).unwrap_err();
+        assert!(matches!(err, Error::NotARepository(_)));
+
+        let io_err: Error = io::Error::new(io::ErrorKind::PermissionDenied, "denied").into();
+        assert_ne!(err.exit_code(), io_err.exit_code());
+        assert_ne!(err.exit_code(), 0);
+    }
+}
@@ -1,72 +1,740 @@
-use std::io::{self, Write};
+use diff::Algorithm;
+use objects::id::ObjectId;
+use objects::read as object_read;
+use objects::tree::FileMode;
+use repository::repo::{XIT_DIR_ENV, XIT_WORK_TREE_ENV};
+use repository::rev_parse;
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::PathBuf;
 use term_colr::{green, red, yellow};
 
+pub mod cli;
+pub mod diff;
+pub mod error;
 pub mod objects;
 pub mod repository;
 
-pub fn run_command(args: &[String]) -> io::Result<()> {
-    if args.len() < 2 {
-        println!("{}", yellow!("Usage: xit <command> [<args>]"));
+pub use error::{Error, Result};
+pub use repository::repo::Repository;
+
+/// Runs the command line and returns the process exit code: 0 on success,
+/// otherwise the code of the error that stopped the command.
+pub fn run_command(args: &[String]) -> i32 {
+    match execute(args) {
+        Ok(()) => 0,
+        Err(Error::Usage(usage)) => {
+            eprintln!("{}", yellow!("{}", usage));
+            2
+        }
+        Err(e) => {
+            eprintln!("{}", red!("Error: {}", e));
+            e.exit_code()
+        }
+    }
+}
+
+/// Reports what went wrong without failing the command, such as a reflog that
+/// could not be written after its ref was updated.
+fn print_warnings(warnings: &[String]) {
+    for warning in warnings {
+        eprintln!("{}", yellow!("warning: {}", warning));
+    }
+}
+
+/// Where the command runs and which repository it targets, from the global options.
+struct Context {
+    cwd: PathBuf,
+    xit_dir: Option<PathBuf>,
+    work_tree: Option<PathBuf>,
+}
+
+impl Context {
+    /// Finds the repository containing `cwd`. `--xit-dir` and `--work-tree` take
+    /// precedence over `XIT_DIR` and `XIT_WORK_TREE`.
+    fn open_repository(&self) -> Result<Repository> {
+        let xit_dir = self
+            .xit_dir
+            .clone()
+            .or_else(|| env::var_os(XIT_DIR_ENV).map(PathBuf::from));
+        let work_tree = self
+            .work_tree
+            .clone()
+            .or_else(|| env::var_os(XIT_WORK_TREE_ENV).map(PathBuf::from));
+        Repository::discover_with_overrides(&self.cwd, xit_dir.as_deref(), work_tree.as_deref())
+    }
+}
+
+fn execute(args: &[String]) -> Result<()> {
+    let (global, rest) = cli::parse_global(args.get(1..).unwrap_or_default())?;
+    if global.flag("version") {
+        println!("xit {}", env!("CARGO_PKG_VERSION"));
+        return Ok(());
+    }
+    if global.flag("help") {
+        println!("{}", cli::global_help());
+        return Ok(());
+    }
+
+    let mut cwd = env::current_dir()?;
+    for dir in global.values("directory") {
+        cwd = cwd.join(dir);
+    }
+    let ctx = Context {
+        xit_dir: global.value("xit-dir").map(|path| cwd.join(path)),
+        work_tree: global.value("work-tree").map(|path| cwd.join(path)),
+        cwd,
+    };
+
+    let Some((command, rest)) = rest.split_first() else {
+        return Err(Error::Usage(cli::global_usage()));
+    };
+    let spec = cli::find_command(command).ok_or_else(|| {
+        cli::usage_error(
+            &format!("'{}' is not a xit command. See 'xit help'.", command),
+            &cli::global_usage(),
+        )
+    })?;
+    let parsed = cli::parse(spec, rest)?;
+    if parsed.flag("help") {
+        println!("{}", cli::help(spec));
         return Ok(());
     }
 
-    let command = &args[1];
+    match spec.name {
+        "init" => init(&ctx, &parsed),
+        "setup" => setup(&parsed),
+        "add" => add(&ctx, &parsed),
+        "commit" => commit(&ctx, &parsed),
+        "status" => status(&ctx, &parsed),
+        "diff" => diff(&ctx, &parsed),
+        "log" => log(&ctx, &parsed),
+        "branch" => branch(&ctx, &parsed),
+        "tag" => tag(&ctx, &parsed),
+        "show" => show(&ctx, &parsed),
+        "switch" => change_branch(&ctx, &parsed, "switch", "create"),
+        "checkout" => change_branch(&ctx, &parsed, "checkout", "branch"),
+        "rev-parse" => rev_parse(&ctx, &parsed),
+        "reflog" => reflog(&ctx, &parsed),
+        "pack-refs" => pack_refs(&ctx, &parsed),
+        "help" => help(&parsed),
+        _ => unreachable!("command table and dispatch are out of sync"),
+    }
+}
+
+/// Fails with the command's usage unless it got between `min` and `max` positionals.
+fn expect_positionals(
+    spec_name: &str,
+    parsed: &cli::ParsedArgs,
+    min: usize,
+    max: usize,
+) -> Result<()> {
+    let count = parsed.positionals.len();
+    if count < min || count > max {
+        let spec = cli::find_command(spec_name).expect("known command");
+        let message = if count < min {
+            "missing argument"
+        } else {
+            "too many arguments"
+        };
+        return Err(cli::usage_error(message, &cli::usage(spec)));
+    }
+    Ok(())
+}
 
-    match command.as_str() {
-        "init" => {
-            repository::repo::create_repository();
-            println!("{}", green!("Initialized empty Xit repository."));
+fn init(ctx: &Context, parsed: &cli::ParsedArgs) -> Result<()> {
+    expect_positionals("init", parsed, 0, 1)?;
+    let work_tree = match parsed.positionals.first() {
+        Some(dir) => {
+            let dir = ctx.cwd.join(dir);
+            fs::create_dir_all(&dir)?;
+            dir
         }
-        "setup" => {
-            if let Err(e) = interactive_setup() {
-                println!("{}", red!("Error during setup: {}", e));
-            } else {
-                println!("{}", green!("Global user setup successful."));
-            }
+        None => ctx.cwd.clone(),
+    };
+    Repository::init(&work_tree)?;
+    println!("{}", green!("Initialized empty Xit repository."));
+    Ok(())
+}
+
+fn setup(parsed: &cli::ParsedArgs) -> Result<()> {
+    expect_positionals("setup", parsed, 0, 0)?;
+    match (parsed.value("name"), parsed.value("email")) {
+        (Some(name), Some(email)) => repository::config::setup_global_user(name, email)?,
+        (None, None) => interactive_setup()?,
+        _ => {
+            return Err(cli::usage_error(
+                "--name and --email must be given together",
+                &cli::usage(cli::find_command("setup").expect("known command")),
+            ));
         }
-        "add" => {
-            if args.len() < 3 {
-                println!("{}", yellow!("Usage: xit add <file>"));
-                return Ok(());
-            }
-            let file_path = &args[2];
-            if let Err(e) = repository::add::add(file_path) {
-                println!("{}", red!("Error: {}", e));
-            } else {
-                println!("{}", green!("Added '{}' to the index.", file_path));
-            }
+    }
+    println!("{}", green!("Global user setup successful."));
+    Ok(())
+}
+
+fn add(ctx: &Context, parsed: &cli::ParsedArgs) -> Result<()> {
+    expect_positionals("add", parsed, 1, usize::MAX)?;
+    let repo = ctx.open_repository()?;
+    for file_path in &parsed.positionals {
+        repo.add(&repo.pathspec(&ctx.cwd, file_path)?)?;
+        println!("{}", green!("Added '{}' to the index.", file_path));
+    }
+    Ok(())
+}
+
+fn commit(ctx: &Context, parsed: &cli::ParsedArgs) -> Result<()> {
+    expect_positionals("commit", parsed, 0, 0)?;
+    let paragraphs = parsed.values("message");
+    if paragraphs.is_empty() {
+        return Err(cli::usage_error(
+            "a commit message is required",
+            &cli::usage(cli::find_command("commit").expect("known command")),
+        ));
+    }
+    // Like git, each -m becomes its own paragraph.
+    let message = paragraphs.join("\n\n");
+    print_warnings(&ctx.open_repository()?.commit(&message)?.warnings);
+    println!("{}", green!("Committed changes."));
+    Ok(())
+}
+
+fn status(ctx: &Context, parsed: &cli::ParsedArgs) -> Result<()> {
+    expect_positionals("status", parsed, 0, 0)?;
+    repository::status::check_status(&ctx.open_repository()?)
+}
+
+fn diff(ctx: &Context, parsed: &cli::ParsedArgs) -> Result<()> {
+    use repository::diff as patch;
+
+    let usage = cli::usage(cli::find_command("diff").expect("known command"));
+    let cached = parsed.flag("cached") || parsed.flag("staged");
+    expect_positionals("diff", parsed, 0, if cached { 1 } else { 2 })?;
+    let context = match parsed.value("unified") {
+        Some(value) => value.parse().map_err(|_| {
+            cli::usage_error(
+                &format!("invalid number of context lines '{}'", value),
+                &usage,
+            )
+        })?,
+        None => patch::DEFAULT_CONTEXT,
+    };
+    let algorithm = match parsed.value("diff-algorithm") {
+        Some(name) => Some(
+            name.parse::<Algorithm>()
+                .map_err(|e| cli::usage_error(&e.to_string(), &usage))?,
+        ),
+        None => ["minimal", "patience", "histogram"]
+            .into_iter()
+            .rfind(|name| parsed.flag(name))
+            .map(|name| name.parse())
+            .transpose()?,
+    };
+
+    let repo = ctx.open_repository()?;
+    let trees = parsed
+        .positionals
+        .iter()
+        .map(|name| {
+            let commit = rev_parse::resolve_commit(&repo, name)?;
+            object_read::get_commit_tree_hash(repo.git_dir(), &commit)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let diffs = match (cached, trees.as_slice()) {
+        (true, trees) => patch::diff_tree_to_index(&repo, trees.first())?,
+        (false, []) => patch::diff_index_to_worktree(&repo)?,
+        (false, [tree]) => patch::diff_tree_to_worktree(&repo, tree)?,
+        (false, [old, new, ..]) => patch::diff_trees(&repo, old, new)?,
+    };
+
+    let options = patch::PatchOptions {
+        context,
+        algorithm: match algorithm {
+            Some(algorithm) => algorithm,
+            None => patch::configured_algorithm(&repo)?,
+        },
+        color: !parsed.flag("no-color") && io::stdout().is_terminal(),
+    };
+    print!("{}", patch::format_patch(&diffs, &options));
+    Ok(())
+}
+
+fn log(ctx: &Context, parsed: &cli::ParsedArgs) -> Result<()> {
+    use repository::log::{self as history, LogFormat, LogOptions};
+
+    let usage = cli::usage(cli::find_command("log").expect("known command"));
+    let invalid = |message: String| cli::usage_error(&message, &usage);
+    let repo = ctx.open_repository()?;
+    let graph = parsed.flag("graph");
+    if graph && parsed.flag("reverse") {
+        return Err(invalid(
+            "options '--reverse' and '--graph' cannot be used together".to_string(),
+        ));
+    }
+
+    // Arguments before `--` are revisions, unless they only make sense as paths.
+    let (mut revisions, mut hidden, mut paths) = (Vec::new(), Vec::new(), Vec::new());
+    let mut any_revision = false;
+    for (position, arg) in parsed.positionals.iter().enumerate() {
+        if parsed
+            .separator
+            .is_some_and(|separator| position >= separator)
+            || !paths.is_empty()
+        {
+            paths.push(repo.pathspec(&ctx.cwd, arg)?);
+        } else if let Ok(range) = rev_parse::resolve_range(&repo, arg) {
+            revisions.extend(range.include);
+            hidden.extend(range.exclude);
+            any_revision = true;
+        } else if parsed.separator.is_none() && ctx.cwd.join(arg).exists() {
+            paths.push(repo.pathspec(&ctx.cwd, arg)?);
+        } else {
+            return Err(Error::InvalidRef(format!(
+                "ambiguous argument '{}': unknown revision or path not in the working tree",
+                arg
+            )));
         }
-        "commit" => {
-            if args.len() < 4 || args[2] != "-m" {
-                println!("{}", yellow!("Usage: xit commit -m <message>"));
-                return Ok(());
+    }
+    if !any_revision {
+        revisions.push(rev_parse::resolve_commit(&repo, "HEAD")?);
+    }
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    let parse_date = |names: [&str; 2]| -> Result<Option<i64>> {
+        let value = names.into_iter().rev().find_map(|name| parsed.value(name));
+        value
+            .map(|value| {
+                repository::date::parse_date(value, now).map_err(|e| invalid(e.to_string()))
+            })
+            .transpose()
+    };
+    let options = LogOptions {
+        max_count: parsed
+            .value("max-count")
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| invalid(format!("invalid commit count '{}'", value)))
+            })
+            .transpose()?,
+        author: parsed.value("author").map(str::to_string),
+        since: parse_date(["since", "after"])?,
+        until: parse_date(["until", "before"])?,
+        paths,
+        reverse: parsed.flag("reverse"),
+        first_parent: parsed.flag("first-parent"),
+        topo_order: graph,
+    };
+    let terminal = io::stdout().is_terminal();
+    let decorate = parsed.flag("decorate") || (!parsed.flag("no-decorate") && (graph || terminal));
+    let formatter = history::CommitFormatter {
+        format: match parsed.value("format").or(parsed.value("pretty")) {
+            Some(format) => LogFormat::parse(format),
+            None if parsed.flag("oneline") => LogFormat::Oneline,
+            None => LogFormat::Medium,
+        },
+        color: !parsed.flag("no-color") && terminal,
+        now,
+        decorations: if decorate {
+            history::decorations(&repo)?
+        } else {
+            Default::default()
+        },
+    };
+
+    let commits = history::log(&repo, &revisions, &hidden, &options)?;
+    let mut out = io::stdout().lock();
+    if !graph {
+        for (i, (id, commit)) in commits.iter().enumerate() {
+            if i > 0 {
+                write!(out, "{}", formatter.separator())?;
             }
-            let message = &args[3];
-            if let Err(e) = repository::commit::commit(message) {
-                println!("{}", red!("Error: {}", e));
-            } else {
-                println!("{}", green!("Committed changes."));
+            writeln!(out, "{}", formatter.format(id, commit))?;
+        }
+        return Ok(());
+    }
+
+    // Lanes only lead to commits that are shown; the others would never close.
+    let shown: HashSet<ObjectId> = commits.iter().map(|(id, _)| *id).collect();
+    let mut lanes = repository::graph::Graph::new();
+    for (i, (id, commit)) in commits.iter().enumerate() {
+        let parents: Vec<ObjectId> = commit
+            .parents
+            .iter()
+            .take(if options.first_parent { 1 } else { usize::MAX })
+            .filter(|parent| shown.contains(*parent))
+            .copied()
+            .collect();
+        let text = formatter.format(id, commit);
+        let mut lines: Vec<&str> = text.lines().collect();
+        if i + 1 < commits.len() {
+            lines.extend(formatter.separator().lines());
+        }
+        for line in lanes.next(id, &parents).decorate(&lines) {
+            writeln!(out, "{}", line)?;
+        }
+    }
+    Ok(())
+}
+
+fn branch(ctx: &Context, parsed: &cli::ParsedArgs) -> Result<()> {
+    use repository::branch as branches;
+
+    let repo = ctx.open_repository()?;
+    let args = parsed.positionals.as_slice();
+    let current = repository::refs::current_branch(repo.git_dir())?;
+
+    if parsed.flag("delete") || parsed.flag("delete-force") {
+        expect_positionals("branch", parsed, 1, usize::MAX)?;
+        let force = parsed.flag("force") || parsed.flag("delete-force");
+        for name in args {
+            let deleted = branches::delete(&repo, name, force)?;
+            print_warnings(&deleted.warnings);
+            println!("Deleted branch {} (was {}).", name, deleted.id.short());
+        }
+        return Ok(());
+    }
+    if parsed.flag("move") || parsed.flag("move-force") {
+        expect_positionals("branch", parsed, 1, 2)?;
+        let force = parsed.flag("force") || parsed.flag("move-force");
+        let (old, new) = match args {
+            [new] => (
+                current.ok_or_else(|| {
+                    Error::InvalidRef("HEAD is detached; name the branch to rename".to_string())
+                })?,
+                new,
+            ),
+            [old, new] => (old.clone(), new),
+            _ => unreachable!("checked by expect_positionals"),
+        };
+        print_warnings(&branches::rename(&repo, &old, new, force)?);
+        return Ok(());
+    }
+    if let Some(name) = args.first() {
+        expect_positionals("branch", parsed, 1, 2)?;
+        let start = args.get(1).map_or("HEAD", String::as_str);
+        print_warnings(&branches::create(&repo, name, start, parsed.flag("force"))?.warnings);
+        return Ok(());
+    }
+
+    // Listing: the current branch (or a detached HEAD) is marked with `*`.
+    let mut rows: Vec<(String, ObjectId, bool)> = Vec::new();
+    if current.is_none() {
+        let head = rev_parse::resolve_commit(&repo, "HEAD")?;
+        rows.push((format!("(HEAD detached at {})", head.short()), head, true));
+    }
+    for branch in branches::list(&repo)? {
+        let is_current = current.as_deref() == Some(branch.name.as_str());
+        rows.push((branch.name, branch.id, is_current));
+    }
+    let color = !parsed.flag("no-color") && io::stdout().is_terminal();
+    let width = rows
+        .iter()
+        .map(|(name, _, _)| name.len())
+        .max()
+        .unwrap_or(0);
+    let mut out = io::stdout().lock();
+    for (name, id, is_current) in rows {
+        let marker = if is_current { '*' } else { ' ' };
+        // Pad by hand: the escape codes of a colored name take no room.
+        let padding = width - name.len();
+        let name = match is_current && color {
+            true => green!("{}", name),
+            false => name,
+        };
+        if parsed.flag("verbose") {
+            let subject = repository::log::subject(&repo.read_commit(&id)?);
+            writeln!(
+                out,
+                "{} {}{:padding$} {} {}",
+                marker,
+                name,
+                "",
+                id.short(),
+                subject
+            )?;
+        } else {
+            writeln!(out, "{} {}", marker, name)?;
+        }
+    }
+    Ok(())
+}
+
+fn tag(ctx: &Context, parsed: &cli::ParsedArgs) -> Result<()> {
+    use repository::tag as tags;
+
+    let repo = ctx.open_repository()?;
+    if parsed.flag("delete") {
+        expect_positionals("tag", parsed, 1, usize::MAX)?;
+        for name in &parsed.positionals {
+            let deleted = tags::delete(&repo, name)?;
+            print_warnings(&deleted.warnings);
+            println!("Deleted tag '{}' (was {})", name, deleted.id.short());
+        }
+        return Ok(());
+    }
+    if parsed.flag("list") || parsed.positionals.is_empty() {
+        for name in tags::list(&repo, &parsed.positionals)? {
+            println!("{}", name);
+        }
+        return Ok(());
+    }
+
+    expect_positionals("tag", parsed, 1, 2)?;
+    let messages = parsed.values("message");
+    if parsed.flag("annotate") && messages.is_empty() {
+        let spec = cli::find_command("tag").expect("known command");
+        return Err(cli::usage_error(
+            "an annotated tag needs a message (-m)",
+            &cli::usage(spec),
+        ));
+    }
+    let message = (!messages.is_empty()).then(|| messages.join("\n\n"));
+    let target = parsed.positionals.get(1).map_or("HEAD", String::as_str);
+    let created = tags::create(
+        &repo,
+        &parsed.positionals[0],
+        target,
+        message.as_deref(),
+        parsed.flag("force"),
+    )?;
+    print_warnings(&created.warnings);
+    Ok(())
+}
+
+fn show(ctx: &Context, parsed: &cli::ParsedArgs) -> Result<()> {
+    use objects::object::Object;
+    use repository::diff as patch;
+
+    let repo = ctx.open_repository()?;
+    let color = !parsed.flag("no-color") && io::stdout().is_terminal();
+    let formatter = repository::log::CommitFormatter {
+        color,
+        ..Default::default()
+    };
+    let options = patch::PatchOptions {
+        algorithm: patch::configured_algorithm(&repo)?,
+        color,
+        ..Default::default()
+    };
+    let names = match parsed.positionals.as_slice() {
+        [] => vec!["HEAD".to_string()],
+        names => names.to_vec(),
+    };
+
+    let mut out = io::stdout().lock();
+    for (i, name) in names.iter().enumerate() {
+        if i > 0 {
+            writeln!(out)?;
+        }
+        // Annotated tags are shown followed by what they point at.
+        let mut id = rev_parse::resolve(&repo, name)?;
+        loop {
+            match repo.read_parsed(&id)? {
+                Object::Tag(tag) => {
+                    writeln!(out, "{}\n", repository::tag::format_tag(&tag, color))?;
+                    id = tag.object;
+                }
+                Object::Commit(commit) => {
+                    writeln!(out, "{}", formatter.format(&id, &commit))?;
+                    if !parsed.flag("no-patch") {
+                        let diffs = patch::diff_commit(&repo, &commit)?;
+                        if !diffs.is_empty() {
+                            write!(out, "\n{}", patch::format_patch(&diffs, &options))?;
+                        }
+                    }
+                    break;
+                }
+                Object::Tree(tree) => {
+                    writeln!(out, "tree {}\n", name)?;
+                    for entry in tree.entries {
+                        let slash = if entry.mode == FileMode::Tree {
+                            "/"
+                        } else {
+                            ""
+                        };
+                        writeln!(out, "{}{}", entry.name, slash)?;
+                    }
+                    break;
+                }
+                Object::Blob(blob) => {
+                    out.write_all(&blob.data)?;
+                    break;
+                }
             }
         }
-        "status" => {
-            if let Err(e) = repository::status::check_status() {
-                println!("{}", red!("Error: {}", e));
+    }
+    Ok(())
+}
+
+/// `xit switch` and `xit checkout`. They differ in the option creating a new
+/// branch and in that only `checkout` detaches HEAD without `--detach`.
+fn change_branch(
+    ctx: &Context,
+    parsed: &cli::ParsedArgs,
+    command: &str,
+    create_option: &str,
+) -> Result<()> {
+    use repository::checkout::{self as worktree, Target};
+
+    let repo = ctx.open_repository()?;
+    let force = parsed.flag("force");
+    let current = repository::refs::current_branch(repo.git_dir())?;
+
+    if let Some(name) = parsed.value(create_option) {
+        expect_positionals(command, parsed, 0, 1)?;
+        let start = parsed.positionals.first().map_or("HEAD", String::as_str);
+        print_warnings(&repository::branch::create(&repo, name, start, false)?.warnings);
+        match worktree::checkout(&repo, &Target::Branch(name.to_string()), force) {
+            Ok(warnings) => print_warnings(&warnings),
+            Err(e) => {
+                repository::branch::delete(&repo, name, true)?;
+                return Err(e);
             }
         }
-        "diff" => {
-            if args.len() < 3 {
-                println!("{}", red!("Usage : xit diff "));
+        println!("Switched to a new branch '{}'", name);
+        return Ok(());
+    }
+
+    expect_positionals(command, parsed, 1, 1)?;
+    let name = parsed.positionals[0].as_str();
+    let is_branch = !parsed.flag("detach")
+        && objects::update::reference_exists(repo.git_dir(), &format!("refs/heads/{}", name));
+    if is_branch {
+        if current.as_deref() == Some(name) {
+            println!("Already on '{}'", name);
+            return Ok(());
+        }
+        print_warnings(&worktree::checkout(
+            &repo,
+            &Target::Branch(name.to_string()),
+            force,
+        )?);
+        println!("Switched to branch '{}'", name);
+    } else if command == "checkout" || parsed.flag("detach") {
+        let id = rev_parse::resolve_commit(&repo, name)?;
+        print_warnings(&worktree::checkout(&repo, &Target::Detached(id), force)?);
+        let subject = repository::log::subject(&repo.read_commit(&id)?);
+        println!("HEAD is now at {} {}", id.short(), subject);
+    } else {
+        let spec = cli::find_command(command).expect("known command");
+        return Err(cli::usage_error(
+            &format!(
+                "a branch is expected, got '{}'; use --detach to check out a commit",
+                name
+            ),
+            &cli::usage(spec),
+        ));
+    }
+    Ok(())
+}
+
+fn rev_parse(ctx: &Context, parsed: &cli::ParsedArgs) -> Result<()> {
+    expect_positionals("rev-parse", parsed, 1, usize::MAX)?;
+    let repo = ctx.open_repository()?;
+    let show = |id: &ObjectId| {
+        if parsed.flag("short") {
+            id.short()
+        } else {
+            id.to_hex()
+        }
+    };
+
+    if parsed.flag("verify") {
+        expect_positionals("rev-parse", parsed, 1, 1)?;
+        let id = rev_parse::resolve(&repo, &parsed.positionals[0])?;
+        repo.read_object(&id)?;
+        println!("{}", show(&id));
+        return Ok(());
+    }
+    for arg in &parsed.positionals {
+        // Like git, a range prints its commits and then the excluded ones with `^`.
+        if arg.contains("..") || arg.starts_with('^') {
+            let range = rev_parse::resolve_range(&repo, arg)?;
+            for id in &range.include {
+                println!("{}", show(id));
+            }
+            for id in &range.exclude {
+                println!("^{}", show(id));
             }
-            // checkl if any changes or anything new added to the index file , we need to keep
-            // track of previous file and show the changes we make
+        } else {
+            println!("{}", show(&rev_parse::resolve(&repo, arg)?));
+        }
+    }
+    Ok(())
+}
+
+fn reflog(ctx: &Context, parsed: &cli::ParsedArgs) -> Result<()> {
+    let usage = cli::usage(cli::find_command("reflog").expect("known command"));
+    let args = match parsed.positionals.as_slice() {
+        [show, rest @ ..] if show == "show" => rest,
+        args => args,
+    };
+    if args.len() > 1 {
+        return Err(cli::usage_error("too many arguments", &usage));
+    }
+    let max_count = parsed
+        .value("max-count")
+        .map(|value| {
+            value
+                .parse::<usize>()
+                .map_err(|_| cli::usage_error(&format!("invalid entry count '{}'", value), &usage))
+        })
+        .transpose()?;
+    let repo = ctx.open_repository()?;
+    let color = !parsed.flag("no-color") && io::stdout().is_terminal();
+
+    // Entries are named like git does: `HEAD@{0}`, `main@{2}`.
+    let name = args.first().map(String::as_str).unwrap_or("HEAD");
+    let ref_name = rev_parse::reflog_ref(&repo, name)?;
+    let label = ref_name
+        .strip_prefix("refs/heads/")
+        .or_else(|| ref_name.strip_prefix("refs/"))
+        .unwrap_or(&ref_name);
+    let entries = repository::reflog::read(repo.git_dir(), &ref_name)?;
+
+    let mut out = io::stdout().lock();
+    let newest_first = entries.iter().rev().enumerate();
+    for (i, entry) in newest_first.take(max_count.unwrap_or(usize::MAX)) {
+        let short = entry.new.short();
+        let short = if color { yellow!("{}", short) } else { short };
+        writeln!(out, "{} {}@{{{}}}: {}", short, label, i, entry.message)?;
+    }
+    Ok(())
+}
+
+fn pack_refs(ctx: &Context, parsed: &cli::ParsedArgs) -> Result<()> {
+    expect_positionals("pack-refs", parsed, 0, 0)?;
+    let repo = ctx.open_repository()?;
+    repo.refs()
+        .pack(parsed.flag("all"), parsed.flag("no-prune"))?;
+    Ok(())
+}
+
+fn help(parsed: &cli::ParsedArgs) -> Result<()> {
+    expect_positionals("help", parsed, 0, 1)?;
+    match parsed.positionals.first() {
+        None => println!("{}", cli::global_help()),
+        Some(name) => {
+            let spec = cli::find_command(name).ok_or_else(|| {
+                cli::usage_error(
+                    &format!("'{}' is not a xit command", name),
+                    &cli::global_usage(),
+                )
+            })?;
+            println!("{}", cli::help(spec));
         }
-        _ => println!("{}", red!("Unknown command: {}", command)),
     }
     Ok(())
 }
 
 /// Handles the interactive setup for the user's global identity.
-pub fn interactive_setup() -> io::Result<()> {
+pub fn interactive_setup() -> Result<()> {
     print!("Enter your name: ");
     io::stdout().flush()?;
     let mut name = String::new();
@@ -82,9 +750,9 @@ pub fn interactive_setup() -> io::Result<()> {
 
 #[cfg(test)]
 mod tests {
-    use super::repository;
+    use super::{Repository, repository, run_command};
+    use crate::repository::test_utils::{set_test_user, test_repo};
     use std::fs;
-    use std::path::Path;
 
     /// A full integration test for the init -> add -> commit workflow.
     #[test]
@@ -96,8 +764,6 @@ mod tests {
             fs::remove_dir_all(&temp_dir).unwrap();
         }
         fs::create_dir(&temp_dir).unwrap();
-        let original_dir = std::env::current_dir().unwrap();
-        std::env::set_current_dir(&temp_dir).unwrap();
 
         // --- Test Execution ---
 
@@ -105,34 +771,126 @@ mod tests {
         repository::config::setup_global_user("Test User", "test@example.com").unwrap();
 
         // 2. TEST `init`
-        repository::repo::create_repository();
+        let repo = Repository::init(&temp_dir).unwrap();
         assert!(temp_dir.join(".xit").is_dir());
         assert!(temp_dir.join(".xit/HEAD").is_file());
 
         // 3. TEST `add`
         let test_file_path = "hello.txt";
-        fs::write(test_file_path, "hello world").unwrap();
-        repository::add::add(test_file_path).unwrap();
+        fs::write(temp_dir.join(test_file_path), "hello world").unwrap();
+        repo.add(test_file_path).unwrap();
         let index_path = temp_dir.join(".xit/index");
         assert!(index_path.is_file());
-        let index_content = fs::read_to_string(index_path).unwrap();
-        assert!(index_content.contains("hello.txt"));
+        let index = repository::index::Index::read(&index_path).unwrap();
+        assert_eq!(index.entries().len(), 1);
+        assert_eq!(index.entries()[0].path, "hello.txt");
+        // Stat data is cached, though the size is zeroed when the file was
+        // written within the index's own timestamp.
+        assert_ne!(index.entries()[0].stat.mtime_s, 0);
 
         // 4. TEST `commit`
         let commit_message = "Initial test commit";
-        repository::commit::commit(commit_message).unwrap();
-        // The index should be gone after a successful commit
-        assert!(!temp_dir.join(".xit/index").exists());
+        let new_commit_hash = repo.commit(commit_message).unwrap().id;
+        // The index stays as the snapshot of the next commit, so the working
+        // tree is clean afterwards.
+        assert!(temp_dir.join(".xit/index").is_file());
+        assert!(repository::status::status(&repo).unwrap().is_clean());
+        assert!(matches!(
+            repo.commit("Nothing changed"),
+            Err(crate::Error::InvalidInput(_))
+        ));
         // The HEAD ref should now exist and contain a commit hash
         let head_ref_path = temp_dir.join(".xit/refs/heads/main");
         assert!(head_ref_path.is_file());
         let commit_hash = fs::read_to_string(head_ref_path).unwrap();
         assert_eq!(commit_hash.trim().len(), 40);
+        assert_eq!(commit_hash.trim(), new_commit_hash.to_hex());
+
+        // The commit object can be read back through the handle.
+        let (obj_type, _) = repo.read_object(&new_commit_hash).unwrap();
+        assert_eq!(obj_type, "commit");
+        let commit = repo.read_commit(&new_commit_hash).unwrap();
+        assert_eq!(commit.author.name, "Test User");
+        assert_eq!(commit.message, "Initial test commit\n");
 
         // --- Teardown ---
 
-        // 5. CLEANUP: Go back to the original directory and delete the temp directory
-        std::env::set_current_dir(original_dir).unwrap();
+        // 5. CLEANUP: Delete the temp directory
+        fs::remove_dir_all(temp_dir).unwrap();
+    }
+
+    /// Two repositories can be driven from separate threads without touching the cwd.
+    #[test]
+    fn test_repositories_on_multiple_threads() {
+        let handles: Vec<_> = (0..2)
+            .map(|i| {
+                std::thread::spawn(move || {
+                    let (repo, temp_dir) = test_repo(&format!("xit_test_thread_repo_{}", i));
+                    fs::write(temp_dir.join("file.txt"), format!("content {}", i)).unwrap();
+                    repo.add("file.txt").unwrap();
+                    let hash = repo.commit("thread commit").unwrap().id;
+                    assert_eq!(repo.read_object(&hash).unwrap().0, "commit");
+
+                    fs::remove_dir_all(temp_dir).unwrap();
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
+
+    /// Failures are reported through the exit code, with usage errors kept apart.
+    #[test]
+    fn test_run_command_exit_codes() {
+        let temp_dir = std::env::temp_dir().join("xit_test_exit_codes");
+        let _ = fs::remove_dir_all(&temp_dir);
+        fs::create_dir_all(&temp_dir).unwrap();
+        let run = |args: &[&str]| {
+            let mut argv = vec!["xit".to_string(), "-C".to_string()];
+            argv.push(temp_dir.to_string_lossy().into_owned());
+            argv.extend(args.iter().map(|arg| arg.to_string()));
+            run_command(&argv)
+        };
+
+        assert_eq!(run(&["status"]), 4);
+        assert_eq!(run(&["init"]), 0);
+        assert_eq!(run(&["add"]), 2);
+        assert_eq!(run(&["frobnicate"]), 2);
+        assert_eq!(run(&["add", "missing.txt"]), 3);
+        assert_eq!(run(&["status"]), 0);
+
+        fs::remove_dir_all(temp_dir).unwrap();
+    }
+
+    /// Options may come in any order and several -m options become paragraphs.
+    #[test]
+    fn test_commit_message_options() {
+        let temp_dir = std::env::temp_dir().join("xit_test_commit_options");
+        let _ = fs::remove_dir_all(&temp_dir);
+        fs::create_dir_all(&temp_dir).unwrap();
+        let dir = temp_dir.to_string_lossy().into_owned();
+        let run = |args: &[&str]| {
+            let mut argv = vec!["xit".to_string(), format!("--directory={}", dir)];
+            argv.extend(args.iter().map(|arg| arg.to_string()));
+            run_command(&argv)
+        };
+
+        assert_eq!(run(&["init"]), 0);
+        let repo = Repository::open(&temp_dir).unwrap();
+        set_test_user(&repo);
+        fs::write(temp_dir.join("a.txt"), "a").unwrap();
+
+        assert_eq!(run(&["add", "a.txt", "--help"]), 0);
+        assert_eq!(run(&["add", "a.txt"]), 0);
+        assert_eq!(run(&["commit", "a.txt", "-m", "x"]), 2);
+        assert_eq!(run(&["commit", "--message=Subject", "-m", "Body"]), 0);
+
+        let head = fs::read_to_string(repo.git_dir().join("refs/heads/main")).unwrap();
+        let commit = repo.read_commit(&head.trim().parse().unwrap()).unwrap();
+        assert_eq!(commit.message, "Subject\n\nBody\n");
+
         fs::remove_dir_all(temp_dir).unwrap();
     }
 }
@@ -1,10 +1,7 @@
 use std::env;
-use term_colr::red;
-use xit;
+use std::process;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if let Err(e) = xit::run_command(&args) {
-        eprintln!("{}", red!("Application error: {}", e));
-    }
-}
\ No newline at end of file
+    process::exit(xit::run_command(&args));
+}
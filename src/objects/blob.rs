@@ -1,13 +1,38 @@
 use flate2::Compression;
 use flate2::write::ZlibEncoder;
 use sha1::{Digest, Sha1};
-use std::io::Result;
 use std::io::Write;
+use std::path::Path;
 
-pub fn compute_sha1(data: &[u8]) -> [u8; 20] {
+use crate::error::Result;
+use crate::objects::id::ObjectId;
+use crate::objects::object;
+
+/// A blob object: the raw content of a file.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Blob {
+    pub data: Vec<u8>,
+}
+
+impl Blob {
+    /// Wraps the content of a blob object (without the `blob <len>\0` header).
+    pub fn parse(data: &[u8]) -> Blob {
+        Blob {
+            data: data.to_vec(),
+        }
+    }
+
+    /// Returns the blob content, which is stored as-is.
+    pub fn serialize(&self) -> Vec<u8> {
+        self.data.clone()
+    }
+}
+
+pub fn compute_sha1(data: &[u8]) -> ObjectId {
     let mut hasher = Sha1::new();
     hasher.update(data);
-    hasher.finalize().into()
+    let digest: [u8; 20] = hasher.finalize().into();
+    ObjectId::from(digest)
 }
 
 pub fn compress_zlib(data: &[u8]) -> Result<Vec<u8>> {
@@ -16,26 +41,8 @@ pub fn compress_zlib(data: &[u8]) -> Result<Vec<u8>> {
     Ok(encoder.finish()?)
 }
 
-pub fn hash_to_hex(hash: &[u8; 20]) -> String {
-    hash.iter()
-        .map(|byte| format!("{:02x}", byte))
-        .collect::<String>()
-}
-
-pub fn create_blob(content: &[u8]) -> Result<String> {
-    let header = format!("blob {}\0", content.len());
-    let data = [header.as_bytes(), content].concat();
-    let hash = compute_sha1(&data);
-    let compressed_data = compress_zlib(&data)?;
-    let hash_str = hash_to_hex(&hash);
-
-    // Create the directory structure
-    let dir_path = format!(".xit/objects/{}", &hash_str[0..2]);
-    std::fs::create_dir_all(&dir_path)?;
-
-    let path = format!("{}/{}", dir_path, &hash_str[2..]);
-    std::fs::write(path, compressed_data)?;
-    Ok(hash_str)
+pub fn create_blob(git_dir: &Path, content: &[u8]) -> Result<ObjectId> {
+    object::write_object(git_dir, "blob", content)
 }
 
 #[cfg(test)]
@@ -45,12 +52,22 @@ mod tests {
 
     #[test]
     fn test_create_blob() {
+        let git_dir = std::env::temp_dir().join("xit_test_create_blob");
+        let _ = fs::remove_dir_all(&git_dir);
+
         let content = b"hello world";
-        let hash = create_blob(content).unwrap();
-        assert_eq!(hash, "95d09f2b10159347eece71399a7e2e907ea3df4f");
+        let hash = create_blob(&git_dir, content).unwrap();
+        assert_eq!(hash.to_hex(), "95d09f2b10159347eece71399a7e2e907ea3df4f");
+        let (dir_name, file_name) = hash.loose_path_parts();
+        assert!(
+            git_dir
+                .join("objects")
+                .join(dir_name)
+                .join(file_name)
+                .is_file()
+        );
 
         // Clean up created files
-        let dir_path = format!(".xit/objects/{}", &hash[0..2]);
-        fs::remove_dir_all(dir_path).unwrap();
+        fs::remove_dir_all(git_dir).unwrap();
     }
-}
\ No newline at end of file
+}
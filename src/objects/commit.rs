@@ -1,54 +1,133 @@
-use crate::objects::blob::{compress_zlib, compute_sha1, hash_to_hex};
-use std::io::Result;
+use crate::error::{Error, Result};
+use crate::objects::id::ObjectId;
+use crate::objects::object;
+use crate::objects::signature::Signature;
+use std::path::Path;
+
+/// A parsed commit object.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Commit {
+    pub tree: ObjectId,
+    pub parents: Vec<ObjectId>,
+    pub author: Signature,
+    pub committer: Signature,
+    /// Headers other than tree/parent/author/committer (e.g. `encoding`, `gpgsig`),
+    /// in their original order. Multi-line values are joined with `\n`.
+    pub extra_headers: Vec<(String, String)>,
+    /// Everything after the blank line that ends the headers.
+    pub message: String,
+}
 
-pub fn create_commit(
-    tree_hash: &str,
-    parent_hash: Option<&str>,
-    author: &str,
-    committer: &str,
-    message: &str,
-) -> Result<String> {
-    // Validate inputs
-    if tree_hash.len() != 40 {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::InvalidData,
-            format!(
-                "Invalid tree hash length: expected 40 characters, got {}",
-                tree_hash.len()
-            ),
-        ));
+impl Commit {
+    /// Parses the content of a commit object (without the `commit <len>\0` header).
+    pub fn parse(data: &[u8]) -> Result<Commit> {
+        let text = std::str::from_utf8(data).map_err(|_| invalid("Commit is not valid UTF-8"))?;
+        let (headers, message) = split_headers(text);
+
+        let mut tree = None;
+        let mut parents = Vec::new();
+        let mut author = None;
+        let mut committer = None;
+        let mut extra_headers = Vec::new();
+
+        for (key, value) in headers {
+            match key {
+                "tree" => tree = Some(parse_id(&value)?),
+                "parent" => parents.push(parse_id(&value)?),
+                "author" => author = Some(Signature::parse(&value)?),
+                "committer" => committer = Some(Signature::parse(&value)?),
+                _ => extra_headers.push((key.to_string(), value)),
+            }
+        }
+
+        Ok(Commit {
+            tree: tree.ok_or_else(|| invalid("Tree hash not found in commit"))?,
+            parents,
+            author: author.ok_or_else(|| invalid("Author not found in commit"))?,
+            committer: committer.ok_or_else(|| invalid("Committer not found in commit"))?,
+            extra_headers,
+            message: message.to_string(),
+        })
     }
 
-    if let Some(parent) = parent_hash {
-        if parent.len() != 40 {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                format!(
-                    "Invalid parent hash length: expected 40 characters, got {}",
-                    parent.len()
-                ),
-            ));
+    /// Serializes the commit back into the git object format.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut content = format!("tree {}\n", self.tree);
+        for parent in &self.parents {
+            content.push_str(&format!("parent {}\n", parent));
+        }
+        content.push_str(&format!("author {}\n", self.author));
+        content.push_str(&format!("committer {}\n", self.committer));
+        for (key, value) in &self.extra_headers {
+            write_header(&mut content, key, value);
+        }
+        content.push('\n');
+        content.push_str(&self.message);
+        content.into_bytes()
+    }
+}
+
+/// Splits an object made of `key value` header lines, a blank line and a message.
+/// Continuation lines (starting with a space) are appended to the previous value.
+pub(crate) fn split_headers(text: &str) -> (Vec<(&str, String)>, &str) {
+    let (header_text, message) = match text.find("\n\n") {
+        Some(pos) => (&text[..pos], &text[pos + 2..]),
+        None => (text.strip_suffix('\n').unwrap_or(text), ""),
+    };
+
+    let mut headers: Vec<(&str, String)> = Vec::new();
+    for line in header_text.lines() {
+        if let Some(continuation) = line.strip_prefix(' ') {
+            if let Some((_, value)) = headers.last_mut() {
+                value.push('\n');
+                value.push_str(continuation);
+            }
+        } else {
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            headers.push((key, value.to_string()));
         }
     }
+    (headers, message)
+}
+
+/// Writes a header, turning embedded newlines into continuation lines.
+pub(crate) fn write_header(content: &mut String, key: &str, value: &str) {
+    content.push_str(key);
+    content.push(' ');
+    content.push_str(&value.replace('\n', "\n "));
+    content.push('\n');
+}
+
+/// Parses an object id found inside an object, reporting failures as corruption.
+pub(crate) fn parse_id(value: &str) -> Result<ObjectId> {
+    ObjectId::from_hex(value)
+        .map_err(|_| Error::CorruptObject(format!("Invalid object id '{}'", value)))
+}
 
+fn invalid(message: &str) -> Error {
+    Error::CorruptObject(message.to_string())
+}
+
+pub fn create_commit(
+    git_dir: &Path,
+    tree_hash: &ObjectId,
+    parent_hash: Option<&ObjectId>,
+    author: &str,
+    committer: &str,
+    message: &str,
+) -> Result<ObjectId> {
+    // Validate inputs
     if author.is_empty() {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::InvalidData,
-            "Author cannot be empty",
-        ));
+        return Err(Error::InvalidInput("Author cannot be empty".to_string()));
     }
 
     if committer.is_empty() {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::InvalidData,
-            "Committer cannot be empty",
-        ));
+        return Err(Error::InvalidInput("Committer cannot be empty".to_string()));
     }
 
     if message.is_empty() {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::InvalidData,
-            "Commit message cannot be empty",
+        return Err(Error::InvalidInput(
+            "Commit message cannot be empty".to_string(),
         ));
     }
 
@@ -85,23 +164,7 @@ pub fn create_commit(
         message
     ));
 
-    // Create commit header: "commit {size}\0"
-    let header = format!("commit {}\0", content.len());
-    let data = [header.as_bytes(), content.as_bytes()].concat();
-
-    // Compute hash and compress
-    let hash = compute_sha1(&data);
-    let compressed_data = compress_zlib(&data)?;
-    let hash_str = hash_to_hex(&hash);
-
-    // Create directory structure and write file
-    let dir_path = format!(".xit/objects/{}", &hash_str[0..2]);
-    std::fs::create_dir_all(&dir_path)?;
-
-    let path = format!("{}/{}", dir_path, &hash_str[2..]);
-    std::fs::write(path, compressed_data)?;
-
-    Ok(hash_str)
+    object::write_object(git_dir, "commit", content.as_bytes())
 }
 
 // // Helper function to create initial commit (no parent)
@@ -221,16 +284,73 @@ mod tests {
 
     #[test]
     fn test_create_commit() {
-        let tree_hash = "1234567890123456789012345678901234567890";
-        let parent_hash = "0987654321098765432109876543210987654321";
+        let git_dir = std::env::temp_dir().join("xit_test_create_commit");
+        let _ = fs::remove_dir_all(&git_dir);
+
+        let tree_hash: ObjectId = "1234567890123456789012345678901234567890".parse().unwrap();
+        let parent_hash: ObjectId = "0987654321098765432109876543210987654321".parse().unwrap();
         let author = "Author Name <author@example.com>";
         let committer = "Committer Name <committer@example.com>";
         let message = "Test commit";
 
-        let hash = create_commit(tree_hash, Some(parent_hash), author, committer, message).unwrap();
+        let hash = create_commit(
+            &git_dir,
+            &tree_hash,
+            Some(&parent_hash),
+            author,
+            committer,
+            message,
+        )
+        .unwrap();
+        let (dir_name, file_name) = hash.loose_path_parts();
+        assert!(
+            git_dir
+                .join("objects")
+                .join(dir_name)
+                .join(file_name)
+                .is_file()
+        );
 
         // Clean up created files
-        let dir_path = format!(".xit/objects/{}", &hash[0..2]);
-        fs::remove_dir_all(dir_path).unwrap();
+        fs::remove_dir_all(git_dir).unwrap();
+    }
+
+    #[test]
+    fn test_commit_round_trip() {
+        let raw = "tree 03e3bbd3138ee3298953d5f416ac0a839b5e5d05\n\
+parent 0987654321098765432109876543210987654321\n\
+parent 1234567890123456789012345678901234567890\n\
+author Author Name <author@example.com> 1700000000 +0530\n\
+committer Committer Name <committer@example.com> 1700000100 -0800\n\
+encoding ISO-8859-1\n\
+gpgsig -----BEGIN PGP SIGNATURE-----\n \n abcdef\n -----END PGP SIGNATURE-----\n\
+\n\
+Subject line\n\nBody paragraph.\n";
+
+        let commit = Commit::parse(raw.as_bytes()).unwrap();
+        assert_eq!(
+            commit.tree.to_hex(),
+            "03e3bbd3138ee3298953d5f416ac0a839b5e5d05"
+        );
+        assert_eq!(commit.parents.len(), 2);
+        assert_eq!(commit.author.name, "Author Name");
+        assert_eq!(commit.author.email, "author@example.com");
+        assert_eq!(commit.author.time, 1700000000);
+        assert_eq!(commit.author.offset_minutes, 330);
+        assert_eq!(commit.committer.offset_minutes, -480);
+        assert_eq!(
+            commit.extra_headers[0],
+            ("encoding".to_string(), "ISO-8859-1".to_string())
+        );
+        assert!(commit.extra_headers[1].1.contains("\nabcdef\n"));
+        assert_eq!(commit.message, "Subject line\n\nBody paragraph.\n");
+
+        assert_eq!(commit.serialize(), raw.as_bytes());
+    }
+
+    #[test]
+    fn test_commit_parse_rejects_missing_tree() {
+        let raw = "author A <a@example.com> 1 +0000\ncommitter A <a@example.com> 1 +0000\n\nmsg\n";
+        assert!(Commit::parse(raw.as_bytes()).is_err());
     }
 }
@@ -0,0 +1,145 @@
+use crate::error::{Error, Result};
+use std::fmt;
+use std::str::FromStr;
+
+/// Length of a SHA-1 object id in bytes.
+pub const OID_LEN: usize = 20;
+
+/// Length of a SHA-1 object id in hexadecimal characters.
+pub const OID_HEX_LEN: usize = OID_LEN * 2;
+
+/// The SHA-1 id of an object. It can only be built from exactly 20 bytes or
+/// 40 hex characters, so every value is a well-formed id.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ObjectId([u8; OID_LEN]);
+
+impl ObjectId {
+    /// Builds an id from its raw bytes, failing unless exactly 20 bytes are given.
+    pub fn from_bytes(bytes: &[u8]) -> Result<ObjectId> {
+        bytes.try_into().map(ObjectId).map_err(|_| {
+            Error::InvalidInput(format!(
+                "Invalid object id length: expected {} bytes, got {}",
+                OID_LEN,
+                bytes.len()
+            ))
+        })
+    }
+
+    /// Parses a full 40-character hexadecimal id. Upper-case digits are accepted.
+    pub fn from_hex(hex: &str) -> Result<ObjectId> {
+        if hex.len() != OID_HEX_LEN {
+            return Err(Error::InvalidInput(format!(
+                "Invalid object id '{}': expected {} hex characters, got {}",
+                hex,
+                OID_HEX_LEN,
+                hex.len()
+            )));
+        }
+        let mut bytes = [0u8; OID_LEN];
+        hex::decode_to_slice(hex, &mut bytes).map_err(|_| {
+            Error::InvalidInput(format!("Invalid object id '{}': not hexadecimal", hex))
+        })?;
+        Ok(ObjectId(bytes))
+    }
+
+    /// The raw 20 bytes, as stored in tree objects.
+    pub fn as_bytes(&self) -> &[u8; OID_LEN] {
+        &self.0
+    }
+
+    /// The full lower-case hexadecimal form.
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.0)
+    }
+
+    /// The first `len` hex characters (clamped to 4..=40), like `git rev-parse --short`.
+    pub fn abbrev(&self, len: usize) -> String {
+        let mut hex = self.to_hex();
+        hex.truncate(len.clamp(4, OID_HEX_LEN));
+        hex
+    }
+
+    /// The default 7-character abbreviation.
+    pub fn short(&self) -> String {
+        self.abbrev(7)
+    }
+
+    /// Returns true if the hex form starts with `prefix` (case-insensitive).
+    pub fn starts_with_hex(&self, prefix: &str) -> bool {
+        prefix.len() <= OID_HEX_LEN
+            && self
+                .to_hex()
+                .as_bytes()
+                .starts_with(prefix.to_ascii_lowercase().as_bytes())
+    }
+
+    /// The directory and file name of the loose object: `ab` and `cdef...`.
+    pub fn loose_path_parts(&self) -> (String, String) {
+        let hex = self.to_hex();
+        (hex[..2].to_string(), hex[2..].to_string())
+    }
+}
+
+impl From<[u8; OID_LEN]> for ObjectId {
+    fn from(bytes: [u8; OID_LEN]) -> ObjectId {
+        ObjectId(bytes)
+    }
+}
+
+impl FromStr for ObjectId {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<ObjectId> {
+        ObjectId::from_hex(s)
+    }
+}
+
+impl fmt::Display for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in &self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ObjectId({})", self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_display() {
+        let hex = "95d09f2b10159347eece71399a7e2e907ea3df4f";
+        let id: ObjectId = hex.parse().unwrap();
+        assert_eq!(id.to_string(), hex);
+        assert_eq!(id.to_hex(), hex);
+        assert_eq!(id.short(), "95d09f2");
+        assert_eq!(id.abbrev(1), "95d0");
+        assert_eq!(id.abbrev(100), hex);
+        assert_eq!(ObjectId::from_hex(&hex.to_uppercase()).unwrap(), id);
+        assert_eq!(ObjectId::from_bytes(id.as_bytes()).unwrap(), id);
+        assert!(id.starts_with_hex("95D09"));
+        assert!(!id.starts_with_hex("95d1"));
+    }
+
+    #[test]
+    fn test_rejects_invalid_ids() {
+        assert!(ObjectId::from_hex("95d09f2").is_err());
+        assert!(ObjectId::from_hex("95d09f2b10159347eece71399a7e2e907ea3df4").is_err());
+        assert!(ObjectId::from_hex("zzd09f2b10159347eece71399a7e2e907ea3df4f").is_err());
+        assert!(ObjectId::from_bytes(&[0u8; 19]).is_err());
+    }
+
+    #[test]
+    fn test_ordering_follows_bytes() {
+        let low = ObjectId::from([0u8; OID_LEN]);
+        let high = ObjectId::from([0xffu8; OID_LEN]);
+        assert!(low < high);
+    }
+}
@@ -1,5 +1,9 @@
 pub mod blob;
 pub mod commit;
+pub mod id;
+pub mod object;
 pub mod read;
+pub mod signature;
+pub mod tag;
 pub mod tree;
 pub mod update;
@@ -0,0 +1,150 @@
+use crate::error::{Error, Result};
+use crate::objects::blob::{Blob, compress_zlib};
+use crate::objects::commit::Commit;
+use crate::objects::id::ObjectId;
+use crate::objects::tag::Tag;
+use crate::objects::tree::Tree;
+use sha1::{Digest, Sha1};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Numbers the temporary files of the objects this process is writing.
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Any object stored in the object database, parsed according to its type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Object {
+    Blob(Blob),
+    Tree(Tree),
+    Commit(Commit),
+    Tag(Tag),
+}
+
+impl Object {
+    /// Parses an object from the type and content returned by `utils::read_object`.
+    pub fn parse(obj_type: &str, content: &[u8]) -> Result<Object> {
+        match obj_type {
+            "blob" => Ok(Object::Blob(Blob::parse(content))),
+            "tree" => Ok(Object::Tree(Tree::parse(content)?)),
+            "commit" => Ok(Object::Commit(Commit::parse(content)?)),
+            "tag" => Ok(Object::Tag(Tag::parse(content)?)),
+            _ => Err(Error::CorruptObject(format!(
+                "Unknown object type '{}'",
+                obj_type
+            ))),
+        }
+    }
+
+    /// The object type as written in the object header.
+    pub fn obj_type(&self) -> &'static str {
+        match self {
+            Object::Blob(_) => "blob",
+            Object::Tree(_) => "tree",
+            Object::Commit(_) => "commit",
+            Object::Tag(_) => "tag",
+        }
+    }
+
+    /// Serializes the object content (without the `<type> <len>\0` header).
+    pub fn serialize(&self) -> Result<Vec<u8>> {
+        match self {
+            Object::Blob(blob) => Ok(blob.serialize()),
+            Object::Tree(tree) => tree.serialize(),
+            Object::Commit(commit) => Ok(commit.serialize()),
+            Object::Tag(tag) => Ok(tag.serialize()),
+        }
+    }
+
+    /// Writes the object to the object database and returns its id.
+    pub fn write(&self, git_dir: &Path) -> Result<ObjectId> {
+        write_object(git_dir, self.obj_type(), &self.serialize()?)
+    }
+}
+
+/// The id of an object of type `kind` (`blob`, `tree`, ...) with this content:
+/// the SHA-1 of the `<kind> <len>\0` header followed by the content, as in git.
+/// Anything that compares files with stored objects must hash them this way.
+pub fn hash_object(kind: &str, content: &[u8]) -> ObjectId {
+    let mut hasher = Sha1::new();
+    hasher.update(format!("{} {}\0", kind, content.len()));
+    hasher.update(content);
+    let digest: [u8; 20] = hasher.finalize().into();
+    ObjectId::from(digest)
+}
+
+/// Stores `content` as a loose object of the given type and returns its id.
+///
+/// The object is written to a temporary file next to it and renamed into
+/// place, so that a concurrent reader or writer never sees it half written.
+pub fn write_object(git_dir: &Path, obj_type: &str, content: &[u8]) -> Result<ObjectId> {
+    let hash = hash_object(obj_type, content);
+    let header = format!("{} {}\0", obj_type, content.len());
+    let data = [header.as_bytes(), content].concat();
+
+    let (dir_name, file_name) = hash.loose_path_parts();
+    let dir_path = git_dir.join("objects").join(dir_name);
+    let path = dir_path.join(file_name);
+    if !path.exists() {
+        let compressed = compress_zlib(&data)?;
+        fs::create_dir_all(&dir_path)?;
+        let temp_path = dir_path.join(format!(
+            "tmp_obj_{}_{}",
+            process::id(),
+            TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let written = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)
+            .and_then(|mut file| {
+                file.write_all(&compressed)?;
+                file.sync_all()
+            })
+            .and_then(|()| fs::rename(&temp_path, &path));
+        if let Err(e) = written {
+            let _ = fs::remove_file(&temp_path);
+            return Err(e.into());
+        }
+    }
+    Ok(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_object_matches_git() {
+        // `git hash-object` and the empty tree id.
+        assert_eq!(
+            hash_object("blob", b"").to_hex(),
+            "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391"
+        );
+        assert_eq!(
+            hash_object("blob", b"hello world").to_hex(),
+            "95d09f2b10159347eece71399a7e2e907ea3df4f"
+        );
+        assert_eq!(
+            hash_object("tree", b"").to_hex(),
+            "4b825dc642cb6eb9a060e54bf8d69288fbee4904"
+        );
+
+        let git_dir = std::env::temp_dir().join("xit_test_hash_object");
+        let _ = fs::remove_dir_all(&git_dir);
+        let content = b"line\r\nwith crlf\r\n";
+        let id = write_object(&git_dir, "blob", content).unwrap();
+        assert_eq!(id, hash_object("blob", content));
+
+        // Only the object itself is left, its temporary file renamed away.
+        let (dir_name, file_name) = id.loose_path_parts();
+        let names: Vec<_> = fs::read_dir(git_dir.join("objects").join(dir_name))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(names, [file_name.as_str()]);
+        fs::remove_dir_all(git_dir).unwrap();
+    }
+}
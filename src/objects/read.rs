@@ -1,83 +1,79 @@
+use crate::error::{Error, Result};
+use crate::objects::commit::Commit;
+use crate::objects::id::ObjectId;
+use crate::objects::object::Object;
+use crate::objects::tree::{FileMode, Tree};
 use crate::repository::utils;
 use std::collections::HashMap;
-use std::io;
-use hex;
+use std::path::Path;
 
-pub fn get_commit_tree_hash(commit_hash: &str) -> io::Result<String> {
-    let (obj_type, content) = utils::read_object(commit_hash)?;
-    if obj_type != "commit" {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidData,
-            "Object is not a commit",
-        ));
+/// Reads an object and parses it according to its type.
+pub fn read_parsed(git_dir: &Path, hash: &ObjectId) -> Result<Object> {
+    let (obj_type, content) = utils::read_object(git_dir, hash)?;
+    Object::parse(&obj_type, &content)
+}
+
+/// Reads and parses a commit object.
+pub fn read_commit(git_dir: &Path, commit_hash: &ObjectId) -> Result<Commit> {
+    match read_parsed(git_dir, commit_hash)? {
+        Object::Commit(commit) => Ok(commit),
+        _ => Err(Error::InvalidInput(format!(
+            "Object {} is not a commit",
+            commit_hash
+        ))),
     }
+}
 
-    let content_str = String::from_utf8_lossy(&content);
-    for line in content_str.lines() {
-        if line.starts_with("tree ") {
-            return Ok(line[5..].to_string());
-        }
+/// Reads and parses a tree object.
+pub fn read_tree(git_dir: &Path, tree_hash: &ObjectId) -> Result<Tree> {
+    match read_parsed(git_dir, tree_hash)? {
+        Object::Tree(tree) => Ok(tree),
+        _ => Err(Error::InvalidInput(format!(
+            "Object {} is not a tree",
+            tree_hash
+        ))),
     }
+}
 
-    Err(io::Error::new(
-        io::ErrorKind::InvalidData,
-        "Tree hash not found in commit",
-    ))
+pub fn get_commit_tree_hash(git_dir: &Path, commit_hash: &ObjectId) -> Result<ObjectId> {
+    Ok(read_commit(git_dir, commit_hash)?.tree)
 }
 
-pub fn list_files_in_tree(tree_hash: &str) -> io::Result<HashMap<String, String>> {
+/// Every file of a tree and its subtrees, by `/`-separated path, with the id
+/// and mode of its blob.
+pub fn list_files_in_tree(
+    git_dir: &Path,
+    tree_hash: &ObjectId,
+) -> Result<HashMap<String, (ObjectId, FileMode)>> {
     let mut files = HashMap::new();
-    list_files_recursive(tree_hash, "", &mut files)?;
+    list_files_recursive(git_dir, tree_hash, "", &mut files)?;
     Ok(files)
 }
 
 fn list_files_recursive(
-    tree_hash: &str,
+    git_dir: &Path,
+    tree_hash: &ObjectId,
     current_path: &str,
-    files: &mut HashMap<String, String>,
-) -> io::Result<()> {
-    let (obj_type, content) = utils::read_object(tree_hash)?;
-    if obj_type != "tree" {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidData,
-            "Object is not a tree",
-        ));
-    }
-
-    let mut cursor = 0;
-    while cursor < content.len() {
-        let space_pos = content[cursor..]
-            .iter()
-            .position(|&b| b == b' ')
-            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid tree entry format"))?
-            + cursor;
-
-        let null_pos = content[cursor..]
-            .iter()
-            .position(|&b| b == 0)
-            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid tree entry format"))?
-            + cursor;
-
-        let name = String::from_utf8_lossy(&content[space_pos + 1..null_pos]).to_string();
-        let hash_bytes = &content[null_pos + 1..null_pos + 21];
-        let hash_hex = hex::encode(hash_bytes);
+    files: &mut HashMap<String, (ObjectId, FileMode)>,
+) -> Result<()> {
+    let tree = read_tree(git_dir, tree_hash)?;
 
+    for entry in tree.entries {
         let path = if current_path.is_empty() {
-            name.clone()
+            entry.name
         } else {
-            format!("{}/{}", current_path, name)
+            format!("{}/{}", current_path, entry.name)
         };
 
-        // For this project, we assume the mode indicates a blob or a tree.
-        // A more robust implementation would parse the mode properly.
-        if content[cursor..space_pos].starts_with(b"100") { // It's a blob
-            files.insert(path, hash_hex);
-        } else { // It's a tree
-            list_files_recursive(&hash_hex, &path, files)?;
+        match entry.mode {
+            FileMode::Tree => list_files_recursive(git_dir, &entry.hash, &path, files)?,
+            // Submodules have no content in this repository.
+            FileMode::Gitlink => {}
+            _ => {
+                files.insert(path, (entry.hash, entry.mode));
+            }
         }
-
-        cursor = null_pos + 21;
     }
 
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,93 @@
+use crate::error::{Error, Result};
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// An author, committer or tagger line: `Name <email> <seconds> <+hhmm>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    pub name: String,
+    pub email: String,
+    /// Seconds since the Unix epoch.
+    pub time: i64,
+    /// Offset from UTC in minutes.
+    pub offset_minutes: i32,
+}
+
+impl Signature {
+    /// Creates a signature stamped with the current time in UTC.
+    pub fn now(name: &str, email: &str) -> Signature {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+        Signature {
+            name: name.to_string(),
+            email: email.to_string(),
+            time,
+            offset_minutes: 0,
+        }
+    }
+
+    /// Parses the value of a signature header (everything after `author `).
+    pub fn parse(value: &str) -> Result<Signature> {
+        let invalid = || Error::CorruptObject(format!("Invalid signature '{}'", value));
+
+        let email_start = value.find('<').ok_or_else(invalid)?;
+        let email_end = value.rfind('>').ok_or_else(invalid)?;
+        if email_end < email_start {
+            return Err(invalid());
+        }
+
+        let name = value[..email_start].trim_end().to_string();
+        let email = value[email_start + 1..email_end].to_string();
+
+        let mut when = value[email_end + 1..].split_whitespace();
+        let time = when
+            .next()
+            .and_then(|t| t.parse::<i64>().ok())
+            .ok_or_else(invalid)?;
+        let offset_minutes = when.next().and_then(parse_offset).ok_or_else(invalid)?;
+        if when.next().is_some() {
+            return Err(invalid());
+        }
+
+        Ok(Signature {
+            name,
+            email,
+            time,
+            offset_minutes,
+        })
+    }
+}
+
+/// Parses a `+hhmm` / `-hhmm` timezone offset into minutes.
+fn parse_offset(offset: &str) -> Option<i32> {
+    let (sign, digits) = match offset.split_at_checked(1)? {
+        ("+", digits) => (1, digits),
+        ("-", digits) => (-1, digits),
+        _ => return None,
+    };
+    if digits.len() != 4 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let hours: i32 = digits[..2].parse().ok()?;
+    let minutes: i32 = digits[2..].parse().ok()?;
+    Some(sign * (hours * 60 + minutes))
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.offset_minutes < 0 { '-' } else { '+' };
+        let offset = self.offset_minutes.abs();
+        write!(
+            f,
+            "{} <{}> {} {}{:02}{:02}",
+            self.name,
+            self.email,
+            self.time,
+            sign,
+            offset / 60,
+            offset % 60
+        )
+    }
+}
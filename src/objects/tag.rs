@@ -0,0 +1,102 @@
+use crate::error::{Error, Result};
+use crate::objects::commit::{parse_id, split_headers, write_header};
+use crate::objects::id::ObjectId;
+use crate::objects::signature::Signature;
+
+/// A parsed annotated tag object.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tag {
+    /// Hash of the tagged object.
+    pub object: ObjectId,
+    /// Type of the tagged object (usually `commit`).
+    pub obj_type: String,
+    /// Name of the tag.
+    pub tag: String,
+    /// Very old tags have no tagger line.
+    pub tagger: Option<Signature>,
+    /// Headers other than object/type/tag/tagger, in their original order.
+    pub extra_headers: Vec<(String, String)>,
+    /// Everything after the blank line that ends the headers, including any signature.
+    pub message: String,
+}
+
+impl Tag {
+    /// Parses the content of a tag object (without the `tag <len>\0` header).
+    pub fn parse(data: &[u8]) -> Result<Tag> {
+        let text = std::str::from_utf8(data).map_err(|_| invalid("Tag is not valid UTF-8"))?;
+        let (headers, message) = split_headers(text);
+
+        let mut object = None;
+        let mut obj_type = None;
+        let mut tag = None;
+        let mut tagger = None;
+        let mut extra_headers = Vec::new();
+
+        for (key, value) in headers {
+            match key {
+                "object" => object = Some(parse_id(&value)?),
+                "type" => obj_type = Some(value),
+                "tag" => tag = Some(value),
+                "tagger" => tagger = Some(Signature::parse(&value)?),
+                _ => extra_headers.push((key.to_string(), value)),
+            }
+        }
+
+        Ok(Tag {
+            object: object.ok_or_else(|| invalid("Object not found in tag"))?,
+            obj_type: obj_type.ok_or_else(|| invalid("Type not found in tag"))?,
+            tag: tag.ok_or_else(|| invalid("Tag name not found in tag"))?,
+            tagger,
+            extra_headers,
+            message: message.to_string(),
+        })
+    }
+
+    /// Serializes the tag back into the git object format.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut content = format!(
+            "object {}\ntype {}\ntag {}\n",
+            self.object, self.obj_type, self.tag
+        );
+        if let Some(tagger) = &self.tagger {
+            content.push_str(&format!("tagger {}\n", tagger));
+        }
+        for (key, value) in &self.extra_headers {
+            write_header(&mut content, key, value);
+        }
+        content.push('\n');
+        content.push_str(&self.message);
+        content.into_bytes()
+    }
+}
+
+fn invalid(message: &str) -> Error {
+    Error::CorruptObject(message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tag_round_trip() {
+        let raw = "object 0987654321098765432109876543210987654321\n\
+type commit\n\
+tag v1.0.0\n\
+tagger Tagger Name <tagger@example.com> 1700000000 +0000\n\
+\n\
+Release 1.0.0\n";
+
+        let tag = Tag::parse(raw.as_bytes()).unwrap();
+        assert_eq!(
+            tag.object.to_hex(),
+            "0987654321098765432109876543210987654321"
+        );
+        assert_eq!(tag.obj_type, "commit");
+        assert_eq!(tag.tag, "v1.0.0");
+        assert_eq!(tag.tagger.as_ref().unwrap().name, "Tagger Name");
+        assert_eq!(tag.message, "Release 1.0.0\n");
+
+        assert_eq!(tag.serialize(), raw.as_bytes());
+    }
+}
@@ -1,103 +1,316 @@
-use std::io::Result;
+use std::path::Path;
 
-use crate::objects::blob::{compress_zlib, compute_sha1, hash_to_hex};
+use crate::error::{Error, Result};
+use crate::objects::id::ObjectId;
+use crate::objects::object;
 
-#[derive(Debug)]
+/// The mode of a tree entry, which also determines the type of object it points to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FileMode {
+    /// `100644`, a regular file.
+    Regular,
+    /// `100755`, an executable file.
+    Executable,
+    /// `120000`, a symbolic link whose target is stored in the blob.
+    Symlink,
+    /// `40000`, a subdirectory.
+    Tree,
+    /// `160000`, a submodule commit.
+    Gitlink,
+}
+
+impl FileMode {
+    /// Parses the octal mode string used in tree objects. Only the modes git
+    /// writes today are accepted; see [`FileMode::parse_legacy`].
+    pub fn parse(mode: &str) -> Option<FileMode> {
+        match mode {
+            "100644" => Some(FileMode::Regular),
+            "100755" => Some(FileMode::Executable),
+            "120000" => Some(FileMode::Symlink),
+            "40000" => Some(FileMode::Tree),
+            "160000" => Some(FileMode::Gitlink),
+            _ => None,
+        }
+    }
+
+    /// Parses a mode that old versions of git, or other tools, wrote
+    /// differently: `100664` for a group-writable file and a zero-padded
+    /// `040000` for a subdirectory.
+    pub fn parse_legacy(mode: &str) -> Option<FileMode> {
+        match mode {
+            "100664" => Some(FileMode::Regular),
+            "040000" => Some(FileMode::Tree),
+            _ => None,
+        }
+    }
+
+    /// The octal mode string as git writes it in tree objects.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FileMode::Regular => "100644",
+            FileMode::Executable => "100755",
+            FileMode::Symlink => "120000",
+            FileMode::Tree => "40000",
+            FileMode::Gitlink => "160000",
+        }
+    }
+
+    /// The type of object an entry with this mode points to.
+    pub fn obj_type(&self) -> &'static str {
+        match self {
+            FileMode::Tree => "tree",
+            FileMode::Gitlink => "commit",
+            _ => "blob",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct TreeEntry {
-    pub mode: String,
-    pub obj_type: String, // tree or blob
-    pub hash: Vec<u8>,
+    pub mode: FileMode,
+    pub hash: ObjectId,
     pub name: String,
+    /// The mode as found in a parsed tree when it is not the way git writes
+    /// `mode` (see [`FileMode::parse_legacy`]). It is written back unchanged,
+    /// so that the tree keeps its bytes and therefore its id.
+    pub legacy_mode: Option<String>,
 }
 
-pub fn create_tree(entries: Vec<TreeEntry>) -> Result<String> {
-    // Sort entries by name (Git requirement)
-    let mut sorted_entries = entries;
-    sorted_entries.sort_by(|a, b| a.name.cmp(&b.name));
-
-    let mut data = Vec::new();
-
-    for entry in sorted_entries {
-        // Validate entry data
-        if entry.hash.len() != 20 {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                format!(
-                    "Invalid hash length for entry '{}': expected 20 bytes, got {}",
-                    entry.name,
-                    entry.hash.len()
-                ),
-            ));
-        }
+impl TreeEntry {
+    /// The type of object the entry points to: tree, blob or commit.
+    pub fn obj_type(&self) -> &'static str {
+        self.mode.obj_type()
+    }
+}
+
+/// A parsed tree object.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Tree {
+    pub entries: Vec<TreeEntry>,
+}
 
-        if entry.name.is_empty() {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "Tree entry name cannot be empty",
-            ));
+impl Tree {
+    /// Parses the content of a tree object (without the `tree <len>\0` header).
+    pub fn parse(content: &[u8]) -> Result<Tree> {
+        let invalid = || Error::CorruptObject("Invalid tree entry format".to_string());
+
+        let mut entries = Vec::new();
+        let mut cursor = 0;
+        while cursor < content.len() {
+            let space_pos = content[cursor..]
+                .iter()
+                .position(|&b| b == b' ')
+                .ok_or_else(invalid)?
+                + cursor;
+            let null_pos = content[space_pos..]
+                .iter()
+                .position(|&b| b == 0)
+                .ok_or_else(invalid)?
+                + space_pos;
+            let hash = content
+                .get(null_pos + 1..null_pos + 21)
+                .ok_or_else(invalid)
+                .and_then(ObjectId::from_bytes)?;
+
+            let mode_str =
+                std::str::from_utf8(&content[cursor..space_pos]).map_err(|_| invalid())?;
+            let (mode, legacy_mode) = match FileMode::parse(mode_str) {
+                Some(mode) => (mode, None),
+                None => {
+                    let mode = FileMode::parse_legacy(mode_str).ok_or_else(invalid)?;
+                    (mode, Some(mode_str.to_string()))
+                }
+            };
+            let name = String::from_utf8(content[space_pos + 1..null_pos].to_vec())
+                .map_err(|_| invalid())?;
+
+            entries.push(TreeEntry {
+                mode,
+                hash,
+                name,
+                legacy_mode,
+            });
+            cursor = null_pos + 21;
         }
 
-        // Build tree entry format: mode name\0hash
-        data.extend_from_slice(entry.mode.as_bytes());
-        data.push(b' ');
-        data.extend_from_slice(entry.name.as_bytes());
-        data.push(b'\0');
-        data.extend_from_slice(&entry.hash);
+        Ok(Tree { entries })
     }
 
-    // Create tree header: "tree {size}\0"
-    let header = format!("tree {}\0", data.len());
-    let full_data = [header.as_bytes(), &data].concat();
+    /// Serializes the entries, in their current order, into the git object format.
+    pub fn serialize(&self) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
 
-    // Compute hash and compress
-    let hash = compute_sha1(&full_data);
-    let compressed_data = compress_zlib(&full_data)?;
-    let hash_str = hash_to_hex(&hash);
+        for entry in &self.entries {
+            // Validate entry data
+            if entry.name.is_empty() {
+                return Err(Error::InvalidInput(
+                    "Tree entry name cannot be empty".to_string(),
+                ));
+            }
 
-    // Create directory structure and write file
-    let dir_path = format!(".xit/objects/{}", &hash_str[0..2]);
-    std::fs::create_dir_all(&dir_path)?;
+            if entry.name.contains('/') || entry.name.contains('\0') {
+                return Err(Error::InvalidInput(format!(
+                    "Invalid tree entry name '{}'",
+                    entry.name
+                )));
+            }
 
-    let path = format!("{}/{}", dir_path, &hash_str[2..]);
-    std::fs::write(path, compressed_data)?;
+            // Build tree entry format: mode name\0hash
+            let mode = entry.legacy_mode.as_deref().unwrap_or(entry.mode.as_str());
+            data.extend_from_slice(mode.as_bytes());
+            data.push(b' ');
+            data.extend_from_slice(entry.name.as_bytes());
+            data.push(b'\0');
+            data.extend_from_slice(entry.hash.as_bytes());
+        }
+
+        Ok(data)
+    }
 
-    Ok(hash_str)
+    /// Sorts the entries into the order git requires. Git compares subtrees as if
+    /// their name ended with '/', so "foo.txt" sorts before a directory named "foo".
+    pub fn sort(&mut self) {
+        self.entries.sort_by_key(sort_key);
+    }
+}
+
+pub fn create_tree(git_dir: &Path, entries: Vec<TreeEntry>) -> Result<ObjectId> {
+    // Sort entries by name (Git requirement)
+    let mut tree = Tree { entries };
+    tree.sort();
+    let data = tree.serialize()?;
+    object::write_object(git_dir, "tree", &data)
+}
+
+/// The byte string git uses to order entries within a tree.
+fn sort_key(entry: &TreeEntry) -> Vec<u8> {
+    let mut key = entry.name.as_bytes().to_vec();
+    if entry.mode == FileMode::Tree {
+        key.push(b'/');
+    }
+    key
 }
 
 // Helper function to create a blob entry
-pub fn create_blob_entry(mode: &str, hash: &[u8; 20], name: &str) -> TreeEntry {
+pub fn create_blob_entry(mode: FileMode, hash: &ObjectId, name: &str) -> TreeEntry {
     TreeEntry {
-        mode: mode.to_string(),
-        obj_type: "blob".to_string(),
-        hash: hash.to_vec(),
+        mode,
+        hash: *hash,
         name: name.to_string(),
+        legacy_mode: None,
     }
 }
 
 // Helper function to create a tree entry
-pub fn create_tree_entry(mode: &str, hash: &[u8; 20], name: &str) -> TreeEntry {
+pub fn create_tree_entry(hash: &ObjectId, name: &str) -> TreeEntry {
     TreeEntry {
-        mode: mode.to_string(),
-        obj_type: "tree".to_string(),
-        hash: hash.to_vec(),
+        mode: FileMode::Tree,
+        hash: *hash,
         name: name.to_string(),
+        legacy_mode: None,
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::objects::blob::compute_sha1;
     use std::fs;
 
     #[test]
     fn test_create_tree() {
+        let git_dir = std::env::temp_dir().join("xit_test_create_tree");
+        let _ = fs::remove_dir_all(&git_dir);
+
         let blob_hash = compute_sha1(b"hello world");
-        let entries = vec![create_blob_entry("100644", &blob_hash, "hello.txt")];
+        let entries = vec![create_blob_entry(
+            FileMode::Regular,
+            &blob_hash,
+            "hello.txt",
+        )];
 
-        let hash = create_tree(entries).unwrap();
+        let hash = create_tree(&git_dir, entries).unwrap();
+        let (dir_name, file_name) = hash.loose_path_parts();
+        assert!(
+            git_dir
+                .join("objects")
+                .join(dir_name)
+                .join(file_name)
+                .is_file()
+        );
 
         // Clean up created files
-        let dir_path = format!(".xit/objects/{}", &hash[0..2]);
-        fs::remove_dir_all(dir_path).unwrap();
+        fs::remove_dir_all(git_dir).unwrap();
+    }
+
+    #[test]
+    fn test_tree_entries_use_git_order() {
+        let git_dir = std::env::temp_dir().join("xit_test_tree_order");
+        let _ = fs::remove_dir_all(&git_dir);
+
+        let blob_hash = compute_sha1(b"blob 0\0");
+        let entries = vec![
+            create_tree_entry(&blob_hash, "foo"),
+            create_blob_entry(FileMode::Regular, &blob_hash, "foo.txt"),
+        ];
+        let hash = create_tree(&git_dir, entries).unwrap();
+
+        let (_, content) = crate::repository::utils::read_object(&git_dir, &hash).unwrap();
+        assert!(content.starts_with(b"100644 foo.txt\0"));
+
+        let nested = vec![create_blob_entry(FileMode::Regular, &blob_hash, "a/b.txt")];
+        assert!(create_tree(&git_dir, nested).is_err());
+
+        fs::remove_dir_all(git_dir).unwrap();
+    }
+
+    #[test]
+    fn test_tree_round_trip() {
+        let mut raw = Vec::new();
+        for (mode, name, byte) in [
+            ("100644", "README.md", 1u8),
+            ("100755", "build.sh", 2),
+            ("120000", "link", 3),
+            ("40000", "src", 4),
+            ("160000", "vendor", 5),
+        ] {
+            raw.extend_from_slice(format!("{} {}\0", mode, name).as_bytes());
+            raw.extend_from_slice(&[byte; 20]);
+        }
+
+        let tree = Tree::parse(&raw).unwrap();
+        let modes: Vec<FileMode> = tree.entries.iter().map(|e| e.mode).collect();
+        assert_eq!(
+            modes,
+            vec![
+                FileMode::Regular,
+                FileMode::Executable,
+                FileMode::Symlink,
+                FileMode::Tree,
+                FileMode::Gitlink
+            ]
+        );
+        assert_eq!(tree.entries[3].obj_type(), "tree");
+        assert_eq!(tree.entries[4].hash, ObjectId::from([5u8; 20]));
+
+        assert_eq!(tree.serialize().unwrap(), raw);
+        assert!(Tree::parse(&raw[..raw.len() - 1]).is_err());
+        assert!(Tree::parse(b"100600 bad\0aaaaaaaaaaaaaaaaaaaa").is_err());
+    }
+
+    #[test]
+    fn test_legacy_modes_keep_their_bytes() {
+        let mut raw = Vec::new();
+        for (mode, name) in [("100664", "shared.txt"), ("040000", "src")] {
+            raw.extend_from_slice(format!("{} {}\0", mode, name).as_bytes());
+            raw.extend_from_slice(&[7u8; 20]);
+        }
+
+        let tree = Tree::parse(&raw).unwrap();
+        assert_eq!(tree.entries[0].mode, FileMode::Regular);
+        assert_eq!(tree.entries[1].mode, FileMode::Tree);
+        assert_eq!(tree.entries[1].legacy_mode.as_deref(), Some("040000"));
+        // Same bytes, so the same id as the tree that was read.
+        assert_eq!(tree.serialize().unwrap(), raw);
     }
 }
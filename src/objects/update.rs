@@ -1,289 +1,199 @@
-use std::io::Result;
+use crate::error::{Error, Result};
+use crate::objects::id::ObjectId;
+use crate::repository::refs::{self, Expected, RefStore};
 use std::path::Path;
 
-/// Update a Git reference to point to a specific commit
-pub fn update_reference(ref_path: &str, commit_hash: &str) -> Result<()> {
+/// Update a Git reference to point to a specific commit. Symbolic refs are
+/// followed, so updating `HEAD` moves the current branch. `message` says why in
+/// the reflog.
+///
+/// This and the other functions changing refs return warnings about reflogs
+/// that could not be written, for the caller to report.
+pub fn update_reference(
+    git_dir: &Path,
+    ref_path: &str,
+    commit_hash: &ObjectId,
+    message: &str,
+) -> Result<Vec<String>> {
+    write_reference(git_dir, ref_path, commit_hash, Expected::Any, message)
+}
+
+/// Like [`update_reference`], but only if the ref still holds what `expected`
+/// says once it is locked.
+fn write_reference(
+    git_dir: &Path,
+    ref_path: &str,
+    commit_hash: &ObjectId,
+    expected: Expected,
+    message: &str,
+) -> Result<Vec<String>> {
     // Validate inputs
     if ref_path.is_empty() {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::InvalidInput,
-            "Reference path cannot be empty",
-        ));
-    }
-
-    if commit_hash.len() != 40 {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::InvalidData,
-            format!(
-                "Invalid commit hash length: expected 40 characters, got {}",
-                commit_hash.len()
-            ),
-        ));
-    }
-
-    // Validate commit hash format (hexadecimal)
-    if !commit_hash.chars().all(|c| c.is_ascii_hexdigit()) {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::InvalidData,
-            "Commit hash must contain only hexadecimal characters",
-        ));
-    }
-
-    // Ensure .git directory exists
-    if !Path::new(".xit").exists() {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            ".xit directory not found. Are you in a Xit repository?",
+        return Err(Error::InvalidRef(
+            "Reference path cannot be empty".to_string(),
         ));
     }
 
-    let path = format!(".git/{}", ref_path);
-
-    // Create parent directories if they don't exist
-    if let Some(parent) = Path::new(&path).parent() {
-        std::fs::create_dir_all(parent)?;
+    // Ensure .xit directory exists
+    if !git_dir.exists() {
+        return Err(Error::NotARepository(git_dir.to_path_buf()));
     }
 
-    // Write the reference with a newline (Git standard)
-    std::fs::write(&path, format!("{}\n", commit_hash))?;
-
-    Ok(())
+    let mut transaction = RefStore::new(git_dir).transaction();
+    transaction.update(ref_path, *commit_hash, expected, message);
+    transaction.commit()
 }
 
-/// Update HEAD reference to point to a specific commit
-pub fn update_head(commit_hash: &str) -> Result<()> {
-    update_reference("HEAD", commit_hash)
+/// Update HEAD to point to a specific commit: the current branch moves, or
+/// HEAD itself when it is detached.
+pub fn update_head(git_dir: &Path, commit_hash: &ObjectId, message: &str) -> Result<Vec<String>> {
+    update_reference(git_dir, "HEAD", commit_hash, message)
 }
 
 /// Update a branch reference
-pub fn update_branch(branch_name: &str, commit_hash: &str) -> Result<()> {
-    // Validate branch name
-    if branch_name.is_empty() {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::InvalidInput,
-            "Branch name cannot be empty",
-        ));
-    }
+pub fn update_branch(
+    git_dir: &Path,
+    branch_name: &str,
+    commit_hash: &ObjectId,
+    message: &str,
+) -> Result<Vec<String>> {
+    let ref_path = branch_ref_path(branch_name)?;
+    update_reference(git_dir, &ref_path, commit_hash, message)
+}
 
-    // Check for invalid characters in branch name
-    let invalid_chars = ['/', '\\', ':', '*', '?', '"', '<', '>', '|', ' '];
-    if branch_name.chars().any(|c| invalid_chars.contains(&c)) {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::InvalidInput,
-            format!("Branch name '{}' contains invalid characters", branch_name),
-        ));
-    }
+/// The full name of a branch, checking that it is a valid one.
+fn branch_ref_path(branch_name: &str) -> Result<String> {
+    refs::check_short_name("branch", branch_name)?;
 
     // Check for reserved names
     let reserved_names = ["HEAD", "ORIGIN_HEAD", "FETCH_HEAD", "MERGE_HEAD"];
     if reserved_names.contains(&branch_name) {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::InvalidInput,
-            format!("'{}' is a reserved reference name", branch_name),
-        ));
+        return Err(Error::InvalidRef(format!(
+            "'{}' is a reserved reference name",
+            branch_name
+        )));
     }
 
-    let ref_path = format!("refs/heads/{}", branch_name);
-    update_reference(&ref_path, commit_hash)
+    Ok(format!("refs/heads/{}", branch_name))
 }
 
 /// Update a tag reference
-pub fn update_tag(tag_name: &str, commit_hash: &str) -> Result<()> {
-    // Validate tag name
-    if tag_name.is_empty() {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::InvalidInput,
-            "Tag name cannot be empty",
-        ));
-    }
-
-    // Check for invalid characters in tag name
-    let invalid_chars = ['/', '\\', ':', '*', '?', '"', '<', '>', '|', ' '];
-    if tag_name.chars().any(|c| invalid_chars.contains(&c)) {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::InvalidInput,
-            format!("Tag name '{}' contains invalid characters", tag_name),
-        ));
-    }
+pub fn update_tag(git_dir: &Path, tag_name: &str, commit_hash: &ObjectId) -> Result<Vec<String>> {
+    refs::check_short_name("tag", tag_name)?;
 
     let ref_path = format!("refs/tags/{}", tag_name);
-    update_reference(&ref_path, commit_hash)
+    update_reference(git_dir, &ref_path, commit_hash, "")
 }
 
-/// Create a new branch pointing to a commit
-pub fn create_branch(branch_name: &str, commit_hash: &str) -> Result<()> {
+/// Create a new branch pointing to a commit. The branch must not exist, also
+/// once it is locked, so that concurrent creations cannot overwrite each other.
+pub fn create_branch(
+    git_dir: &Path,
+    branch_name: &str,
+    commit_hash: &ObjectId,
+    message: &str,
+) -> Result<Vec<String>> {
+    let ref_path = branch_ref_path(branch_name)?;
     // Check if branch already exists
-    let ref_path = format!("refs/heads/{}", branch_name);
-    let full_path = format!(".xit/{}", ref_path);
-
-    if Path::new(&full_path).exists() {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::AlreadyExists,
-            format!("Branch '{}' already exists", branch_name),
-        ));
+    if reference_exists(git_dir, &ref_path) {
+        return Err(Error::Conflict(format!(
+            "Branch '{}' already exists",
+            branch_name
+        )));
     }
 
-    update_branch(branch_name, commit_hash)
+    write_reference(git_dir, &ref_path, commit_hash, Expected::Missing, message)
 }
 
-/// Create a new tag pointing to a commit
-pub fn create_tag(tag_name: &str, commit_hash: &str) -> Result<()> {
-    // Check if tag already exists
+/// Create a new tag pointing to a commit, which must not exist either.
+pub fn create_tag(git_dir: &Path, tag_name: &str, commit_hash: &ObjectId) -> Result<Vec<String>> {
+    refs::check_short_name("tag", tag_name)?;
     let ref_path = format!("refs/tags/{}", tag_name);
-    let full_path = format!(".xit/{}", ref_path);
-
-    if Path::new(&full_path).exists() {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::AlreadyExists,
-            format!("Tag '{}' already exists", tag_name),
-        ));
+    // Check if tag already exists
+    if reference_exists(git_dir, &ref_path) {
+        return Err(Error::Conflict(format!(
+            "Tag '{}' already exists",
+            tag_name
+        )));
     }
 
-    update_tag(tag_name, commit_hash)
+    write_reference(git_dir, &ref_path, commit_hash, Expected::Missing, "")
 }
 
-/// Delete a branch reference
-pub fn delete_branch(branch_name: &str) -> Result<()> {
-    if branch_name.is_empty() {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::InvalidInput,
-            "Branch name cannot be empty",
-        ));
-    }
-
-    let ref_path = format!("refs/heads/{}", branch_name);
-    let full_path = format!(".xit/{}", ref_path);
-
-    if !Path::new(&full_path).exists() {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            format!("Branch '{}' does not exist", branch_name),
-        ));
-    }
-
-    std::fs::remove_file(&full_path)?;
-    Ok(())
+/// Delete a branch reference, provided it still holds what `expected` says.
+pub fn delete_branch(git_dir: &Path, branch_name: &str, expected: Expected) -> Result<Vec<String>> {
+    refs::check_short_name("branch", branch_name)?;
+    delete_reference(
+        git_dir,
+        "Branch",
+        branch_name,
+        &format!("refs/heads/{}", branch_name),
+        expected,
+    )
 }
 
-/// Delete a tag reference
-pub fn delete_tag(tag_name: &str) -> Result<()> {
-    if tag_name.is_empty() {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::InvalidInput,
-            "Tag name cannot be empty",
-        ));
-    }
-
-    let ref_path = format!("refs/tags/{}", tag_name);
-    let full_path = format!(".xit/{}", ref_path);
+/// Delete a tag reference, provided it still holds what `expected` says.
+pub fn delete_tag(git_dir: &Path, tag_name: &str, expected: Expected) -> Result<Vec<String>> {
+    refs::check_short_name("tag", tag_name)?;
+    delete_reference(
+        git_dir,
+        "Tag",
+        tag_name,
+        &format!("refs/tags/{}", tag_name),
+        expected,
+    )
+}
 
-    if !Path::new(&full_path).exists() {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            format!("Tag '{}' does not exist", tag_name),
-        ));
+fn delete_reference(
+    git_dir: &Path,
+    kind: &str,
+    name: &str,
+    ref_path: &str,
+    expected: Expected,
+) -> Result<Vec<String>> {
+    if !reference_exists(git_dir, ref_path) {
+        return Err(Error::InvalidRef(format!(
+            "{} '{}' does not exist",
+            kind, name
+        )));
     }
-
-    std::fs::remove_file(&full_path)?;
-    Ok(())
+    let mut transaction = RefStore::new(git_dir).transaction();
+    transaction.delete(ref_path, expected, "");
+    transaction.commit()
 }
 
 /// Read a reference and return the commit hash it points to
-pub fn read_reference(ref_path: &str) -> Result<String> {
+pub fn read_reference(git_dir: &Path, ref_path: &str) -> Result<ObjectId> {
     if ref_path.is_empty() {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::InvalidInput,
-            "Reference path cannot be empty",
-        ));
-    }
-
-    let path = format!(".git/{}", ref_path);
-
-    if !Path::new(&path).exists() {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            format!("Reference '{}' does not exist", ref_path),
-        ));
-    }
-
-    let content = std::fs::read_to_string(&path)?;
-    let commit_hash = content.trim_end_matches('\n');
-
-    // Validate the read hash
-    if commit_hash.len() != 40 {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::InvalidData,
-            format!(
-                "Invalid commit hash in reference: expected 40 characters, got {}",
-                commit_hash.len()
-            ),
-        ));
-    }
-
-    if !commit_hash.chars().all(|c| c.is_ascii_hexdigit()) {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::InvalidData,
-            "Invalid commit hash format in reference",
+        return Err(Error::InvalidRef(
+            "Reference path cannot be empty".to_string(),
         ));
     }
 
-    Ok(commit_hash.to_string())
+    refs::get_commit_hash(git_dir, ref_path)
 }
 
 /// Check if a reference exists
-pub fn reference_exists(ref_path: &str) -> bool {
-    if ref_path.is_empty() {
-        return false;
-    }
-
-    let path = format!(".git/{}", ref_path);
-    Path::new(&path).exists()
+pub fn reference_exists(git_dir: &Path, ref_path: &str) -> bool {
+    RefStore::new(git_dir).exists(ref_path).unwrap_or(false)
 }
 
-/// List all branch references
-pub fn list_branches() -> Result<Vec<String>> {
-    let heads_dir = ".git/refs/heads";
-
-    if !Path::new(heads_dir).exists() {
-        return Ok(Vec::new());
-    }
-
-    let mut branches = Vec::new();
-
-    for entry in std::fs::read_dir(heads_dir)? {
-        let entry = entry?;
-        if entry.file_type()?.is_file() {
-            if let Some(name) = entry.file_name().to_str() {
-                branches.push(name.to_string());
-            }
-        }
-    }
-
-    branches.sort();
-    Ok(branches)
+/// List all branch references, including nested ones such as `feature/foo`
+pub fn list_branches(git_dir: &Path) -> Result<Vec<String>> {
+    list_names(git_dir, "refs/heads/")
 }
 
 /// List all tag references
-pub fn list_tags() -> Result<Vec<String>> {
-    let tags_dir = ".git/refs/tags";
-
-    if !Path::new(tags_dir).exists() {
-        return Ok(Vec::new());
-    }
-
-    let mut tags = Vec::new();
-
-    for entry in std::fs::read_dir(tags_dir)? {
-        let entry = entry?;
-        if entry.file_type()?.is_file() {
-            if let Some(name) = entry.file_name().to_str() {
-                tags.push(name.to_string());
-            }
-        }
-    }
+pub fn list_tags(git_dir: &Path) -> Result<Vec<String>> {
+    list_names(git_dir, "refs/tags/")
+}
 
-    tags.sort();
-    Ok(tags)
+fn list_names(git_dir: &Path, prefix: &str) -> Result<Vec<String>> {
+    Ok(RefStore::new(git_dir)
+        .list(prefix)?
+        .into_iter()
+        .map(|(name, _)| name[prefix.len()..].to_string())
+        .collect())
 }
 
 #[cfg(test)]
@@ -293,18 +203,59 @@ mod tests {
 
     #[test]
     fn test_update_reference() {
-        let git_dir = ".git";
-        fs::create_dir_all(git_dir).unwrap();
+        let git_dir = std::env::temp_dir().join("xit_test_update_reference");
+        let _ = fs::remove_dir_all(&git_dir);
+        fs::create_dir_all(&git_dir).unwrap();
 
         let ref_path = "refs/heads/test-branch";
-        let commit_hash = "a1b2c3d4e5f6a1b2c3d4e5f6a1b2c3d4e5f6a1b2";
+        let commit_hash: ObjectId = "a1b2c3d4e5f6a1b2c3d4e5f6a1b2c3d4e5f6a1b2".parse().unwrap();
 
-        update_reference(ref_path, commit_hash).unwrap();
+        update_reference(
+            &git_dir,
+            ref_path,
+            &commit_hash,
+            "branch: Created from HEAD",
+        )
+        .unwrap();
 
-        let content = fs::read_to_string(format!("{}/{}", git_dir, ref_path)).unwrap();
+        let content = fs::read_to_string(git_dir.join(ref_path)).unwrap();
         assert_eq!(content, format!("{}\n", commit_hash));
+        let log = fs::read_to_string(git_dir.join("logs").join(ref_path)).unwrap();
+        assert!(log.starts_with(&format!("{} {} ", "0".repeat(40), commit_hash)));
+        assert!(log.ends_with("\tbranch: Created from HEAD\n"));
 
         // Clean up created files
         fs::remove_dir_all(git_dir).unwrap();
     }
+
+    #[test]
+    fn test_create_and_delete_check_the_old_value() {
+        let git_dir = std::env::temp_dir().join("xit_test_update_expected");
+        let _ = fs::remove_dir_all(&git_dir);
+        fs::create_dir_all(&git_dir).unwrap();
+        let one: ObjectId = "a1b2c3d4e5f6a1b2c3d4e5f6a1b2c3d4e5f6a1b2".parse().unwrap();
+        let two: ObjectId = "b1b2c3d4e5f6a1b2c3d4e5f6a1b2c3d4e5f6a1b2".parse().unwrap();
+
+        create_branch(&git_dir, "topic", &one, "").unwrap();
+        assert!(matches!(
+            create_branch(&git_dir, "topic", &two, ""),
+            Err(Error::Conflict(_))
+        ));
+        // Created by someone else between the check and the write.
+        assert!(matches!(
+            write_reference(&git_dir, "refs/heads/topic", &two, Expected::Missing, ""),
+            Err(Error::Conflict(_))
+        ));
+
+        // Moved by someone else since it was read.
+        assert!(matches!(
+            delete_branch(&git_dir, "topic", Expected::Value(two)),
+            Err(Error::Conflict(_))
+        ));
+        assert_eq!(read_reference(&git_dir, "refs/heads/topic").unwrap(), one);
+        delete_branch(&git_dir, "topic", Expected::Value(one)).unwrap();
+        assert!(!reference_exists(&git_dir, "refs/heads/topic"));
+
+        fs::remove_dir_all(git_dir).unwrap();
+    }
 }
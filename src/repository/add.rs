@@ -1,79 +1,173 @@
+use crate::error::{Error, Result};
 use crate::objects::blob;
-use std::collections::HashMap;
+use crate::repository::index::{Index, IndexEntry};
+use crate::repository::repo::Repository;
+use crate::repository::utils;
+use crate::repository::worktree::{self, WorktreeFile};
 use std::fs;
-use std::io::{self, BufRead, Write};
-use std::path::Path;
-/// if suppose it is previosuly added how can i update the blob hash , ideally it should updaate the blob hash also ????/
+
 /// Handles the `xit add` command.
-pub fn add(file_path_str: &str) -> io::Result<()> {
-    let git_dir = ".xit";
-    let file_path = Path::new(file_path_str);
+///
+/// `file_path_str` is interpreted relative to the repository's working tree.
+/// A directory adds every file below it that is not ignored. Re-adding a file
+/// simply replaces its blob hash in the index. Tracked files that are gone
+/// from the working tree, the one named or those below the directory, are
+/// removed from the index, which stages their deletion.
+pub fn add(repo: &Repository, file_path_str: &str) -> Result<()> {
+    let file_path = repo.work_tree().join(file_path_str);
+    // The index stores paths relative to the working tree.
+    let relative_path = file_path
+        .strip_prefix(repo.work_tree())
+        .map_err(|_| Error::InvalidInput(format!("'{}' is outside repository", file_path_str)))
+        .and_then(utils::path_to_slash)?;
 
     // 1. --- Validation ---
-    // Ensure we are in a xit repository
-    if !Path::new(git_dir).is_dir() {
-        return Err(io::Error::new(
-            io::ErrorKind::NotFound,
-            "Not a xit repository (or any of the parent directories): .git",
-        ));
-    }
-
-    // Ensure the file to be added exists
-    if !file_path.is_file() {
-        return Err(io::Error::new(
-            io::ErrorKind::NotFound,
-            format!("pathspec '{}' did not match any files", file_path_str),
-        ));
-    }
+    // Find the files to be added. The metadata is taken before the content is
+    // read, so that a write racing with this one leaves stat data that no
+    // longer matches the file.
+    let threads = worktree::configured_threads(repo)?;
+    // A symbolic link is added as a link, whatever it points to.
+    let metadata = fs::symlink_metadata(&file_path).ok();
+    let exists = metadata.is_some();
+    let files = match metadata {
+        Some(metadata) if metadata.is_dir() => {
+            let ignore_patterns = worktree::read_ignore_file(&repo.work_tree().join(".xitignore"))?;
+            worktree::walk(repo.work_tree(), &file_path, &ignore_patterns, threads)?
+        }
+        Some(metadata) => vec![WorktreeFile {
+            path: relative_path.clone(),
+            metadata,
+        }],
+        None => Vec::new(),
+    };
 
     // 2. --- Blob Creation ---
-    // Read the file's content and create a blob object.
-    // Your existing `create_blob` function already handles hashing, compression,
-    // and writing the object to the .xit/objects directory.
-    let file_content = fs::read(file_path)?;
-    let blob_hash = blob::create_blob(&file_content)?;
+    // Read each file's content (a link's target for a symbolic link) and
+    // create a blob object, on several threads. `create_blob` handles hashing,
+    // compression, and writing the object to the .xit/objects directory.
+    let entries = worktree::map_parallel(&files, threads, |file| {
+        let file_content = worktree::read_content(&repo.work_tree().join(&file.path))?;
+        let blob_hash = blob::create_blob(repo.git_dir(), &file_content)?;
+        Ok(IndexEntry::from_metadata(
+            &file.path,
+            blob_hash,
+            &file.metadata,
+        ))
+    })
+    .into_iter()
+    .collect::<Result<Vec<_>>>()?;
 
     // 3. --- Index Update ---
-    // Now, we update the index to stage this file for the next commit.
-    update_index(file_path_str, &blob_hash)?;
+    // Now, we update the index to stage these files for the next commit.
+    let (mut index, lock) = Index::lock(&repo.index_path())?;
+    let deleted: Vec<String> = index
+        .entries()
+        .iter()
+        .filter(|entry| is_below(&entry.path, &relative_path))
+        .filter(|entry| fs::symlink_metadata(repo.work_tree().join(&entry.path)).is_err())
+        .map(|entry| entry.path.clone())
+        .collect();
+    if !exists && deleted.is_empty() {
+        return Err(Error::InvalidInput(format!(
+            "pathspec '{}' did not match any files",
+            file_path_str
+        )));
+    }
+    for path in &deleted {
+        index.remove(path);
+    }
+    index.add_all(entries);
+    index.commit(lock)
+}
 
-    //    println!("Added '{}' to the staging area.", file_path_str);
-    Ok(())
+/// Whether `path` is `dir` or lies below it; every path lies below "".
+fn is_below(path: &str, dir: &str) -> bool {
+    dir.is_empty()
+        || path
+            .strip_prefix(dir)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
 }
 
-/// Updates the .xit/index file with the new file path and its blob hash.
-fn update_index(file_path: &str, blob_hash: &str) -> io::Result<()> {
-    let git_dir = ".xit";
-    let index_path = Path::new(git_dir).join("index");
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::object;
+    use crate::objects::read as object_read;
+    use crate::objects::tree::FileMode;
+    use crate::repository::test_utils::test_repo;
 
-    // Our index is a simple text file. We can read it into a HashMap
-    // for easy lookup and modification.
-    let mut index_entries: HashMap<String, String> = HashMap::new();
+    #[test]
+    fn test_add_stages_deletions() {
+        let (repo, temp_dir) = test_repo("xit_test_add_deletions");
+        fs::create_dir_all(temp_dir.join("src")).unwrap();
+        let committed_files = |commit| {
+            let tree = object_read::get_commit_tree_hash(repo.git_dir(), &commit).unwrap();
+            let mut files: Vec<String> = object_read::list_files_in_tree(repo.git_dir(), &tree)
+                .unwrap()
+                .into_keys()
+                .collect();
+            files.sort();
+            files
+        };
 
-    // If the index file already exists, read its contents.
-    if index_path.exists() {
-        let file = fs::File::open(&index_path)?;
-        for line in io::BufReader::new(file).lines() {
-            let line = line?;
-            let parts: Vec<&str> = line.splitn(2, ' ').collect();
-            if parts.len() == 2 {
-                // The format is "hash path", so we store it as (path, hash)
-                index_entries.insert(parts[1].to_string(), parts[0].to_string());
-            }
+        for file in ["gone.txt", "kept.txt", "src/a.rs", "src/b.rs"] {
+            fs::write(temp_dir.join(file), file).unwrap();
         }
-    }
+        add(&repo, ".").unwrap();
+        repo.commit("First").unwrap();
+
+        // A deleted file named on its own.
+        fs::remove_file(temp_dir.join("gone.txt")).unwrap();
+        add(&repo, "gone.txt").unwrap();
+        let second = repo.commit("Remove gone.txt").unwrap().id;
+        assert_eq!(
+            committed_files(second),
+            ["kept.txt", "src/a.rs", "src/b.rs"]
+        );
+        assert!(matches!(
+            add(&repo, "gone.txt"),
+            Err(Error::InvalidInput(_))
+        ));
 
-    // Add or update the entry for the current file.
-    // The key is the file path, the value is the blob hash.
-    index_entries.insert(file_path.to_string(), blob_hash.to_string());
+        // Deleted files below an added directory, even a directory now gone.
+        fs::remove_file(temp_dir.join("src/a.rs")).unwrap();
+        add(&repo, ".").unwrap();
+        let third = repo.commit("Remove src/a.rs").unwrap().id;
+        assert_eq!(committed_files(third), ["kept.txt", "src/b.rs"]);
+        fs::remove_dir_all(temp_dir.join("src")).unwrap();
+        add(&repo, "src").unwrap();
+        let fourth = repo.commit("Remove src").unwrap().id;
+        assert_eq!(committed_files(fourth), ["kept.txt"]);
 
-    // Write the updated entries back to the index file, overwriting it.
-    let mut file = fs::File::create(&index_path)?;
-    for (path, hash) in &index_entries {
-        // We will use a simple format: <hash> <path>
+        // Deleting every file empties the index, which can still be committed.
+        fs::remove_file(temp_dir.join("kept.txt")).unwrap();
+        add(&repo, ".").unwrap();
+        let fifth = repo.commit("Remove everything").unwrap().id;
+        assert!(committed_files(fifth).is_empty());
+        assert!(matches!(repo.commit("Again"), Err(Error::InvalidInput(_))));
 
-        writeln!(file, "{} {}", hash, path)?;
+        fs::remove_dir_all(temp_dir).unwrap();
     }
 
-    Ok(())
+    #[cfg(unix)]
+    #[test]
+    fn test_add_symlink_stores_its_target() {
+        let (repo, temp_dir) = test_repo("xit_test_add_symlink");
+        fs::create_dir_all(temp_dir.join("dir")).unwrap();
+        fs::write(temp_dir.join("dir/file"), "content").unwrap();
+        std::os::unix::fs::symlink("dir/file", temp_dir.join("link")).unwrap();
+        std::os::unix::fs::symlink("missing", temp_dir.join("dangling")).unwrap();
+
+        add(&repo, "link").unwrap();
+        add(&repo, "dangling").unwrap();
+        let index = Index::read(&repo.index_path()).unwrap();
+        let link = index.get("link").unwrap();
+        assert_eq!(link.mode, FileMode::Symlink);
+        assert_eq!(link.id, object::hash_object("blob", b"dir/file"));
+        let dangling = index.get("dangling").unwrap();
+        assert_eq!(dangling.mode, FileMode::Symlink);
+        assert_eq!(dangling.id, object::hash_object("blob", b"missing"));
+
+        fs::remove_dir_all(temp_dir).unwrap();
+    }
 }
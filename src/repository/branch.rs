@@ -0,0 +1,210 @@
+//! Creating, listing, renaming and deleting branches (`xit branch`).
+
+use crate::error::{Error, Result};
+use crate::objects::id::ObjectId;
+use crate::objects::update;
+use crate::repository::refs::{self, Expected, RefChange};
+use crate::repository::repo::Repository;
+use crate::repository::{rev_parse, revwalk};
+
+/// A branch and the commit at its tip.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Branch {
+    pub name: String,
+    pub id: ObjectId,
+}
+
+/// All branches, sorted by name.
+pub fn list(repo: &Repository) -> Result<Vec<Branch>> {
+    let git_dir = repo.git_dir();
+    update::list_branches(git_dir)?
+        .into_iter()
+        .map(|name| {
+            let id = update::read_reference(git_dir, &format!("refs/heads/{}", name))?;
+            Ok(Branch { name, id })
+        })
+        .collect()
+}
+
+/// Creates a branch at the commit named by `start` (see [`rev_parse::resolve`]).
+/// With `force` an existing branch is moved instead, unless it is checked out.
+pub fn create(repo: &Repository, name: &str, start: &str, force: bool) -> Result<RefChange> {
+    let git_dir = repo.git_dir();
+    let id = rev_parse::resolve_commit(repo, start)?;
+    let warnings = if force && update::reference_exists(git_dir, &format!("refs/heads/{}", name)) {
+        if is_current(repo, name)? {
+            return Err(Error::Conflict(format!(
+                "Cannot force update the current branch '{}'",
+                name
+            )));
+        }
+        update::update_branch(git_dir, name, &id, &format!("branch: Reset to {}", start))?
+    } else {
+        update::create_branch(
+            git_dir,
+            name,
+            &id,
+            &format!("branch: Created from {}", start),
+        )?
+    };
+    Ok(RefChange { id, warnings })
+}
+
+/// Deletes a branch and returns the commit it pointed to.
+///
+/// Unless `force` is set, the branch must be merged into HEAD so that no
+/// commit becomes unreachable. The current branch is never deleted.
+pub fn delete(repo: &Repository, name: &str, force: bool) -> Result<RefChange> {
+    let git_dir = repo.git_dir();
+    let id = update::read_reference(git_dir, &format!("refs/heads/{}", name))
+        .map_err(|_| Error::InvalidRef(format!("Branch '{}' not found", name)))?;
+    if is_current(repo, name)? {
+        return Err(Error::Conflict(format!(
+            "Cannot delete branch '{}' checked out at '{}'",
+            name,
+            repo.work_tree().display()
+        )));
+    }
+    if !force {
+        let merged = match rev_parse::resolve_commit(repo, "HEAD") {
+            Ok(head) => revwalk::is_ancestor(repo, id, head)?,
+            Err(_) => false,
+        };
+        if !merged {
+            return Err(Error::Conflict(format!(
+                "The branch '{}' is not fully merged.\n\
+                 If you are sure you want to delete it, run 'xit branch -D {}'.",
+                name, name
+            )));
+        }
+    }
+    // Only the commit that was found merged may be deleted.
+    let warnings = update::delete_branch(git_dir, name, Expected::Value(id))?;
+    Ok(RefChange { id, warnings })
+}
+
+/// Renames a branch. HEAD follows the branch if it was checked out, which also
+/// works before its first commit. With `force` an existing `new` is replaced.
+/// Returns the warnings of the ref update.
+pub fn rename(repo: &Repository, old: &str, new: &str, force: bool) -> Result<Vec<String>> {
+    let git_dir = repo.git_dir();
+    let current = is_current(repo, old)?;
+    let id = match update::read_reference(git_dir, &format!("refs/heads/{}", old)) {
+        Ok(id) => Some(id),
+        Err(_) if current => None,
+        Err(_) => return Err(Error::InvalidRef(format!("Branch '{}' not found", old))),
+    };
+
+    let mut warnings = Vec::new();
+    if old != new {
+        if update::reference_exists(git_dir, &format!("refs/heads/{}", new)) {
+            if !force {
+                return Err(Error::Conflict(format!(
+                    "A branch named '{}' already exists",
+                    new
+                )));
+            }
+            if is_current(repo, new)? {
+                return Err(Error::Conflict(format!(
+                    "Cannot overwrite the current branch '{}'",
+                    new
+                )));
+            }
+        }
+        refs::check_short_name("branch", new)?;
+        if id.is_some() {
+            let (old_ref, new_ref) = (format!("refs/heads/{}", old), format!("refs/heads/{}", new));
+            let reason = format!("Branch: renamed {} to {}", old_ref, new_ref);
+            warnings = repo.refs().rename(&old_ref, &new_ref, &reason)?;
+        }
+    }
+    if current {
+        repo.refs()
+            .set_symbolic("HEAD", &format!("refs/heads/{}", new))?;
+    }
+    Ok(warnings)
+}
+
+fn is_current(repo: &Repository, name: &str) -> Result<bool> {
+    Ok(refs::current_branch(repo.git_dir())?.as_deref() == Some(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::test_utils::test_repo;
+    use std::fs;
+
+    #[test]
+    fn test_branch_lifecycle() {
+        let (repo, temp_dir) = test_repo("xit_test_branch");
+
+        // Renaming works before the first commit.
+        rename(&repo, "main", "trunk", false).unwrap();
+        assert_eq!(
+            refs::current_branch(repo.git_dir()).unwrap().as_deref(),
+            Some("trunk")
+        );
+        assert!(create(&repo, "topic", "HEAD", false).is_err());
+
+        fs::write(temp_dir.join("a.txt"), "one").unwrap();
+        repo.add("a.txt").unwrap();
+        let first = repo.commit("First").unwrap().id;
+        assert_eq!(create(&repo, "topic", "HEAD", false).unwrap().id, first);
+        assert!(create(&repo, "topic", "HEAD", false).is_err());
+
+        fs::write(temp_dir.join("a.txt"), "two").unwrap();
+        repo.add("a.txt").unwrap();
+        let second = repo.commit("Second").unwrap().id;
+        create(&repo, "ahead", "HEAD", false).unwrap();
+        rename(&repo, "trunk", "main", false).unwrap();
+        assert_eq!(
+            list(&repo).unwrap(),
+            vec![
+                Branch {
+                    name: "ahead".to_string(),
+                    id: second
+                },
+                Branch {
+                    name: "main".to_string(),
+                    id: second
+                },
+                Branch {
+                    name: "topic".to_string(),
+                    id: first
+                },
+            ]
+        );
+
+        // `topic` is behind HEAD, so deleting it loses nothing.
+        assert_eq!(delete(&repo, "topic", false).unwrap().id, first);
+        assert!(delete(&repo, "main", true).is_err());
+        assert!(delete(&repo, "missing", true).is_err());
+
+        // `ahead` gets a commit HEAD does not have.
+        fs::write(temp_dir.join("a.txt"), "three").unwrap();
+        repo.add("a.txt").unwrap();
+        let third = repo.commit("Third").unwrap().id;
+        update::update_branch(repo.git_dir(), "main", &second, "reset").unwrap();
+        create(&repo, "ahead", &third.to_hex(), true).unwrap();
+        assert!(matches!(
+            delete(&repo, "ahead", false),
+            Err(Error::Conflict(_))
+        ));
+        create(&repo, "old", "main~1", false).unwrap();
+        assert!(rename(&repo, "old", "ahead", false).is_err());
+        rename(&repo, "old", "ahead", true).unwrap();
+        assert_eq!(delete(&repo, "ahead", false).unwrap().id, first);
+
+        // Branch names may be nested like directories.
+        create(&repo, "feature/foo", "HEAD", false).unwrap();
+        assert!(create(&repo, "feature", "HEAD", false).is_err());
+        assert!(create(&repo, "feature/bad..name", "HEAD", false).is_err());
+        let names: Vec<String> = list(&repo).unwrap().into_iter().map(|b| b.name).collect();
+        assert_eq!(names, ["feature/foo", "main"]);
+        delete(&repo, "feature/foo", false).unwrap();
+        assert!(!repo.git_dir().join("refs/heads/feature").exists());
+
+        fs::remove_dir_all(temp_dir).unwrap();
+    }
+}
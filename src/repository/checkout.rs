@@ -0,0 +1,449 @@
+//! Moving HEAD to another branch or commit and updating the working tree and
+//! index to match (`xit switch` and `xit checkout`).
+
+use crate::error::{Error, Result};
+use crate::objects::id::ObjectId;
+use crate::objects::read as object_read;
+use crate::objects::tree::FileMode;
+use crate::objects::update;
+use crate::repository::refs::{Expected, RefValue};
+use crate::repository::repo::Repository;
+use crate::repository::{diff, index, status, utils};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::Path;
+
+/// Where HEAD points after a checkout.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    /// HEAD becomes `ref: refs/heads/<name>`.
+    Branch(String),
+    /// HEAD holds the commit id itself.
+    Detached(ObjectId),
+}
+
+/// Checks out `target`: files that differ between the HEAD commit and the
+/// target commit are rewritten in the working tree and the index, then HEAD is
+/// moved. Local changes to other files are kept, as git does.
+///
+/// Unless `force` is set, nothing is touched when a file that has to change has
+/// staged or unstaged changes, or exists untracked in the working tree, or when
+/// untracked files are in the way of a file of the target (a file where the
+/// target has a directory, or the other way round). With `force` those changes
+/// and files are thrown away and the index matches the target.
+///
+/// Returns the warnings of the ref update that moved HEAD.
+pub fn checkout(repo: &Repository, target: &Target, force: bool) -> Result<Vec<String>> {
+    let git_dir = repo.git_dir();
+    let store = repo.refs();
+    // The commit the working tree is compared against, which HEAD must still
+    // point at when it is moved.
+    let old_head = store.resolve("HEAD")?;
+    let commit = match target {
+        Target::Branch(name) => update::read_reference(git_dir, &format!("refs/heads/{}", name))
+            .map_err(|_| Error::InvalidRef(format!("Branch '{}' not found", name)))?,
+        Target::Detached(id) => *id,
+    };
+    let target_tree = object_read::get_commit_tree_hash(git_dir, &commit)?;
+    let target_entries = object_read::list_files_in_tree(git_dir, &target_tree)?;
+    let head_entries = status::get_head_tree_entries(git_dir)?;
+    let mut index_entries = diff::index_or_head(repo)?;
+
+    let changing: BTreeSet<&String> = head_entries
+        .keys()
+        .chain(target_entries.keys())
+        .filter(|path| head_entries.get(*path) != target_entries.get(*path))
+        .collect();
+
+    let removing: BTreeSet<&str> = changing
+        .iter()
+        .filter(|path| !target_entries.contains_key(**path))
+        .map(|path| path.as_str())
+        .collect();
+    // Everything is checked before the first file is written, so that a
+    // refused checkout leaves the working tree as it was.
+    let obstructions = find_obstructions(repo.work_tree(), &changing, &removing)?;
+    if !force {
+        check_overwrites(repo, &changing, &head_entries, &index_entries)?;
+        if !obstructions.is_empty() {
+            return Err(untracked_error(&obstructions));
+        }
+    } else {
+        for path in &obstructions {
+            let file = repo.work_tree().join(path);
+            if fs::symlink_metadata(&file)?.is_dir() {
+                fs::remove_dir_all(&file)?;
+            } else {
+                fs::remove_file(&file)?;
+            }
+        }
+        index_entries = head_entries.clone();
+        for file in diff::diff_index_to_worktree(repo)? {
+            if let Some(&(hash, mode)) = head_entries.get(&file.path) {
+                write_file(repo, &file.path, &hash, mode)?;
+            }
+        }
+    }
+
+    // Removals first, so that a directory the target replaces with a file
+    // (or the other way round) is out of the way.
+    for path in &removing {
+        remove_file(repo.work_tree(), path)?;
+        index_entries.remove(*path);
+    }
+    for path in &changing {
+        if let Some(&(hash, mode)) = target_entries.get(*path) {
+            write_file(repo, path, &hash, mode)?;
+            index_entries.insert(path.to_string(), (hash, mode));
+        }
+    }
+    index::write_index(&repo.index_path(), &index_entries)?;
+
+    let from = match store.head()? {
+        RefValue::Symbolic(branch) => branch.trim_start_matches("refs/heads/").to_string(),
+        RefValue::Direct(id) => id.to_hex(),
+    };
+    let (head, to) = match target {
+        Target::Branch(name) => (
+            RefValue::Symbolic(format!("refs/heads/{}", name)),
+            name.clone(),
+        ),
+        Target::Detached(id) => (RefValue::Direct(*id), id.to_hex()),
+    };
+    // HEAD is locked before its old value is checked, and the reflog entry
+    // is written as part of the same update. Without a commit there is no
+    // old value to check.
+    let expected = match old_head {
+        Some(id) => Expected::Value(id),
+        None => Expected::Any,
+    };
+    let reason = format!("checkout: moving from {} to {}", from, to);
+    let mut transaction = store.transaction();
+    transaction.write("HEAD", head, expected, &reason);
+    transaction.commit()
+}
+
+/// Fails if checking out would lose staged, unstaged or untracked content in
+/// one of the `changing` paths.
+fn check_overwrites(
+    repo: &Repository,
+    changing: &BTreeSet<&String>,
+    head_entries: &HashMap<String, (ObjectId, FileMode)>,
+    index_entries: &HashMap<String, (ObjectId, FileMode)>,
+) -> Result<()> {
+    let modified: BTreeSet<String> = diff::diff_index_to_worktree(repo)?
+        .into_iter()
+        .map(|file| file.path)
+        .collect();
+
+    let mut local_changes: Vec<String> = Vec::new();
+    let mut untracked = Vec::new();
+    for &path in changing {
+        if index_entries.get(path) != head_entries.get(path) || modified.contains(path) {
+            local_changes.push(path.to_string());
+        } else if !index_entries.contains_key(path)
+            && fs::symlink_metadata(repo.work_tree().join(path)).is_ok_and(|m| !m.is_dir())
+        {
+            untracked.push(path.to_string());
+        }
+    }
+
+    if !local_changes.is_empty() {
+        return Err(Error::Conflict(format!(
+            "Your local changes to the following files would be overwritten by checkout:\n\t{}\n\
+             Please commit your changes before you switch branches.",
+            local_changes.join("\n\t")
+        )));
+    }
+    if !untracked.is_empty() {
+        return Err(untracked_error(&untracked));
+    }
+    Ok(())
+}
+
+fn untracked_error(paths: &[String]) -> Error {
+    Error::Conflict(format!(
+        "The following untracked working tree files would be overwritten by checkout:\n\t{}\n\
+         Please move or remove them before you switch branches.",
+        paths.join("\n\t")
+    ))
+}
+
+/// Finds what stands in the way of the files the checkout writes, other than
+/// tracked files it removes anyway: a file or symbolic link where one of their
+/// directories has to go, or a directory holding other files where a file has
+/// to go.
+fn find_obstructions(
+    work_tree: &Path,
+    changing: &BTreeSet<&String>,
+    removing: &BTreeSet<&str>,
+) -> Result<Vec<String>> {
+    let mut obstructions = BTreeSet::new();
+    for path in changing
+        .iter()
+        .filter(|path| !removing.contains(path.as_str()))
+    {
+        let mut dir = path.as_str();
+        while let Some((parent, _)) = dir.rsplit_once('/') {
+            if fs::symlink_metadata(work_tree.join(parent)).is_ok_and(|m| !m.is_dir())
+                && !removing.contains(parent)
+            {
+                obstructions.insert(parent.to_string());
+            }
+            dir = parent;
+        }
+
+        let file = work_tree.join(path.as_str());
+        if fs::symlink_metadata(&file).is_ok_and(|m| m.is_dir()) {
+            for entry in walkdir::WalkDir::new(&file) {
+                let entry = entry.map_err(|e| Error::Io(e.into()))?;
+                if entry.file_type().is_dir() {
+                    continue;
+                }
+                let relative = entry.path().strip_prefix(work_tree).unwrap_or(entry.path());
+                if !removing.contains(utils::path_to_slash(relative)?.as_str()) {
+                    obstructions.insert(path.to_string());
+                    break;
+                }
+            }
+        }
+    }
+    Ok(obstructions.into_iter().collect())
+}
+
+/// Writes a blob to `path` in the working tree: a file, executable for mode
+/// 100755, or a symbolic link to the blob's content for mode 120000.
+fn write_file(repo: &Repository, path: &str, hash: &ObjectId, mode: FileMode) -> Result<()> {
+    let (obj_type, content) = utils::read_object(repo.git_dir(), hash)?;
+    if obj_type != "blob" {
+        return Err(Error::CorruptObject(format!(
+            "Expected blob {}, found {}",
+            hash, obj_type
+        )));
+    }
+    let file = repo.work_tree().join(path);
+    if let Some(parent) = file.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // Start from scratch: writing through an old symbolic link would change
+    // its target, and a new file gets the default permissions. A directory
+    // still here only holds empty directories (see `find_obstructions`).
+    match fs::symlink_metadata(&file) {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(&file)?,
+        Ok(_) => fs::remove_file(&file)?,
+        Err(_) => {}
+    }
+    match mode {
+        FileMode::Symlink => create_symlink(&content, &file),
+        _ => {
+            fs::write(&file, content)?;
+            if mode == FileMode::Executable {
+                set_executable(&file)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(unix)]
+fn create_symlink(target: &[u8], file: &Path) -> Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    std::os::unix::fs::symlink(std::ffi::OsStr::from_bytes(target), file)?;
+    Ok(())
+}
+
+/// Without symbolic links the target is written as a plain file, as git does
+/// with `core.symlinks` off.
+#[cfg(not(unix))]
+fn create_symlink(target: &[u8], file: &Path) -> Result<()> {
+    fs::write(file, target)?;
+    Ok(())
+}
+
+/// Adds the execute bits matching the read bits, as git does.
+#[cfg(unix)]
+fn set_executable(file: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    let mut permissions = fs::metadata(file)?.permissions();
+    let mode = permissions.mode();
+    permissions.set_mode(mode | ((mode & 0o444) >> 2));
+    fs::set_permissions(file, permissions)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_executable(_file: &Path) -> Result<()> {
+    Ok(())
+}
+
+/// Removes `path` from the working tree, along with directories it leaves empty.
+fn remove_file(work_tree: &Path, path: &str) -> Result<()> {
+    let file = work_tree.join(path);
+    if fs::symlink_metadata(&file).is_ok_and(|m| !m.is_dir()) {
+        fs::remove_file(&file)?;
+    }
+    let mut dir = file.parent();
+    while let Some(current) = dir {
+        if current == work_tree || fs::remove_dir(current).is_err() {
+            break;
+        }
+        dir = current.parent();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::test_utils::test_repo;
+    use crate::repository::{branch, reflog, rev_parse};
+
+    #[test]
+    fn test_checkout_updates_worktree_and_index() {
+        let (repo, temp_dir) = test_repo("xit_test_checkout");
+        let read = |path: &str| fs::read_to_string(temp_dir.join(path)).ok();
+
+        fs::write(temp_dir.join("shared.txt"), "shared").unwrap();
+        fs::write(temp_dir.join("a.txt"), "main version").unwrap();
+        repo.add("shared.txt").unwrap();
+        repo.add("a.txt").unwrap();
+        let first = repo.commit("First").unwrap().id;
+        branch::create(&repo, "topic", "HEAD", false).unwrap();
+
+        checkout(&repo, &Target::Branch("topic".to_string()), false).unwrap();
+        fs::create_dir_all(temp_dir.join("docs")).unwrap();
+        fs::write(temp_dir.join("docs/topic.txt"), "topic only").unwrap();
+        fs::write(temp_dir.join("a.txt"), "topic version").unwrap();
+        repo.add("shared.txt").unwrap();
+        repo.add("a.txt").unwrap();
+        repo.add("docs/topic.txt").unwrap();
+        let second = repo.commit("Second").unwrap().id;
+
+        // Back on main: topic's files are gone, main's content is restored.
+        checkout(&repo, &Target::Branch("main".to_string()), false).unwrap();
+        assert_eq!(read("a.txt").as_deref(), Some("main version"));
+        assert!(!temp_dir.join("docs").exists());
+        assert_eq!(
+            index::read_index(&repo.index_path()).unwrap(),
+            object_read::list_files_in_tree(
+                repo.git_dir(),
+                &object_read::get_commit_tree_hash(repo.git_dir(), &first).unwrap()
+            )
+            .unwrap()
+        );
+        assert_eq!(rev_parse::resolve(&repo, "HEAD").unwrap(), first);
+
+        // An edit to a file that has to change is never overwritten...
+        fs::write(temp_dir.join("a.txt"), "work in progress").unwrap();
+        let target = Target::Branch("topic".to_string());
+        assert!(matches!(
+            checkout(&repo, &target, false),
+            Err(Error::Conflict(_))
+        ));
+        assert_eq!(read("a.txt").as_deref(), Some("work in progress"));
+        // ...but an edit to a file both commits agree on is carried over.
+        fs::write(temp_dir.join("a.txt"), "main version").unwrap();
+        fs::write(temp_dir.join("shared.txt"), "edited").unwrap();
+        checkout(&repo, &target, false).unwrap();
+        assert_eq!(read("shared.txt").as_deref(), Some("edited"));
+        assert_eq!(read("docs/topic.txt").as_deref(), Some("topic only"));
+
+        // Untracked files in the way are protected too.
+        checkout(&repo, &Target::Detached(first), true).unwrap();
+        assert_eq!(read("shared.txt").as_deref(), Some("shared"));
+        assert_eq!(
+            fs::read_to_string(repo.git_dir().join("HEAD")).unwrap(),
+            format!("{}\n", first)
+        );
+        fs::create_dir_all(temp_dir.join("docs")).unwrap();
+        fs::write(temp_dir.join("docs/topic.txt"), "untracked").unwrap();
+        assert!(checkout(&repo, &target, false).is_err());
+        checkout(&repo, &target, true).unwrap();
+        assert_eq!(read("docs/topic.txt").as_deref(), Some("topic only"));
+        assert_eq!(rev_parse::resolve(&repo, "HEAD").unwrap(), second);
+
+        // Every move of HEAD is in its reflog, newest last.
+        let messages: Vec<String> = reflog::read(repo.git_dir(), "HEAD")
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.message)
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                "commit (initial): First".to_string(),
+                "checkout: moving from main to topic".to_string(),
+                "commit: Second".to_string(),
+                "checkout: moving from topic to main".to_string(),
+                "checkout: moving from main to topic".to_string(),
+                format!("checkout: moving from topic to {}", first),
+                format!("checkout: moving from {} to topic", first),
+            ]
+        );
+        assert_eq!(rev_parse::resolve(&repo, "HEAD@{1}").unwrap(), first);
+
+        fs::remove_dir_all(temp_dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_checkout_keeps_modes_and_replaces_directories() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let (repo, temp_dir) = test_repo("xit_test_checkout_modes");
+        let is_executable = |path: &str| {
+            fs::metadata(temp_dir.join(path))
+                .unwrap()
+                .permissions()
+                .mode()
+                & 0o111
+                != 0
+        };
+
+        // main: an executable script and a directory `out`.
+        fs::write(temp_dir.join("run.sh"), "#!/bin/sh\n").unwrap();
+        fs::set_permissions(temp_dir.join("run.sh"), fs::Permissions::from_mode(0o755)).unwrap();
+        fs::create_dir_all(temp_dir.join("out")).unwrap();
+        fs::write(temp_dir.join("out/log"), "log").unwrap();
+        repo.add("run.sh").unwrap();
+        repo.add("out/log").unwrap();
+        repo.commit("Main").unwrap();
+        branch::create(&repo, "topic", "HEAD", false).unwrap();
+
+        // topic: `out` becomes a file.
+        checkout(&repo, &Target::Branch("topic".to_string()), false).unwrap();
+        fs::remove_dir_all(temp_dir.join("out")).unwrap();
+        fs::write(temp_dir.join("out"), "file").unwrap();
+        repo.add("out").unwrap();
+        index::write_index(&repo.index_path(), &{
+            let mut entries = index::read_index(&repo.index_path()).unwrap();
+            entries.remove("out/log");
+            entries
+        })
+        .unwrap();
+        repo.commit("Topic").unwrap();
+
+        checkout(&repo, &Target::Branch("main".to_string()), false).unwrap();
+        assert_eq!(fs::read_to_string(temp_dir.join("out/log")).unwrap(), "log");
+        assert!(is_executable("run.sh"));
+
+        // An untracked file in `out` stops the switch before anything is written.
+        fs::write(temp_dir.join("out/extra"), "untracked").unwrap();
+        fs::set_permissions(temp_dir.join("run.sh"), fs::Permissions::from_mode(0o644)).unwrap();
+        let topic = Target::Branch("topic".to_string());
+        assert!(matches!(
+            checkout(&repo, &topic, false),
+            Err(Error::Conflict(_))
+        ));
+        assert_eq!(fs::read_to_string(temp_dir.join("out/log")).unwrap(), "log");
+        fs::remove_file(temp_dir.join("out/extra")).unwrap();
+
+        checkout(&repo, &topic, false).unwrap();
+        assert_eq!(fs::read_to_string(temp_dir.join("out")).unwrap(), "file");
+        let entries = index::read_index(&repo.index_path()).unwrap();
+        assert_eq!(entries["run.sh"].1, FileMode::Executable);
+        assert_eq!(entries["out"].1, FileMode::Regular);
+
+        fs::remove_dir_all(temp_dir).unwrap();
+    }
+}
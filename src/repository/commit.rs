@@ -1,71 +1,170 @@
-use crate::objects::{commit as commit_object, tree, update};
-use crate::repository::{config, index, refs, utils};
-use std::collections::HashMap;
-use std::fs;
-use std::io::{self, Result};
+use crate::error::{Error, Result};
+use crate::objects::id::ObjectId;
+use crate::objects::signature::Signature;
+use crate::objects::{commit as commit_object, read as object_read, tree};
+use crate::repository::refs::{self, Expected, RefChange};
+use crate::repository::repo::Repository;
+use crate::repository::{config, index};
+use std::collections::BTreeMap;
 use std::path::Path;
 
-/// Orchestrates the entire commit process.
-pub fn commit(message: &str) -> Result<()> {
+/// Orchestrates the entire commit process and returns the new commit hash.
+///
+/// The index is the full snapshot of the next commit and stays in place
+/// afterwards, so a commit with nothing staged since the last one is refused.
+pub fn commit(repo: &Repository, message: &str) -> Result<RefChange> {
+    let git_dir = repo.git_dir();
+
     // 1. --- Build Tree from Index ---
-    let index_path = Path::new(".xit").join("index");
+    let index_path = repo.index_path();
     if !index_path.exists() {
-        return Err(io::Error::new(
-            io::ErrorKind::NotFound,
-            "Nothing to commit. The index is empty.",
+        return Err(Error::InvalidInput(
+            "Nothing to commit. The index is empty.".to_string(),
         ));
     }
 
-    let index_entries = index::read_index(&index_path)?;
-    if index_entries.is_empty() {
-        println!("Nothing to commit, index is empty.");
-        return Ok(());
+    let index = index::Index::read(&index_path)?;
+    let head_ref_path = refs::get_head_ref_path(git_dir)?;
+    let parent_hash = refs::get_commit_hash(git_dir, &head_ref_path).ok();
+    // With a parent, an empty index records the deletion of every file.
+    if index.entries().is_empty() && parent_hash.is_none() {
+        return Err(Error::InvalidInput(
+            "Nothing to commit, index is empty.".to_string(),
+        ));
+    }
+    if index.entries().iter().any(|entry| entry.stage != 0) {
+        return Err(Error::Conflict(
+            "Committing is not possible because you have unmerged files.".to_string(),
+        ));
     }
 
-    let tree_hash = create_tree_from_index(index_entries)?;
+    let tree_hash = create_tree_from_index(git_dir, &index)?;
 
-    // 2. --- Find Parent Commit ---
-    let head_ref_path = refs::get_head_ref_path()?;
-    let parent_hash = refs::get_commit_hash(&head_ref_path).ok();
+    // 2. --- Compare with the Parent Commit ---
+    if let Some(parent) = &parent_hash
+        && object_read::get_commit_tree_hash(git_dir, parent)? == tree_hash
+    {
+        return Err(Error::InvalidInput(
+            "Nothing to commit, the index matches HEAD.".to_string(),
+        ));
+    }
 
     // 3. --- Get Author and Committer Info ---
-    let user_config = config::get_user_config()?;
-    let author = format!("{} <{}>", user_config.name, user_config.email);
+    let user_config = config::get_user_config(git_dir)?;
+    let author = Signature::now(&user_config.name, &user_config.email).to_string();
     // For this project, the author and committer are the same.
     let committer = &author;
 
     // 4. --- Create the Commit Object ---
     let new_commit_hash = commit_object::create_commit(
+        git_dir,
         &tree_hash,
-        parent_hash.as_deref(),
+        parent_hash.as_ref(),
         &author,
         committer,
         message,
     )?;
 
     // 5. --- Update the Branch Reference (HEAD) ---
-    update::update_reference(&head_ref_path, &new_commit_hash)?;
+    let subject = message.lines().next().unwrap_or_default();
+    let reason = match parent_hash {
+        Some(_) => format!("commit: {}", subject),
+        None => format!("commit (initial): {}", subject),
+    };
+    // Fails if another process moved the branch since its tip was read.
+    let expected = match parent_hash {
+        Some(parent) => Expected::Value(parent),
+        None => Expected::Missing,
+    };
+    let mut transaction = repo.refs().transaction();
+    transaction.update("HEAD", new_commit_hash, expected, &reason);
+    let warnings = transaction.commit()?;
 
-    // 6. --- Clear the Index ---
-    fs::remove_file(&index_path)?;
-
-    //    println!("Committed to [{}]: {}", &new_commit_hash[..7], message);
-    Ok(())
+    Ok(RefChange {
+        id: new_commit_hash,
+        warnings,
+    })
 }
 
 /// Builds a tree object from the current index and returns its hash.
-fn create_tree_from_index(index: HashMap<String, String>) -> Result<String> {
+///
+/// Index paths such as `src/main.rs` are split on `/` so that every directory
+/// becomes its own subtree, as git does.
+fn create_tree_from_index(git_dir: &Path, index: &index::Index) -> Result<ObjectId> {
+    let entries: Vec<(&str, ObjectId, tree::FileMode)> = index
+        .entries()
+        .iter()
+        .map(|entry| (entry.path.as_str(), entry.id, entry.mode))
+        .collect();
+    write_tree(git_dir, &entries)
+}
+
+/// Writes the tree for one directory level. `entries` holds paths relative to
+/// that directory together with their blob hashes and modes.
+fn write_tree(git_dir: &Path, entries: &[(&str, ObjectId, tree::FileMode)]) -> Result<ObjectId> {
     let mut tree_entries: Vec<tree::TreeEntry> = Vec::new();
-    for (path, hash_hex) in index {
-        let hash_bytes = utils::hex_to_bytes(&hash_hex)
-            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Invalid hash in index"))?;
-        tree_entries.push(tree::TreeEntry {
-            mode: "100644".to_string(), // Assuming normal file mode for simplicity
-            obj_type: "blob".to_string(),
-            hash: hash_bytes,
-            name: path,
-        });
+    let mut subdirs: BTreeMap<&str, Vec<(&str, ObjectId, tree::FileMode)>> = BTreeMap::new();
+
+    for &(path, hash, mode) in entries {
+        match path.split_once('/') {
+            Some((dir, rest)) => subdirs.entry(dir).or_default().push((rest, hash, mode)),
+            None => tree_entries.push(tree::create_blob_entry(mode, &hash, path)),
+        }
     }
+
+    for (dir, children) in subdirs {
+        let subtree_hash = write_tree(git_dir, &children)?;
+        tree_entries.push(tree::create_tree_entry(&subtree_hash, dir));
+    }
+
     // Call the low-level tree creation function from the objects module.
-    tree::create_tree(tree_entries)
+    tree::create_tree(git_dir, tree_entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_nested_tree_matches_git() {
+        let git_dir = std::env::temp_dir().join("xit_test_nested_tree");
+        let _ = fs::remove_dir_all(&git_dir);
+
+        // Blob hashes of the file contents, as produced by `git hash-object`.
+        let mut index = index::Index::default();
+        for (path, hash) in [
+            ("src/main.rs", "f328e4d9d04c31d0d70d16d21a07d1613be9d577"),
+            (
+                "src/bin/tool.rs",
+                "587be6b4c3f93f93c489c0111bba5596147a26cb",
+            ),
+            ("README.md", "8178c76d627cade75005b40711b92f4177bc6cfc"),
+            ("docs.txt", "8e695ec83aa8b1d596183b26206a514576570fff"),
+            ("docs/guide.md", "7e2b6439aebf0bb975796f691b3b227d0af43bb5"),
+        ] {
+            let entry =
+                index::IndexEntry::new(path, hash.parse().unwrap(), tree::FileMode::Regular);
+            index.add(entry);
+        }
+
+        // `git write-tree` for the same content yields this root tree.
+        let tree_hash = create_tree_from_index(&git_dir, &index).unwrap();
+        assert_eq!(
+            tree_hash.to_hex(),
+            "03e3bbd3138ee3298953d5f416ac0a839b5e5d05"
+        );
+
+        let files = crate::objects::read::list_files_in_tree(&git_dir, &tree_hash).unwrap();
+        assert_eq!(
+            files
+                .get("src/bin/tool.rs")
+                .map(|(id, _)| id.to_hex())
+                .as_deref(),
+            Some("587be6b4c3f93f93c489c0111bba5596147a26cb")
+        );
+        assert_eq!(files.len(), 5);
+
+        fs::remove_dir_all(git_dir).unwrap();
+    }
 }
@@ -1,6 +1,7 @@
+use crate::error::{Error, Result};
 use std::env;
 use std::fs;
-use std::io::{self, BufRead, Result, Write};
+use std::io::{self, BufRead, Write};
 use std::path::{Path, PathBuf};
 
 /// Represents the user's identity as found in the .xit/config file.
@@ -13,8 +14,8 @@ pub struct UserConfig {
 /// Gets the path to the global xit config file (e.g., ~/.xit/config)
 fn get_global_config_path() -> Result<PathBuf> {
     // Find the user's home directory.
-    let home_dir = env::var("HOME")
-        .map_err(|_| io::Error::new(io::ErrorKind::NotFound, "Could not find HOME directory"))?;
+    let home_dir =
+        env::var("HOME").map_err(|_| Error::Config("Could not find HOME directory".to_string()))?;
     let config_dir = Path::new(&home_dir).join(".xit");
     Ok(config_dir.join("config"))
 }
@@ -23,25 +24,16 @@ fn get_global_config_path() -> Result<PathBuf> {
 pub fn setup_global_user(name: &str, email: &str) -> Result<()> {
     // Validate inputs
     if name.trim().is_empty() {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidInput,
-            "User name cannot be empty",
-        ));
+        return Err(Error::Config("User name cannot be empty".to_string()));
     }
 
     if email.trim().is_empty() {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidInput,
-            "User email cannot be empty",
-        ));
+        return Err(Error::Config("User email cannot be empty".to_string()));
     }
 
     // Basic email validation
     if !email.contains('@') {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidInput,
-            "Invalid email format",
-        ));
+        return Err(Error::Config("Invalid email format".to_string()));
     }
 
     let config_path = get_global_config_path()?;
@@ -64,9 +56,9 @@ pub fn setup_global_user(name: &str, email: &str) -> Result<()> {
 /// This function uses a simple line-by-line parser that looks for the `[user]`
 /// section and then extracts the `name` and `email` key-value pairs.
 /// Reads config from local and global files to find the user's identity.
-pub fn get_user_config() -> Result<UserConfig> {
+pub fn get_user_config(git_dir: &Path) -> Result<UserConfig> {
     // 1. Try to read from the local repository config first.
-    let local_path = Path::new(".xit").join("config");
+    let local_path = git_dir.join("config");
     if let Ok(Some(config)) = read_user_from_path(&local_path) {
         return Ok(config);
     }
@@ -78,9 +70,8 @@ pub fn get_user_config() -> Result<UserConfig> {
     }
 
     // 3. If not found anywhere, return an error.
-    Err(io::Error::new(
-        io::ErrorKind::NotFound,
-        "User identity not found. Please run `xit setup` to configure your identity.",
+    Err(Error::Config(
+        "User identity not found. Please run `xit setup` to configure your identity.".to_string(),
     ))
 }
 
@@ -134,6 +125,39 @@ fn read_user_from_path(path: &Path) -> Result<Option<UserConfig>> {
     }
 }
 
+/// Looks up `key` in `[section]`, first in the repository config and then in the
+/// global one. Later assignments in a file override earlier ones, like git.
+pub fn get_config_value(git_dir: &Path, section: &str, key: &str) -> Result<Option<String>> {
+    if let Some(value) = read_value_from_path(&git_dir.join("config"), section, key)? {
+        return Ok(Some(value));
+    }
+    read_value_from_path(&get_global_config_path()?, section, key)
+}
+
+/// Reads a single `key = value` entry of a section from one config file.
+fn read_value_from_path(path: &Path, section: &str, key: &str) -> Result<Option<String>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let header = format!("[{}]", section);
+    let mut in_section = false;
+    let mut value = None;
+    for line in io::BufReader::new(fs::File::open(path)?).lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.starts_with('[') {
+            in_section = line.eq_ignore_ascii_case(&header);
+        } else if in_section
+            && let Some((name, entry)) = line.split_once('=')
+            && name.trim().eq_ignore_ascii_case(key)
+        {
+            value = Some(entry.trim().to_string());
+        }
+    }
+    Ok(value)
+}
+
 // /// Sets up local repository user configuration
 // pub fn setup_local_user(name: &str, email: &str) -> Result<()> {
 //     // Validate inputs
@@ -236,4 +260,4 @@ fn read_user_from_path(path: &Path) -> Result<Option<UserConfig>> {
 //     }
 
 //     false
-// }
\ No newline at end of file
+// }
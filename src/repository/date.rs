@@ -0,0 +1,208 @@
+//! Conversions between Unix timestamps and the dates shown to and typed by users.
+
+use crate::error::{Error, Result};
+
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Formats a timestamp like git's default date format, in the given UTC offset:
+/// `Tue Oct 17 09:30:00 2026 +0200`.
+pub fn format_date(time: i64, offset_minutes: i32) -> String {
+    let local = time + i64::from(offset_minutes) * 60;
+    let days = local.div_euclid(86400);
+    let seconds = local.rem_euclid(86400);
+    let (year, month, day) = civil_from_days(days);
+    let weekday = WEEKDAYS[(days + 4).rem_euclid(7) as usize];
+    let sign = if offset_minutes < 0 { '-' } else { '+' };
+    let offset = offset_minutes.abs();
+    format!(
+        "{} {} {} {:02}:{:02}:{:02} {} {}{:02}{:02}",
+        weekday,
+        MONTHS[month as usize - 1],
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60,
+        year,
+        sign,
+        offset / 60,
+        offset % 60
+    )
+}
+
+/// Formats a timestamp as `YYYY-MM-DD` in the given UTC offset.
+pub fn format_short(time: i64, offset_minutes: i32) -> String {
+    let days = (time + i64::from(offset_minutes) * 60).div_euclid(86400);
+    let (year, month, day) = civil_from_days(days);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Describes how long before `now` a timestamp is: `3 days ago`.
+pub fn format_relative(time: i64, now: i64) -> String {
+    let elapsed = now - time;
+    if elapsed < 0 {
+        return "in the future".to_string();
+    }
+    let (count, unit) = match elapsed {
+        0..90 => (elapsed, "second"),
+        90..5400 => ((elapsed + 30) / 60, "minute"),
+        5400..129600 => ((elapsed + 1800) / 3600, "hour"),
+        129600..1209600 => ((elapsed + 43200) / 86400, "day"),
+        1209600..5184000 => ((elapsed + 302400) / 604800, "week"),
+        5184000..31536000 => ((elapsed + 1296000) / 2592000, "month"),
+        _ => ((elapsed + 15768000) / 31536000, "year"),
+    };
+    format!(
+        "{} {}{} ago",
+        count,
+        unit,
+        if count == 1 { "" } else { "s" }
+    )
+}
+
+/// Parses a date given on the command line, relative to `now`. Accepted forms
+/// are a Unix timestamp (`@1700000000` or a bare number), `YYYY-MM-DD` with an
+/// optional `HH:MM[:SS]` time (UTC), `now`, `yesterday`, and `<n> <unit>s ago`
+/// with units from seconds to years.
+pub fn parse_date(text: &str, now: i64) -> Result<i64> {
+    let invalid = || Error::InvalidInput(format!("Invalid date '{}'", text));
+    let text = text.trim();
+
+    if let Some(seconds) = text.strip_prefix('@') {
+        return seconds.parse().map_err(|_| invalid());
+    }
+    if text.len() > 4
+        && let Ok(seconds) = text.parse::<i64>()
+    {
+        return Ok(seconds);
+    }
+    match text {
+        "now" => return Ok(now),
+        "yesterday" => return Ok(now - 86400),
+        _ => {}
+    }
+
+    let words: Vec<&str> = text
+        .split(|c: char| c.is_whitespace() || c == '.')
+        .collect();
+    if let [count, unit, "ago"] = words.as_slice() {
+        let count: i64 = count.parse().map_err(|_| invalid())?;
+        let unit_seconds = match unit.trim_end_matches('s') {
+            "second" | "sec" => 1,
+            "minute" | "min" => 60,
+            "hour" => 3600,
+            "day" => 86400,
+            "week" => 604800,
+            "month" => 2592000,
+            "year" => 31536000,
+            _ => return Err(invalid()),
+        };
+        return count
+            .checked_mul(unit_seconds)
+            .and_then(|seconds| now.checked_sub(seconds))
+            .ok_or_else(invalid);
+    }
+
+    let (date, time) = match text.split_once(['T', ' ']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (text, None),
+    };
+    let date: Vec<i64> = date
+        .split('-')
+        .map(|part| part.parse().map_err(|_| invalid()))
+        .collect::<Result<_>>()?;
+    let [year, month, day] = date[..] else {
+        return Err(invalid());
+    };
+    // Far enough out for any commit, and no overflow converting to seconds.
+    if !(0..=9999).contains(&year) || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return Err(invalid());
+    }
+    let mut seconds = 0;
+    if let Some(time) = time {
+        let parts: Vec<i64> = time
+            .split(':')
+            .map(|part| part.parse().map_err(|_| invalid()))
+            .collect::<Result<_>>()?;
+        let (hour, minute, second) = match parts[..] {
+            [hour, minute] => (hour, minute, 0),
+            [hour, minute, second] => (hour, minute, second),
+            _ => return Err(invalid()),
+        };
+        if !(0..24).contains(&hour) || !(0..60).contains(&minute) || !(0..60).contains(&second) {
+            return Err(invalid());
+        }
+        seconds = hour * 3600 + minute * 60 + second;
+    }
+    Ok(days_from_civil(year, month as u32, day as u32) * 86400 + seconds)
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = i64::from(month);
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// The proleptic Gregorian date of a number of days since 1970-01-01.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_date() {
+        assert_eq!(format_date(0, 0), "Thu Jan 1 00:00:00 1970 +0000");
+        assert_eq!(
+            format_date(1700000000, 330),
+            "Wed Nov 15 03:43:20 2023 +0530"
+        );
+        assert_eq!(
+            format_date(1700000000, -480),
+            "Tue Nov 14 14:13:20 2023 -0800"
+        );
+        assert_eq!(format_short(1700000000, 0), "2023-11-14");
+        assert_eq!(format_relative(1000, 1000 + 3 * 86400), "3 days ago");
+    }
+
+    #[test]
+    fn test_parse_date() {
+        let now = 1700000000;
+        assert_eq!(parse_date("2023-11-14", now).unwrap(), 1699920000);
+        assert_eq!(parse_date("2023-11-14 22:13:20", now).unwrap(), now);
+        assert_eq!(parse_date("2023-11-14T22:13", now).unwrap(), now - 20);
+        assert_eq!(parse_date("@1234", now).unwrap(), 1234);
+        assert_eq!(parse_date("2 weeks ago", now).unwrap(), now - 1209600);
+        assert_eq!(parse_date("1.day.ago", now).unwrap(), now - 86400);
+        assert!(parse_date("last tuesday", now).is_err());
+        assert!(parse_date("2023-13-01", now).is_err());
+        assert!(parse_date("2023-01-01 99:99", now).is_err());
+        assert!(parse_date("2023-01-01 12:00:60", now).is_err());
+        assert!(parse_date("99999999999999 years ago", now).is_err());
+        assert!(parse_date("99999999999999999-01-01", now).is_err());
+    }
+}
@@ -0,0 +1,461 @@
+use crate::diff::{self, Algorithm, Edit, unified};
+use crate::error::{Error, Result};
+use crate::objects::commit::Commit;
+use crate::objects::id::ObjectId;
+use crate::objects::object;
+use crate::objects::read as object_read;
+use crate::objects::tree::FileMode;
+use crate::repository::repo::Repository;
+use crate::repository::{config, index, status, utils, worktree};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::io;
+use term_colr::{blue, green, red, yellow};
+
+/// Number of unchanged lines shown around each change.
+pub const DEFAULT_CONTEXT: usize = 3;
+
+/// How a patch is computed and rendered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PatchOptions {
+    /// Unchanged lines shown around each change.
+    pub context: usize,
+    pub algorithm: Algorithm,
+    pub color: bool,
+}
+
+impl Default for PatchOptions {
+    fn default() -> PatchOptions {
+        PatchOptions {
+            context: DEFAULT_CONTEXT,
+            algorithm: Algorithm::default(),
+            color: false,
+        }
+    }
+}
+
+/// The diff algorithm configured with `diff.algorithm`, or the default one.
+pub fn configured_algorithm(repo: &Repository) -> Result<Algorithm> {
+    match config::get_config_value(repo.git_dir(), "diff", "algorithm")? {
+        Some(name) => name
+            .parse()
+            .map_err(|_| Error::Config(format!("Invalid diff.algorithm '{}'", name))),
+        None => Ok(Algorithm::default()),
+    }
+}
+
+/// One side of a file comparison.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileVersion {
+    pub hash: ObjectId,
+    pub mode: FileMode,
+    pub content: Vec<u8>,
+}
+
+/// A file that differs between two snapshots. `old` is `None` for added files
+/// and `new` is `None` for deleted files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileDiff {
+    pub path: String,
+    pub old: Option<FileVersion>,
+    pub new: Option<FileVersion>,
+}
+
+/// Changes in the working tree that are not staged (`xit diff`).
+pub fn diff_index_to_worktree(repo: &Repository) -> Result<Vec<FileDiff>> {
+    let index_entries = index_or_head(repo)?;
+    compare(
+        repo,
+        &index_entries,
+        &worktree_side(repo, index_entries.keys())?,
+    )
+}
+
+/// Staged changes relative to a commit tree, HEAD when `tree` is `None` (`xit diff --cached`).
+pub fn diff_tree_to_index(repo: &Repository, tree: Option<&ObjectId>) -> Result<Vec<FileDiff>> {
+    let tree_entries = tree_or_head(repo, tree)?;
+    compare(repo, &tree_entries, &Side::Stored(index_or_head(repo)?))
+}
+
+/// Changes in the working tree relative to a commit tree (`xit diff <commit>`).
+pub fn diff_tree_to_worktree(repo: &Repository, tree: &ObjectId) -> Result<Vec<FileDiff>> {
+    let tree_entries = object_read::list_files_in_tree(repo.git_dir(), tree)?;
+    let index_entries = index_or_head(repo)?;
+    let tracked = tree_entries.keys().chain(index_entries.keys());
+    compare(repo, &tree_entries, &worktree_side(repo, tracked)?)
+}
+
+/// Changes between two trees (`xit diff <commit> <commit>`).
+pub fn diff_trees(repo: &Repository, old: &ObjectId, new: &ObjectId) -> Result<Vec<FileDiff>> {
+    let old_entries = object_read::list_files_in_tree(repo.git_dir(), old)?;
+    let new_entries = object_read::list_files_in_tree(repo.git_dir(), new)?;
+    compare(repo, &old_entries, &Side::Stored(new_entries))
+}
+
+/// The changes a commit made relative to its first parent; every file of a
+/// root commit shows as added (`xit show`).
+pub fn diff_commit(repo: &Repository, commit: &Commit) -> Result<Vec<FileDiff>> {
+    let git_dir = repo.git_dir();
+    let parent_entries = match commit.parents.first() {
+        Some(parent) => {
+            let tree = object_read::get_commit_tree_hash(git_dir, parent)?;
+            object_read::list_files_in_tree(git_dir, &tree)?
+        }
+        None => HashMap::new(),
+    };
+    let entries = object_read::list_files_in_tree(git_dir, &commit.tree)?;
+    compare(repo, &parent_entries, &Side::Stored(entries))
+}
+
+/// The index entries, or the HEAD tree when there is no index file yet.
+pub(crate) fn index_or_head(repo: &Repository) -> Result<HashMap<String, (ObjectId, FileMode)>> {
+    let index_path = repo.index_path();
+    if index_path.exists() {
+        index::read_index(&index_path)
+    } else {
+        status::get_head_tree_entries(repo.git_dir())
+    }
+}
+
+fn tree_or_head(
+    repo: &Repository,
+    tree: Option<&ObjectId>,
+) -> Result<HashMap<String, (ObjectId, FileMode)>> {
+    match tree {
+        Some(tree) => object_read::list_files_in_tree(repo.git_dir(), tree),
+        None => status::get_head_tree_entries(repo.git_dir()),
+    }
+}
+
+/// The new side of a comparison: either object ids, or contents read from disk.
+enum Side {
+    Stored(HashMap<String, (ObjectId, FileMode)>),
+    Worktree(HashMap<String, FileVersion>),
+}
+
+/// Reads the given tracked paths from the working tree; missing files are left out.
+fn worktree_side<'a>(repo: &Repository, paths: impl Iterator<Item = &'a String>) -> Result<Side> {
+    let mut files = HashMap::new();
+    for path in paths {
+        let file = repo.work_tree().join(path);
+        let metadata = match fs::symlink_metadata(&file) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        let content = worktree::read_content(&file)?;
+        let hash = object::hash_object("blob", &content);
+        let mode = index::file_mode(&metadata);
+        files.insert(
+            path.clone(),
+            FileVersion {
+                hash,
+                mode,
+                content,
+            },
+        );
+    }
+    Ok(Side::Worktree(files))
+}
+
+fn compare(
+    repo: &Repository,
+    old: &HashMap<String, (ObjectId, FileMode)>,
+    new: &Side,
+) -> Result<Vec<FileDiff>> {
+    let new_entries: HashMap<&String, (ObjectId, FileMode)> = match new {
+        Side::Stored(entries) => entries.iter().map(|(path, entry)| (path, *entry)).collect(),
+        Side::Worktree(files) => files
+            .iter()
+            .map(|(path, file)| (path, (file.hash, file.mode)))
+            .collect(),
+    };
+    let paths: BTreeSet<&String> = old.keys().chain(new_entries.keys().copied()).collect();
+
+    // A file differs when its content or its mode does.
+    let mut diffs = Vec::new();
+    for path in paths {
+        let old_entry = old.get(path);
+        let new_entry = new_entries.get(path);
+        if old_entry == new_entry {
+            continue;
+        }
+        let old = old_entry
+            .map(|(hash, mode)| read_version(repo, hash, *mode))
+            .transpose()?;
+        let new = match new {
+            Side::Stored(_) => new_entry
+                .map(|(hash, mode)| read_version(repo, hash, *mode))
+                .transpose()?,
+            Side::Worktree(files) => files.get(path).cloned(),
+        };
+        diffs.push(FileDiff {
+            path: path.clone(),
+            old,
+            new,
+        });
+    }
+    Ok(diffs)
+}
+
+fn read_version(repo: &Repository, hash: &ObjectId, mode: FileMode) -> Result<FileVersion> {
+    let (obj_type, content) = utils::read_object(repo.git_dir(), hash)?;
+    if obj_type != "blob" {
+        return Err(Error::CorruptObject(format!(
+            "Expected blob {}, found {}",
+            hash, obj_type
+        )));
+    }
+    Ok(FileVersion {
+        hash: *hash,
+        mode,
+        content,
+    })
+}
+
+/// Renders the diffs as a git-style unified patch.
+pub fn format_patch(diffs: &[FileDiff], options: &PatchOptions) -> String {
+    let paint = |text: String, kind: LineKind| -> String {
+        if !options.color {
+            return text;
+        }
+        match kind {
+            LineKind::Meta => yellow!("{}", text),
+            LineKind::Frag => blue!("{}", text),
+            LineKind::Old => red!("{}", text),
+            LineKind::New => green!("{}", text),
+            LineKind::Context => text,
+        }
+    };
+
+    let mut out = String::new();
+    let mut emit = |text: String, kind: LineKind| {
+        out.push_str(&paint(text, kind));
+        out.push('\n');
+    };
+
+    for file in diffs {
+        match (&file.old, &file.new) {
+            // As in git, a file that became a symbolic link or the other way
+            // round shows as a deletion followed by an addition.
+            (Some(old), Some(new))
+                if (old.mode == FileMode::Symlink) != (new.mode == FileMode::Symlink) =>
+            {
+                format_file(&file.path, Some(old), None, options, &mut emit);
+                format_file(&file.path, None, Some(new), options, &mut emit);
+            }
+            (old, new) => format_file(&file.path, old.as_ref(), new.as_ref(), options, &mut emit),
+        }
+    }
+    out
+}
+
+/// Renders the patch of one file.
+fn format_file(
+    path: &str,
+    old: Option<&FileVersion>,
+    new: Option<&FileVersion>,
+    options: &PatchOptions,
+    emit: &mut impl FnMut(String, LineKind),
+) {
+    let zero = "0000000".to_string();
+    let old_id = old.map_or(zero.clone(), |v| v.hash.short());
+    let new_id = new.map_or(zero, |v| v.hash.short());
+
+    emit(format!("diff --git a/{} b/{}", path, path), LineKind::Meta);
+    match (old, new) {
+        (None, Some(new)) => {
+            emit(
+                format!("new file mode {}", new.mode.as_str()),
+                LineKind::Meta,
+            );
+            emit(format!("index {}..{}", old_id, new_id), LineKind::Meta);
+        }
+        (Some(old), None) => {
+            emit(
+                format!("deleted file mode {}", old.mode.as_str()),
+                LineKind::Meta,
+            );
+            emit(format!("index {}..{}", old_id, new_id), LineKind::Meta);
+        }
+        (Some(old), Some(new)) if old.mode != new.mode => {
+            emit(format!("old mode {}", old.mode.as_str()), LineKind::Meta);
+            emit(format!("new mode {}", new.mode.as_str()), LineKind::Meta);
+            if old.hash != new.hash {
+                emit(format!("index {}..{}", old_id, new_id), LineKind::Meta);
+            }
+        }
+        (Some(old), Some(_)) => emit(
+            format!("index {}..{} {}", old_id, new_id, old.mode.as_str()),
+            LineKind::Meta,
+        ),
+        (None, None) => {}
+    }
+
+    let old_content = old.map_or(&[][..], |v| &v.content[..]);
+    let new_content = new.map_or(&[][..], |v| &v.content[..]);
+    let old_name = old.map_or("/dev/null".to_string(), |_| format!("a/{}", path));
+    let new_name = new.map_or("/dev/null".to_string(), |_| format!("b/{}", path));
+
+    if diff::is_binary(old_content) || diff::is_binary(new_content) {
+        emit(
+            format!("Binary files {} and {} differ", old_name, new_name),
+            LineKind::Context,
+        );
+        return;
+    }
+
+    let old_lines = diff::split_lines(old_content);
+    let new_lines = diff::split_lines(new_content);
+    let edits = diff::diff_with(options.algorithm, &old_lines, &new_lines);
+    let hunks = unified::hunks(&edits, options.context);
+    if hunks.is_empty() {
+        return;
+    }
+
+    emit(format!("--- {}", old_name), LineKind::Meta);
+    emit(format!("+++ {}", new_name), LineKind::Meta);
+    for hunk in hunks {
+        emit(hunk.header(), LineKind::Frag);
+        for edit in &hunk.edits {
+            let (prefix, line, kind) = match *edit {
+                Edit::Equal(o, _) => (' ', old_lines[o], LineKind::Context),
+                Edit::Delete(o) => ('-', old_lines[o], LineKind::Old),
+                Edit::Insert(n) => ('+', new_lines[n], LineKind::New),
+            };
+            let text = String::from_utf8_lossy(line);
+            match text.strip_suffix('\n') {
+                Some(text) => emit(format!("{}{}", prefix, text), kind),
+                None => {
+                    emit(format!("{}{}", prefix, text), kind);
+                    emit(
+                        "\\ No newline at end of file".to_string(),
+                        LineKind::Context,
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum LineKind {
+    Meta,
+    Frag,
+    Old,
+    New,
+    Context,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::test_utils::test_repo;
+
+    #[test]
+    fn test_diff_index_to_worktree_and_cached() {
+        let (repo, temp_dir) = test_repo("xit_test_diff");
+
+        fs::write(temp_dir.join("a.txt"), "one\ntwo\nthree\n").unwrap();
+        repo.add("a.txt").unwrap();
+        assert!(diff_index_to_worktree(&repo).unwrap().is_empty());
+
+        fs::write(temp_dir.join("a.txt"), "one\n2\nthree").unwrap();
+        let patch = format_patch(
+            &diff_index_to_worktree(&repo).unwrap(),
+            &PatchOptions::default(),
+        );
+        let expected = "diff --git a/a.txt b/a.txt\n\
+index 4cb29ea..a623a0b 100644\n\
+--- a/a.txt\n\
++++ b/a.txt\n\
+@@ -1,3 +1,3 @@\n \
+one\n\
+-two\n\
+-three\n\
++2\n\
++three\n\
+\\ No newline at end of file\n";
+        assert_eq!(patch, expected);
+
+        // Nothing is committed yet, so the whole staged file is new.
+        let patch = format_patch(
+            &diff_tree_to_index(&repo, None).unwrap(),
+            &PatchOptions::default(),
+        );
+        assert!(patch.contains("new file mode 100644\nindex 0000000..4cb29ea\n"));
+        assert!(patch.contains("--- /dev/null\n+++ b/a.txt\n@@ -0,0 +1,3 @@\n+one\n"));
+
+        fs::remove_dir_all(temp_dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_patch_shows_modes() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let (repo, temp_dir) = test_repo("xit_test_diff_modes");
+        let patch = || {
+            format_patch(
+                &diff_index_to_worktree(&repo).unwrap(),
+                &PatchOptions::default(),
+            )
+        };
+
+        fs::write(temp_dir.join("run.sh"), "one\n").unwrap();
+        fs::write(temp_dir.join("link"), "run.sh").unwrap();
+        repo.add(".").unwrap();
+
+        // A mode-only change has no hunks but still shows.
+        fs::set_permissions(temp_dir.join("run.sh"), fs::Permissions::from_mode(0o755)).unwrap();
+        assert_eq!(
+            patch(),
+            "diff --git a/run.sh b/run.sh\nold mode 100644\nnew mode 100755\n"
+        );
+        repo.add("run.sh").unwrap();
+        fs::write(temp_dir.join("run.sh"), "two\n").unwrap();
+        assert!(
+            patch().starts_with("diff --git a/run.sh b/run.sh\nindex 5626abf..f719efd 100755\n")
+        );
+        repo.add("run.sh").unwrap();
+
+        // A file replaced by a symbolic link is deleted, then added.
+        fs::remove_file(temp_dir.join("link")).unwrap();
+        std::os::unix::fs::symlink("run.sh", temp_dir.join("link")).unwrap();
+        let patch = patch();
+        assert!(patch.starts_with(
+            "diff --git a/link b/link\ndeleted file mode 100644\nindex e0e6347..0000000\n"
+        ));
+        assert!(
+            patch.contains(
+                "diff --git a/link b/link\nnew file mode 120000\nindex 0000000..e0e6347\n"
+            )
+        );
+        assert!(patch.ends_with("+run.sh\n\\ No newline at end of file\n"));
+
+        fs::remove_dir_all(temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_configured_algorithm() {
+        let (repo, temp_dir) = test_repo("xit_test_diff_config");
+        let config_path = repo.git_dir().join("config");
+        let config = fs::read_to_string(&config_path).unwrap();
+
+        fs::write(
+            &config_path,
+            format!("{}[diff]\n\talgorithm = Histogram\n", config),
+        )
+        .unwrap();
+        assert_eq!(configured_algorithm(&repo).unwrap(), Algorithm::Histogram);
+
+        fs::write(
+            &config_path,
+            format!("{}[diff]\n\talgorithm = fast\n", config),
+        )
+        .unwrap();
+        assert!(matches!(configured_algorithm(&repo), Err(Error::Config(_))));
+
+        fs::remove_dir_all(temp_dir).unwrap();
+    }
+}
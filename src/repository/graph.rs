@@ -0,0 +1,173 @@
+use crate::objects::id::ObjectId;
+
+/// Draws the lanes of `xit log --graph`, one commit at a time.
+///
+/// Each lane is waiting for a commit: the parent of a commit already shown.
+/// Commits must be fed children first (topological order); a commit takes the
+/// lane that was waiting for it, its first parent continues in that lane and
+/// further parents of a merge open new lanes to its right. Lanes waiting for the
+/// same commit are joined as soon as one of them reaches it.
+#[derive(Debug, Default)]
+pub struct Graph {
+    columns: Vec<ObjectId>,
+}
+
+/// The graph drawn next to one commit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GraphRows {
+    /// The row with the commit's `*`, followed by the rows drawing lanes that
+    /// fork or join below it.
+    pub rows: Vec<String>,
+    /// The row to repeat next to any further output lines of the commit.
+    pub padding: String,
+}
+
+impl GraphRows {
+    /// Prefixes `lines` with the graph, padding it to a common width. Extra graph
+    /// rows that have no line of their own are printed on their own.
+    pub fn decorate(&self, lines: &[&str]) -> Vec<String> {
+        let width = self
+            .rows
+            .iter()
+            .chain([&self.padding])
+            .map(String::len)
+            .max()
+            .unwrap_or(0);
+        let count = lines.len().max(self.rows.len());
+        (0..count)
+            .map(|i| {
+                let graph = self.rows.get(i).unwrap_or(&self.padding);
+                match lines.get(i) {
+                    Some(line) => format!("{:width$} {}", graph, line, width = width),
+                    None => graph.clone(),
+                }
+            })
+            .map(|row| row.trim_end().to_string())
+            .collect()
+    }
+}
+
+impl Graph {
+    pub fn new() -> Graph {
+        Graph::default()
+    }
+
+    /// Places a commit and returns the rows to draw next to it.
+    pub fn next(&mut self, id: &ObjectId, parents: &[ObjectId]) -> GraphRows {
+        let column = match self.columns.iter().position(|lane| lane == id) {
+            Some(column) => column,
+            None => {
+                self.columns.push(*id);
+                self.columns.len() - 1
+            }
+        };
+
+        let mut rows = vec![self.commit_row(column)];
+
+        // Lanes below the commit: the first parent takes over the commit's lane
+        // and new parents are inserted right after it.
+        let before = self.columns.clone();
+        let mut after: Vec<ObjectId> = Vec::new();
+        for (i, lane) in before.iter().enumerate() {
+            if i == column {
+                for parent in parents {
+                    if !after.contains(parent) && !before[i + 1..].contains(parent) {
+                        after.push(*parent);
+                    }
+                }
+            } else if !after.contains(lane) {
+                after.push(*lane);
+            }
+        }
+
+        // Where each lane goes in the next row.
+        let mut moves: Vec<(usize, usize)> = Vec::new();
+        for (i, lane) in before.iter().enumerate() {
+            let targets: Vec<&ObjectId> = if i == column {
+                parents.iter().collect()
+            } else {
+                vec![lane]
+            };
+            for target in targets {
+                if let Some(j) = after.iter().position(|next| next == target) {
+                    moves.push((i, j));
+                }
+            }
+        }
+        if moves.iter().any(|(i, j)| i != j) || moves.len() > before.len() {
+            rows.push(transition_row(&moves));
+        }
+
+        self.columns = after;
+        GraphRows {
+            rows,
+            padding: lanes_row(self.columns.len()),
+        }
+    }
+
+    fn commit_row(&self, column: usize) -> String {
+        (0..self.columns.len())
+            .map(|i| if i == column { "*" } else { "|" })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+fn lanes_row(count: usize) -> String {
+    vec!["|"; count].join(" ")
+}
+
+/// Draws lanes moving from column `i` to column `j`: `|` when they stay, `/`
+/// when they move left and `\` when they move right.
+fn transition_row(moves: &[(usize, usize)]) -> String {
+    let width = moves
+        .iter()
+        .map(|&(i, j)| 2 * i.max(j) + 2)
+        .max()
+        .unwrap_or(0);
+    let mut cells = vec![' '; width];
+    for &(i, j) in moves {
+        match j.cmp(&i) {
+            std::cmp::Ordering::Equal => cells[2 * i] = '|',
+            std::cmp::Ordering::Less => cells[2 * i - 1] = '/',
+            std::cmp::Ordering::Greater => cells[2 * i + 1] = '\\',
+        }
+    }
+    cells.into_iter().collect::<String>().trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(n: u8) -> ObjectId {
+        ObjectId::from([n; 20])
+    }
+
+    /// Renders a history given as (commit, parents), children first.
+    fn render(history: &[(u8, &[u8])]) -> Vec<String> {
+        let mut graph = Graph::new();
+        let mut out = Vec::new();
+        for (commit, parents) in history {
+            let parents: Vec<ObjectId> = parents.iter().map(|&p| id(p)).collect();
+            let rows = graph.next(&id(*commit), &parents);
+            out.extend(rows.decorate(&[&commit.to_string()]));
+        }
+        out
+    }
+
+    #[test]
+    fn test_linear_history() {
+        assert_eq!(
+            render(&[(3, &[2]), (2, &[1]), (1, &[])]),
+            ["* 3", "* 2", "* 1"]
+        );
+    }
+
+    #[test]
+    fn test_merge_and_fork() {
+        //   5 merges 4 and 3, which both come from 2.
+        let rows = render(&[(5, &[4, 3]), (4, &[2]), (3, &[2]), (2, &[1]), (1, &[])]);
+        assert_eq!(rows, ["*   5", "|\\", "* | 4", "| * 3", "|/", "* 2", "* 1"]);
+    }
+}
@@ -1,19 +1,712 @@
-use std::collections::HashMap;
+//! The index (`.xit/index`): the staged snapshot of the next commit, stored in
+//! git's binary "DIRC" format so that git and xit can read each other's index.
+//!
+//! The file is a 12-byte header (`DIRC`, the version and the number of
+//! entries), the entries sorted by path, optional extensions, and the SHA-1 of
+//! everything before it. Each entry records the blob id, the file mode and the
+//! stat data of the file when it was staged. Versions 2, 3 (extended flags) and
+//! 4 (paths compressed against the previous entry) are read and written.
+
+use crate::error::{Error, Result};
+use crate::objects::blob;
+use crate::objects::id::ObjectId;
+use crate::objects::tree::FileMode;
+use crate::repository::lockfile::LockFile;
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::io::{self, BufRead};
 use std::path::Path;
 
-/// Reads the .xit/index file and returns a map of file paths to their blob hashes.
-pub fn read_index(path: &Path) -> io::Result<HashMap<String, String>> {
-    let mut entries = HashMap::new();
-    let file = fs::File::open(path)?;
-    for line in io::BufReader::new(file).lines() {
-        let line = line?;
-        let parts: Vec<&str> = line.splitn(2, ' ').collect();
-        if parts.len() == 2 {
-            // The format is <hash> <path>
-            entries.insert(parts[1].to_string(), parts[0].to_string());
+/// The version new indexes are written in, as git does by default.
+pub const DEFAULT_VERSION: u32 = 2;
+
+const SIGNATURE: &[u8; 4] = b"DIRC";
+const HEADER_LEN: usize = 12;
+const CHECKSUM_LEN: usize = 20;
+/// Size of an entry before its path: ten 32-bit stat fields, the id and flags.
+const ENTRY_FIXED_LEN: usize = 62;
+
+const FLAG_ASSUME_VALID: u16 = 0x8000;
+const FLAG_EXTENDED: u16 = 0x4000;
+const FLAG_STAGE_SHIFT: u16 = 12;
+const FLAG_NAME_MASK: u16 = 0x0fff;
+
+/// What `stat` said about a file when it was staged, truncated to 32 bits as
+/// in git. Used to tell whether a file may have changed without reading it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StatData {
+    pub ctime_s: u32,
+    pub ctime_ns: u32,
+    pub mtime_s: u32,
+    pub mtime_ns: u32,
+    pub dev: u32,
+    pub ino: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u32,
+}
+
+impl StatData {
+    /// Whether a file still looks as it did when this stat data was taken.
+    /// Only the modification time, size and inode are compared; stat data
+    /// that was never filled in matches nothing.
+    pub fn matches(&self, current: &StatData) -> bool {
+        *self != StatData::default()
+            && self.mtime_s == current.mtime_s
+            && self.mtime_ns == current.mtime_ns
+            && self.size == current.size
+            && self.ino == current.ino
+    }
+
+    /// Whether the file was modified no earlier than the index file was
+    /// written (`index_mtime`). Such a file may have changed again within the
+    /// same timestamp after it was staged, so matching stat data proves
+    /// nothing and its content has to be compared.
+    pub fn is_racy(&self, index_mtime: &StatData) -> bool {
+        (self.mtime_s, self.mtime_ns) >= (index_mtime.mtime_s, index_mtime.mtime_ns)
+    }
+
+    #[cfg(unix)]
+    pub fn from_metadata(metadata: &fs::Metadata) -> StatData {
+        use std::os::unix::fs::MetadataExt;
+        StatData {
+            ctime_s: metadata.ctime() as u32,
+            ctime_ns: metadata.ctime_nsec() as u32,
+            mtime_s: metadata.mtime() as u32,
+            mtime_ns: metadata.mtime_nsec() as u32,
+            dev: metadata.dev() as u32,
+            ino: metadata.ino() as u32,
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            size: metadata.len() as u32,
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub fn from_metadata(metadata: &fs::Metadata) -> StatData {
+        let mtime = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+            .unwrap_or_default();
+        StatData {
+            ctime_s: mtime.as_secs() as u32,
+            ctime_ns: mtime.subsec_nanos(),
+            mtime_s: mtime.as_secs() as u32,
+            mtime_ns: mtime.subsec_nanos(),
+            size: metadata.len() as u32,
+            ..StatData::default()
+        }
+    }
+}
+
+/// One staged file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexEntry {
+    /// `/`-separated path relative to the working tree.
+    pub path: String,
+    pub id: ObjectId,
+    pub mode: FileMode,
+    pub stat: StatData,
+    /// 0 for a normal entry, 1 to 3 for the sides of a merge conflict.
+    pub stage: u8,
+    pub assume_valid: bool,
+    /// The second set of flags of version 3 (skip-worktree, intent-to-add).
+    pub extended_flags: u16,
+}
+
+impl IndexEntry {
+    /// An entry without stat data, so that the file is always compared by
+    /// content until it is staged again.
+    pub fn new(path: &str, id: ObjectId, mode: FileMode) -> IndexEntry {
+        IndexEntry {
+            path: path.to_string(),
+            id,
+            mode,
+            stat: StatData::default(),
+            stage: 0,
+            assume_valid: false,
+            extended_flags: 0,
+        }
+    }
+
+    /// An entry for a file just staged from the working tree.
+    pub fn from_metadata(path: &str, id: ObjectId, metadata: &fs::Metadata) -> IndexEntry {
+        IndexEntry {
+            stat: StatData::from_metadata(metadata),
+            ..IndexEntry::new(path, id, file_mode(metadata))
+        }
+    }
+}
+
+/// The mode git records for a file: executable or not, or a symbolic link.
+pub fn file_mode(metadata: &fs::Metadata) -> FileMode {
+    if metadata.file_type().is_symlink() {
+        return FileMode::Symlink;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if metadata.permissions().mode() & 0o111 != 0 {
+            return FileMode::Executable;
+        }
+    }
+    FileMode::Regular
+}
+
+/// The entries of an index file, sorted by path and stage.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Index {
+    pub version: u32,
+    entries: Vec<IndexEntry>,
+}
+
+impl Default for Index {
+    fn default() -> Index {
+        Index {
+            version: DEFAULT_VERSION,
+            entries: Vec::new(),
+        }
+    }
+}
+
+impl Index {
+    /// Reads and verifies an index file.
+    pub fn read(path: &Path) -> Result<Index> {
+        Index::parse(&fs::read(path)?)
+    }
+
+    /// Parses the content of an index file, checking its checksum.
+    pub fn parse(data: &[u8]) -> Result<Index> {
+        let corrupt = |message: &str| Error::CorruptObject(format!("index: {}", message));
+        if data.len() < HEADER_LEN + CHECKSUM_LEN || &data[..4] != SIGNATURE {
+            return Err(corrupt("bad signature"));
+        }
+        let (body, checksum) = data.split_at(data.len() - CHECKSUM_LEN);
+        // git writes a null checksum when index.skipHash is set.
+        if checksum.iter().any(|&b| b != 0) && blob::compute_sha1(body).as_bytes() != checksum {
+            return Err(corrupt("checksum mismatch"));
+        }
+        let version = read_u32(body, 4);
+        if !(2..=4).contains(&version) {
+            return Err(corrupt(&format!("unsupported version {}", version)));
+        }
+        let count = read_u32(body, 8) as usize;
+
+        let mut reader = Reader {
+            data: body,
+            pos: HEADER_LEN,
+        };
+        let mut entries = Vec::with_capacity(count);
+        let mut previous_path: Vec<u8> = Vec::new();
+        for _ in 0..count {
+            let start = reader.pos;
+            let fixed = reader
+                .take(ENTRY_FIXED_LEN)
+                .ok_or_else(|| corrupt("truncated entry"))?;
+            let field = |i: usize| read_u32(fixed, i * 4);
+            let stat = StatData {
+                ctime_s: field(0),
+                ctime_ns: field(1),
+                mtime_s: field(2),
+                mtime_ns: field(3),
+                dev: field(4),
+                ino: field(5),
+                uid: field(7),
+                gid: field(8),
+                size: field(9),
+            };
+            let raw_mode = field(6);
+            let mode = FileMode::parse(&format!("{:o}", raw_mode))
+                .ok_or_else(|| corrupt(&format!("invalid mode {:o}", raw_mode)))?;
+            let id = ObjectId::from_bytes(&fixed[40..60])?;
+            let flags = u16::from_be_bytes([fixed[60], fixed[61]]);
+            let extended_flags = if flags & FLAG_EXTENDED != 0 {
+                if version < 3 {
+                    return Err(corrupt("extended flags in a version 2 index"));
+                }
+                let bytes = reader.take(2).ok_or_else(|| corrupt("truncated entry"))?;
+                u16::from_be_bytes([bytes[0], bytes[1]])
+            } else {
+                0
+            };
+
+            let path = if version == 4 {
+                let strip = reader.varint().ok_or_else(|| corrupt("truncated path"))?;
+                let keep = previous_path
+                    .len()
+                    .checked_sub(strip)
+                    .ok_or_else(|| corrupt("invalid path compression"))?;
+                let suffix = reader
+                    .until_nul()
+                    .ok_or_else(|| corrupt("truncated path"))?;
+                let mut path = previous_path[..keep].to_vec();
+                path.extend_from_slice(suffix);
+                path
+            } else {
+                let path = reader
+                    .until_nul()
+                    .ok_or_else(|| corrupt("truncated path"))?
+                    .to_vec();
+                // Entries are padded with NULs to a multiple of 8 bytes.
+                let len = reader.pos - start;
+                reader
+                    .take((8 - len % 8) % 8)
+                    .ok_or_else(|| corrupt("truncated entry"))?;
+                path
+            };
+            let path_str =
+                String::from_utf8(path.clone()).map_err(|_| corrupt("path is not valid UTF-8"))?;
+            previous_path = path;
+
+            entries.push(IndexEntry {
+                path: path_str,
+                id,
+                mode,
+                stat,
+                stage: ((flags >> FLAG_STAGE_SHIFT) & 0x3) as u8,
+                assume_valid: flags & FLAG_ASSUME_VALID != 0,
+                extended_flags,
+            });
+        }
+
+        // Extensions (cached trees, resolve-undo, ...) only speed git up or are
+        // rebuilt by it; an uppercase signature marks them as safe to ignore.
+        while reader.pos < body.len() {
+            let header = reader
+                .take(8)
+                .ok_or_else(|| corrupt("truncated extension"))?;
+            let signature = &header[..4];
+            let size = read_u32(header, 4) as usize;
+            if !signature[0].is_ascii_uppercase() {
+                return Err(corrupt(&format!(
+                    "unsupported extension '{}'",
+                    String::from_utf8_lossy(signature)
+                )));
+            }
+            reader
+                .take(size)
+                .ok_or_else(|| corrupt("truncated extension"))?;
+        }
+
+        let mut index = Index { version, entries };
+        if !index.is_sorted() {
+            index.entries.sort_by(compare);
+        }
+        Ok(index)
+    }
+
+    /// The content of the index file. Extensions are not written; git
+    /// rebuilds them when it needs them.
+    pub fn serialize(&self) -> Vec<u8> {
+        // Extended flags need version 3 at least.
+        let mut version = self.version;
+        if version < 3 && self.entries.iter().any(|entry| entry.extended_flags != 0) {
+            version = 3;
+        }
+        let mut out = Vec::new();
+        out.extend_from_slice(SIGNATURE);
+        out.extend_from_slice(&version.to_be_bytes());
+        out.extend_from_slice(&(self.entries.len() as u32).to_be_bytes());
+
+        let mut previous_path: &[u8] = &[];
+        for entry in &self.entries {
+            let start = out.len();
+            let stat = &entry.stat;
+            let mode = u32::from_str_radix(entry.mode.as_str(), 8).expect("modes are octal");
+            for field in [
+                stat.ctime_s,
+                stat.ctime_ns,
+                stat.mtime_s,
+                stat.mtime_ns,
+                stat.dev,
+                stat.ino,
+                mode,
+                stat.uid,
+                stat.gid,
+                stat.size,
+            ] {
+                out.extend_from_slice(&field.to_be_bytes());
+            }
+            out.extend_from_slice(entry.id.as_bytes());
+
+            let path = entry.path.as_bytes();
+            let mut flags = (path.len().min(FLAG_NAME_MASK as usize) as u16)
+                | ((entry.stage as u16 & 0x3) << FLAG_STAGE_SHIFT);
+            if entry.assume_valid {
+                flags |= FLAG_ASSUME_VALID;
+            }
+            if entry.extended_flags != 0 {
+                flags |= FLAG_EXTENDED;
+            }
+            out.extend_from_slice(&flags.to_be_bytes());
+            if entry.extended_flags != 0 {
+                out.extend_from_slice(&entry.extended_flags.to_be_bytes());
+            }
+
+            if version == 4 {
+                let common = previous_path
+                    .iter()
+                    .zip(path)
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                write_varint(&mut out, previous_path.len() - common);
+                out.extend_from_slice(&path[common..]);
+                out.push(0);
+            } else {
+                out.extend_from_slice(path);
+                // At least one NUL, then padding to a multiple of 8 bytes.
+                let len = out.len() - start;
+                out.resize(start + (len + 8) / 8 * 8, 0);
+            }
+            previous_path = path;
+        }
+
+        let checksum = blob::compute_sha1(&out);
+        out.extend_from_slice(checksum.as_bytes());
+        out
+    }
+
+    /// Replaces the index file through `index.lock`, so that concurrent xit
+    /// processes cannot both change it and readers never see half a file.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        self.commit(acquire_lock(path)?)
+    }
+
+    /// Locks the index file and reads it (an empty index if there is none),
+    /// so that it can be changed and written back with [`Index::commit`]
+    /// without losing another process's changes.
+    pub fn lock(path: &Path) -> Result<(Index, LockFile)> {
+        let lock = acquire_lock(path)?;
+        let index = if path.exists() {
+            Index::read(path)?
+        } else {
+            Index::default()
+        };
+        Ok((index, lock))
+    }
+
+    /// Replaces the index file through the lock taken by [`Index::lock`].
+    ///
+    /// Entries modified no earlier than the old index file or the lock were
+    /// written are smudged: their cached size is zeroed, as git does, so
+    /// that a change within the same timestamp is not hidden once the new
+    /// index file has a later mtime than the file.
+    pub fn commit(&self, mut lock: LockFile) -> Result<()> {
+        // The new index file is at least as recent as the lock.
+        let mut racy_since = StatData::from_metadata(&lock.metadata()?);
+        if let Ok(metadata) = fs::metadata(lock.path()) {
+            let old = StatData::from_metadata(&metadata);
+            if (old.mtime_s, old.mtime_ns) < (racy_since.mtime_s, racy_since.mtime_ns) {
+                racy_since = old;
+            }
+        }
+        let mut index = self.clone();
+        for entry in &mut index.entries {
+            if entry.stat != StatData::default() && entry.stat.is_racy(&racy_since) {
+                entry.stat.size = 0;
+            }
+        }
+        lock.write_all(&index.serialize())?;
+        lock.commit()
+    }
+
+    /// The entries, sorted by path and stage.
+    pub fn entries(&self) -> &[IndexEntry] {
+        &self.entries
+    }
+
+    /// The normal (stage 0) entry for `path`.
+    pub fn get(&self, path: &str) -> Option<&IndexEntry> {
+        self.entries
+            .binary_search_by(|entry| compare_key(entry, path, 0))
+            .ok()
+            .map(|i| &self.entries[i])
+    }
+
+    /// Adds or replaces an entry. Staging a normal entry resolves a conflict,
+    /// so the other stages of the path are dropped.
+    pub fn add(&mut self, entry: IndexEntry) {
+        if entry.stage == 0 {
+            self.remove(&entry.path);
+        }
+        match self
+            .entries
+            .binary_search_by(|other| compare_key(other, &entry.path, entry.stage))
+        {
+            Ok(i) => self.entries[i] = entry,
+            Err(i) => self.entries.insert(i, entry),
+        }
+    }
+
+    /// Adds or replaces many normal entries at once, sorting only once. When a
+    /// path comes more than once, the last entry wins.
+    pub fn add_all(&mut self, entries: Vec<IndexEntry>) {
+        let mut seen = HashSet::new();
+        let mut added: Vec<IndexEntry> = entries
+            .into_iter()
+            .rev()
+            .filter(|entry| seen.insert(entry.path.clone()))
+            .collect();
+        self.entries.retain(|entry| !seen.contains(&entry.path));
+        self.entries.append(&mut added);
+        self.entries.sort_by(compare);
+    }
+
+    /// Records new stat data for `path` if it is still staged as `id`.
+    /// Returns whether it was.
+    pub fn update_stat(&mut self, path: &str, id: &ObjectId, stat: StatData) -> bool {
+        match self
+            .entries
+            .binary_search_by(|entry| compare_key(entry, path, 0))
+        {
+            Ok(i) if self.entries[i].id == *id => {
+                self.entries[i].stat = stat;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Removes every stage of `path`. Returns whether there was any.
+    pub fn remove(&mut self, path: &str) -> bool {
+        let before = self.entries.len();
+        self.entries.retain(|entry| entry.path != path);
+        self.entries.len() != before
+    }
+
+    /// The normal entries as a map from path to blob id and mode.
+    pub fn to_map(&self) -> HashMap<String, (ObjectId, FileMode)> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.stage == 0)
+            .map(|entry| (entry.path.clone(), (entry.id, entry.mode)))
+            .collect()
+    }
+
+    fn is_sorted(&self) -> bool {
+        self.entries
+            .windows(2)
+            .all(|pair| compare(&pair[0], &pair[1]).is_lt())
+    }
+}
+
+/// Reads the index file and returns a map of file paths to their blob hashes
+/// and modes.
+pub fn read_index(path: &Path) -> Result<HashMap<String, (ObjectId, FileMode)>> {
+    Ok(Index::read(path)?.to_map())
+}
+
+/// Replaces the index file with the given entries. Entries whose blob and
+/// mode did not change keep their stat data; the others are recorded without
+/// stat data, so their content is checked on the next status.
+pub fn write_index(path: &Path, entries: &HashMap<String, (ObjectId, FileMode)>) -> Result<()> {
+    let (previous, lock) = Index::lock(path)?;
+    let mut index = Index {
+        version: previous.version,
+        entries: Vec::with_capacity(entries.len()),
+    };
+    for (file, &(id, mode)) in entries {
+        let entry = match previous.get(file) {
+            Some(entry) if entry.id == id && entry.mode == mode => entry.clone(),
+            _ => IndexEntry::new(file, id, mode),
+        };
+        index.entries.push(entry);
+    }
+    index.entries.sort_by(compare);
+    index.commit(lock)
+}
+
+/// Takes `index.lock`, reporting a held lock as [`Error::IndexLocked`].
+fn acquire_lock(path: &Path) -> Result<LockFile> {
+    LockFile::acquire(path).map_err(|e| match e {
+        Error::Conflict(_) => {
+            let mut lock_path = path.as_os_str().to_owned();
+            lock_path.push(".lock");
+            Error::IndexLocked(lock_path.into())
+        }
+        e => e,
+    })
+}
+
+/// git's order: by path bytes, then by stage.
+fn compare(a: &IndexEntry, b: &IndexEntry) -> std::cmp::Ordering {
+    compare_key(a, &b.path, b.stage)
+}
+
+fn compare_key(entry: &IndexEntry, path: &str, stage: u8) -> std::cmp::Ordering {
+    entry
+        .path
+        .as_bytes()
+        .cmp(path.as_bytes())
+        .then(entry.stage.cmp(&stage))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes(
+        data[offset..offset + 4]
+            .try_into()
+            .expect("slice of 4 bytes"),
+    )
+}
+
+/// git's variable-length integers for version 4 path compression: 7 bits per
+/// byte, most significant first, with an offset that makes encodings unique.
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    let mut bytes = vec![(value & 0x7f) as u8];
+    value >>= 7;
+    while value != 0 {
+        value -= 1;
+        bytes.push(0x80 | (value & 0x7f) as u8);
+        value >>= 7;
+    }
+    out.extend(bytes.iter().rev());
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(bytes)
+    }
+
+    /// The bytes up to the next NUL, which is consumed too.
+    fn until_nul(&mut self) -> Option<&'a [u8]> {
+        let len = self.data.get(self.pos..)?.iter().position(|&b| b == 0)?;
+        let bytes = self.take(len)?;
+        self.pos += 1;
+        Some(bytes)
+    }
+
+    fn varint(&mut self) -> Option<usize> {
+        let mut byte = self.take(1)?[0];
+        let mut value = (byte & 0x7f) as usize;
+        while byte & 0x80 != 0 {
+            byte = self.take(1)?[0];
+            value = value
+                .checked_add(1)?
+                .checked_mul(128)?
+                .checked_add((byte & 0x7f) as usize)?;
+        }
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Index {
+        let mut index = Index::default();
+        let stat = StatData {
+            ctime_s: 1_700_000_000,
+            ctime_ns: 5,
+            mtime_s: 1_700_000_001,
+            mtime_ns: 6,
+            dev: 7,
+            ino: 8,
+            uid: 1000,
+            gid: 1000,
+            size: 3,
+        };
+        for (i, path) in ["src/main.rs", "a\nb.txt", "src/lib.rs", "README.md"]
+            .into_iter()
+            .enumerate()
+        {
+            let mut entry =
+                IndexEntry::new(path, ObjectId::from([i as u8 + 1; 20]), FileMode::Regular);
+            entry.stat = stat;
+            index.add(entry);
+        }
+        index.add(IndexEntry::new(
+            "run.sh",
+            ObjectId::from([9u8; 20]),
+            FileMode::Executable,
+        ));
+        index
+    }
+
+    #[test]
+    fn test_entries_are_sorted() {
+        let index = sample();
+        let paths: Vec<&str> = index.entries().iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            [
+                "README.md",
+                "a\nb.txt",
+                "run.sh",
+                "src/lib.rs",
+                "src/main.rs"
+            ]
+        );
+        assert_eq!(
+            index.get("src/lib.rs").unwrap().id,
+            ObjectId::from([3u8; 20])
+        );
+        assert!(index.get("src").is_none());
+
+        let mut bulk = Index::default();
+        bulk.add_all(sample().entries().iter().rev().cloned().collect());
+        let changed = IndexEntry::new("run.sh", ObjectId::from([7u8; 20]), FileMode::Regular);
+        bulk.add_all(vec![changed.clone()]);
+        assert_eq!(bulk.entries().len(), index.entries().len());
+        assert_eq!(bulk.get("run.sh"), Some(&changed));
+        assert_eq!(bulk.entries()[0].path, "README.md");
+    }
+
+    #[test]
+    fn test_round_trip_all_versions() {
+        for version in 2..=4 {
+            let mut index = sample();
+            index.version = version;
+            if version == 3 {
+                let mut entry = index.get("run.sh").unwrap().clone();
+                entry.extended_flags = 0x4000;
+                index.add(entry);
+            }
+            let data = index.serialize();
+            assert_eq!(&data[..4], b"DIRC");
+            assert_eq!(read_u32(&data, 4), version);
+            if version != 4 {
+                // Entries are padded to 8 bytes: 62 + 9 ("README.md") + 1 NUL -> 72.
+                assert_eq!(data[HEADER_LEN + 62 + 9], 0);
+            }
+            assert_eq!(Index::parse(&data).unwrap(), index, "version {}", version);
+        }
+        // Extended flags force version 3.
+        let mut index = sample();
+        let mut entry = index.get("run.sh").unwrap().clone();
+        entry.extended_flags = 0x2000;
+        index.add(entry);
+        assert_eq!(Index::parse(&index.serialize()).unwrap().version, 3);
+    }
+
+    #[test]
+    fn test_rejects_corruption() {
+        let mut data = sample().serialize();
+        let last = data.len() - CHECKSUM_LEN - 1;
+        data[last] ^= 1;
+        assert!(Index::parse(&data).is_err());
+        assert!(Index::parse(b"not an index").is_err());
+    }
+
+    #[test]
+    fn test_varint() {
+        for value in [0, 1, 127, 128, 255, 16_511, 16_512, 1 << 20] {
+            let mut out = Vec::new();
+            write_varint(&mut out, value);
+            let mut reader = Reader { data: &out, pos: 0 };
+            assert_eq!(reader.varint(), Some(value));
+            assert_eq!(reader.pos, out.len());
         }
+        // Same encoding as git: 128 is 0x80 0x00.
+        let mut out = Vec::new();
+        write_varint(&mut out, 128);
+        assert_eq!(out, [0x80, 0x00]);
     }
-    Ok(entries)
-}
\ No newline at end of file
+}
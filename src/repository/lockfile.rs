@@ -0,0 +1,141 @@
+//! Lock files, the way git replaces files safely: the new content is written to
+//! `<file>.lock`, created exclusively so that only one process can hold it, and
+//! then renamed over the file. Readers see either the old or the new content,
+//! never a truncated file. A lock that is dropped without being committed is
+//! removed and the file stays untouched.
+
+use crate::error::{Error, Result};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub struct LockFile {
+    path: PathBuf,
+    lock_path: PathBuf,
+    file: Option<File>,
+}
+
+impl LockFile {
+    /// Takes the lock on `path` by creating `<path>.lock`. Fails with a
+    /// conflict if another process holds it.
+    pub fn acquire(path: &Path) -> Result<LockFile> {
+        let mut lock_path = path.as_os_str().to_owned();
+        lock_path.push(".lock");
+        let lock_path = PathBuf::from(lock_path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&lock_path)
+        {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                return Err(Error::Conflict(format!(
+                    "Unable to create '{}': File exists.\n\
+                     Another xit process seems to be running in this repository. \
+                     If it crashed, remove the file and try again.",
+                    lock_path.display()
+                )));
+            }
+            Err(e) => return Err(e.into()),
+        };
+        Ok(LockFile {
+            path: path.to_path_buf(),
+            lock_path,
+            file: Some(file),
+        })
+    }
+
+    /// The file this lock protects.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The metadata of the lock file, which becomes the file's on commit.
+    pub fn metadata(&self) -> Result<fs::Metadata> {
+        let file = self
+            .file
+            .as_ref()
+            .expect("lock file is open until committed");
+        Ok(file.metadata()?)
+    }
+
+    /// Appends to the new content.
+    pub fn write_all(&mut self, bytes: &[u8]) -> Result<()> {
+        let file = self
+            .file
+            .as_mut()
+            .expect("lock file is open until committed");
+        file.write_all(bytes)?;
+        Ok(())
+    }
+
+    /// Replaces the file with what was written and releases the lock.
+    pub fn commit(mut self) -> Result<()> {
+        if let Some(file) = &self.file {
+            file.sync_all()?;
+        }
+        self.file = None;
+        if let Err(e) = fs::rename(&self.lock_path, &self.path) {
+            let _ = fs::remove_file(&self.lock_path);
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    /// Deletes the file instead of replacing it, then releases the lock.
+    pub fn commit_delete(self) -> Result<()> {
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+        // Dropping `self` removes the lock file.
+    }
+}
+
+impl Drop for LockFile {
+    fn drop(&mut self) {
+        // After `commit` the lock file has become the file itself.
+        if self.file.is_some() {
+            self.file = None;
+            let _ = fs::remove_file(&self.lock_path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_commit_and_rollback() {
+        let dir = std::env::temp_dir().join("xit_test_lockfile");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("refs/heads/main");
+
+        let mut lock = LockFile::acquire(&path).unwrap();
+        assert!(matches!(LockFile::acquire(&path), Err(Error::Conflict(_))));
+        lock.write_all(b"one\n").unwrap();
+        assert!(!path.exists());
+        lock.commit().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "one\n");
+        assert!(!dir.join("refs/heads/main.lock").exists());
+
+        // A dropped lock leaves the file as it was.
+        let mut lock = LockFile::acquire(&path).unwrap();
+        lock.write_all(b"two\n").unwrap();
+        drop(lock);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "one\n");
+        assert!(!dir.join("refs/heads/main.lock").exists());
+
+        LockFile::acquire(&path).unwrap().commit_delete().unwrap();
+        assert!(!path.exists());
+        assert!(!dir.join("refs/heads/main.lock").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,522 @@
+use crate::error::Result;
+use crate::objects::commit::Commit;
+use crate::objects::id::ObjectId;
+use crate::objects::object::Object;
+use crate::objects::read as object_read;
+use crate::objects::tree::FileMode;
+use crate::repository::date;
+use crate::repository::repo::Repository;
+use crate::repository::revwalk::RevWalk;
+use std::collections::HashMap;
+use term_colr::{blue, green, yellow};
+
+/// How each commit is printed by `xit log`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    /// Hash, author, date and the full message (git's default).
+    #[default]
+    Medium,
+    /// Abbreviated hash and subject on one line.
+    Oneline,
+    /// Hash, author and subject.
+    Short,
+    /// Hash, author, committer and the full message.
+    Full,
+    /// A `--format` template with `%` placeholders.
+    Template(String),
+}
+
+impl LogFormat {
+    /// Parses a `--format` value: a named format or a template, optionally
+    /// prefixed with `format:` or `tformat:`.
+    pub fn parse(value: &str) -> LogFormat {
+        match value {
+            "medium" => LogFormat::Medium,
+            "oneline" => LogFormat::Oneline,
+            "short" => LogFormat::Short,
+            "full" => LogFormat::Full,
+            _ => {
+                let template = value
+                    .strip_prefix("format:")
+                    .or_else(|| value.strip_prefix("tformat:"))
+                    .unwrap_or(value);
+                LogFormat::Template(template.to_string())
+            }
+        }
+    }
+}
+
+/// Which commits `xit log` shows.
+#[derive(Debug, Clone, Default)]
+pub struct LogOptions {
+    /// Stop after this many commits.
+    pub max_count: Option<usize>,
+    /// Only commits whose author (`Name <email>`) contains this text.
+    pub author: Option<String>,
+    /// Only commits committed at or after this time.
+    pub since: Option<i64>,
+    /// Only commits committed at or before this time.
+    pub until: Option<i64>,
+    /// Only commits changing a file at or below one of these paths.
+    pub paths: Vec<String>,
+    /// Output the selected commits oldest first.
+    pub reverse: bool,
+    /// Only follow the first parent of merges.
+    pub first_parent: bool,
+    /// Never show a parent before its children (implied by `--graph`).
+    pub topo_order: bool,
+}
+
+/// Walks history from `starts`, skipping everything reachable from `hidden`,
+/// and returns the commits selected by `options` in display order.
+pub fn log(
+    repo: &Repository,
+    starts: &[ObjectId],
+    hidden: &[ObjectId],
+    options: &LogOptions,
+) -> Result<Vec<(ObjectId, Commit)>> {
+    let mut walk = RevWalk::new(repo)
+        .first_parent(options.first_parent)
+        .topo_order(options.topo_order);
+    for id in hidden {
+        walk.hide(*id)?;
+    }
+    for id in starts {
+        walk.push(*id)?;
+    }
+
+    let mut commits = Vec::new();
+    for item in walk {
+        if options.max_count.is_some_and(|max| commits.len() >= max) {
+            break;
+        }
+        let (id, commit) = item?;
+        if matches(repo, &commit, options)? {
+            commits.push((id, commit));
+        }
+    }
+    if options.reverse {
+        commits.reverse();
+    }
+    Ok(commits)
+}
+
+fn matches(repo: &Repository, commit: &Commit, options: &LogOptions) -> Result<bool> {
+    if let Some(author) = &options.author {
+        let identity = format!("{} <{}>", commit.author.name, commit.author.email);
+        if !identity.contains(author.as_str()) {
+            return Ok(false);
+        }
+    }
+    let time = commit.committer.time;
+    if options.since.is_some_and(|since| time < since)
+        || options.until.is_some_and(|until| time > until)
+    {
+        return Ok(false);
+    }
+    if options.paths.is_empty() {
+        return Ok(true);
+    }
+    touches_paths(repo, commit, &options.paths, options.first_parent)
+}
+
+/// Whether the commit changes a file under `paths` compared with its parents.
+/// A merge only counts when it differs from every parent, so changes brought in
+/// from a branch are reported on the branch commit rather than on the merge.
+fn touches_paths(
+    repo: &Repository,
+    commit: &Commit,
+    paths: &[String],
+    first_parent: bool,
+) -> Result<bool> {
+    let files = filtered_files(repo, &commit.tree, paths)?;
+    if commit.parents.is_empty() {
+        return Ok(!files.is_empty());
+    }
+    let parents = if first_parent {
+        &commit.parents[..1]
+    } else {
+        &commit.parents[..]
+    };
+    for parent in parents {
+        let tree = repo.read_commit(parent)?.tree;
+        if filtered_files(repo, &tree, paths)? == files {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+fn filtered_files(
+    repo: &Repository,
+    tree: &ObjectId,
+    paths: &[String],
+) -> Result<HashMap<String, (ObjectId, FileMode)>> {
+    let mut files = object_read::list_files_in_tree(repo.git_dir(), tree)?;
+    files.retain(|file, _| paths.iter().any(|path| path_matches(path, file)));
+    Ok(files)
+}
+
+/// Whether `file` is `path` itself or lies in the directory `path`.
+fn path_matches(path: &str, file: &str) -> bool {
+    let path = path.trim_end_matches('/');
+    path.is_empty()
+        || path == "."
+        || file == path
+        || file
+            .strip_prefix(path)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// Renders commits for `xit log`.
+#[derive(Debug, Clone, Default)]
+pub struct CommitFormatter {
+    pub format: LogFormat,
+    pub color: bool,
+    /// The current time, for relative dates.
+    pub now: i64,
+    /// Ref names shown next to the commits they point at; see [`decorations`].
+    pub decorations: HashMap<ObjectId, Vec<String>>,
+}
+
+impl CommitFormatter {
+    /// Renders one commit, without a final newline.
+    pub fn format(&self, id: &ObjectId, commit: &Commit) -> String {
+        let paint_hash = |hash: String| {
+            if self.color {
+                yellow!("{}", hash)
+            } else {
+                hash
+            }
+        };
+        let decoration = self
+            .decoration(id)
+            .map(|names| format!(" {}", names))
+            .unwrap_or_default();
+
+        match &self.format {
+            LogFormat::Oneline => format!(
+                "{}{} {}",
+                paint_hash(id.short()),
+                decoration,
+                subject(commit)
+            ),
+            LogFormat::Template(template) => self.expand_template(template, id, commit),
+            format => {
+                let mut out = format!("{}{}\n", paint_hash(format!("commit {}", id)), decoration);
+                if commit.parents.len() > 1 {
+                    let parents: Vec<String> = commit.parents.iter().map(ObjectId::short).collect();
+                    out.push_str(&format!("Merge: {}\n", parents.join(" ")));
+                }
+                out.push_str(&format!(
+                    "Author: {} <{}>\n",
+                    commit.author.name, commit.author.email
+                ));
+                match format {
+                    LogFormat::Medium => out.push_str(&format!(
+                        "Date:   {}\n",
+                        date::format_date(commit.author.time, commit.author.offset_minutes)
+                    )),
+                    LogFormat::Full => out.push_str(&format!(
+                        "Commit: {} <{}>\n",
+                        commit.committer.name, commit.committer.email
+                    )),
+                    _ => {}
+                }
+                out.push('\n');
+
+                let text = if *format == LogFormat::Short {
+                    subject(commit)
+                } else {
+                    commit.message.trim_end().to_string()
+                };
+                let lines: Vec<String> = text.lines().map(|line| format!("    {}", line)).collect();
+                out.push_str(&lines.join("\n"));
+                out
+            }
+        }
+    }
+
+    /// The lines printed between two formatted commits.
+    pub fn separator(&self) -> &'static str {
+        match self.format {
+            LogFormat::Medium | LogFormat::Short | LogFormat::Full => "\n",
+            LogFormat::Oneline | LogFormat::Template(_) => "",
+        }
+    }
+
+    /// The ref names pointing at `id`, as `(HEAD -> main, tag: v1.0)`.
+    fn decoration(&self, id: &ObjectId) -> Option<String> {
+        let names = self.decorations.get(id)?;
+        if !self.color {
+            return Some(format!("({})", names.join(", ")));
+        }
+        let painted: Vec<String> = names
+            .iter()
+            .map(|name| {
+                if let Some(branch) = name.strip_prefix("HEAD -> ") {
+                    format!("{} {}", blue!("HEAD ->"), green!("{}", branch))
+                } else if name == "HEAD" {
+                    blue!("HEAD")
+                } else if name.starts_with("tag: ") {
+                    yellow!("{}", name)
+                } else {
+                    green!("{}", name)
+                }
+            })
+            .collect();
+        Some(format!(
+            "{}{}{}",
+            yellow!("("),
+            painted.join(&yellow!(", ")),
+            yellow!(")")
+        ))
+    }
+
+    /// Expands the `%` placeholders of a `--format` template.
+    fn expand_template(&self, template: &str, id: &ObjectId, commit: &Commit) -> String {
+        let mut out = String::new();
+        let mut rest = template;
+        while let Some(pos) = rest.find('%') {
+            out.push_str(&rest[..pos]);
+            rest = &rest[pos + 1..];
+
+            let (author, committer) = (&commit.author, &commit.committer);
+            let join = |ids: Vec<String>| ids.join(" ");
+            let names = self.decorations.get(id).map(|names| names.join(", "));
+            let (value, used) = match rest.as_bytes() {
+                [b'%', ..] => ("%".to_string(), 1),
+                [b'n', ..] => ("\n".to_string(), 1),
+                [b'H', ..] => (id.to_hex(), 1),
+                [b'h', ..] => (id.short(), 1),
+                [b'T', ..] => (commit.tree.to_hex(), 1),
+                [b't', ..] => (commit.tree.short(), 1),
+                [b'P', ..] => (join(commit.parents.iter().map(|p| p.to_hex()).collect()), 1),
+                [b'p', ..] => (
+                    join(commit.parents.iter().map(ObjectId::short).collect()),
+                    1,
+                ),
+                [b'd', ..] => (names.map(|n| format!(" ({})", n)).unwrap_or_default(), 1),
+                [b'D', ..] => (names.unwrap_or_default(), 1),
+                [b's', ..] => (subject(commit), 1),
+                [b'b', ..] => (body(commit), 1),
+                [b'B', ..] => (commit.message.clone(), 1),
+                [who @ (b'a' | b'c'), field, ..] => {
+                    let person = if *who == b'a' { author } else { committer };
+                    let value = match field {
+                        b'n' => Some(person.name.clone()),
+                        b'e' => Some(person.email.clone()),
+                        b'd' => Some(date::format_date(person.time, person.offset_minutes)),
+                        b'r' => Some(date::format_relative(person.time, self.now)),
+                        b't' => Some(person.time.to_string()),
+                        b's' => Some(date::format_short(person.time, person.offset_minutes)),
+                        _ => None,
+                    };
+                    match value {
+                        Some(value) => (value, 2),
+                        None => ("%".to_string(), 0),
+                    }
+                }
+                // Unknown placeholders are printed as they are.
+                _ => ("%".to_string(), 0),
+            };
+            out.push_str(&value);
+            rest = &rest[used..];
+        }
+        out.push_str(rest);
+        out
+    }
+}
+
+/// The branches and tags pointing at each commit, in the order git shows them:
+/// `HEAD -> <branch>` (or a detached `HEAD`), then tags, then other branches.
+pub fn decorations(repo: &Repository) -> Result<HashMap<ObjectId, Vec<String>>> {
+    let store = repo.refs();
+    let current_branch = store.current_branch()?;
+    let current_branch = current_branch.as_deref();
+
+    let mut decorations: HashMap<ObjectId, Vec<String>> = HashMap::new();
+    if let Some(id) = store.resolve("HEAD")? {
+        let label = match current_branch {
+            Some(branch) => format!("HEAD -> {}", branch),
+            None => "HEAD".to_string(),
+        };
+        decorations.entry(id).or_default().push(label);
+    }
+    for (name, id) in store.list("refs/tags/")? {
+        decorations
+            .entry(peel(repo, id)?)
+            .or_default()
+            .push(format!("tag: {}", &name["refs/tags/".len()..]));
+    }
+    for (name, id) in store.list("refs/heads/")? {
+        let branch = &name["refs/heads/".len()..];
+        if Some(branch) != current_branch {
+            decorations.entry(id).or_default().push(branch.to_string());
+        }
+    }
+    Ok(decorations)
+}
+
+/// Follows annotated tags down to the object they finally point at.
+fn peel(repo: &Repository, mut id: ObjectId) -> Result<ObjectId> {
+    while let Object::Tag(tag) = repo.read_parsed(&id)? {
+        id = tag.object;
+    }
+    Ok(id)
+}
+
+/// The first paragraph of the message, joined into one line.
+pub fn subject(commit: &Commit) -> String {
+    let paragraph = commit
+        .message
+        .trim_start()
+        .split("\n\n")
+        .next()
+        .unwrap_or("");
+    paragraph
+        .lines()
+        .map(str::trim)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Everything after the first paragraph of the message.
+fn body(commit: &Commit) -> String {
+    match commit.message.trim_start().split_once("\n\n") {
+        Some((_, body)) => body.trim_start_matches('\n').to_string(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::signature::Signature;
+    use crate::objects::update;
+    use crate::repository::test_utils::test_repo;
+    use std::fs;
+
+    fn sample_commit() -> Commit {
+        let signature = Signature::parse("Ann Author <ann@example.com> 1700000000 +0000").unwrap();
+        Commit {
+            tree: ObjectId::from([1u8; 20]),
+            parents: vec![ObjectId::from([2u8; 20]), ObjectId::from([3u8; 20])],
+            author: signature.clone(),
+            committer: signature,
+            extra_headers: Vec::new(),
+            message: "Subject line\n\nFirst body line\n\nSecond\n".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_format_commit() {
+        let commit = sample_commit();
+        let id = ObjectId::from([0xab; 20]);
+        let mut formatter = CommitFormatter {
+            format: LogFormat::Oneline,
+            now: 1700000000 + 7200,
+            ..CommitFormatter::default()
+        };
+
+        assert_eq!(formatter.format(&id, &commit), "abababa Subject line");
+        formatter.format = LogFormat::Medium;
+        assert_eq!(
+            formatter.format(&id, &commit),
+            format!(
+                "commit {}\nMerge: 0202020 0303030\nAuthor: Ann Author <ann@example.com>\n\
+Date:   Tue Nov 14 22:13:20 2023 +0000\n\n    Subject line\n    \n    First body line\n    \n    Second",
+                id
+            )
+        );
+        formatter.format = LogFormat::parse("format:%h %an <%ae> %ar%n%s|%b|%x %%");
+        assert_eq!(
+            formatter.format(&id, &commit),
+            "abababa Ann Author <ann@example.com> 2 hours ago\nSubject line|First body line\n\nSecond\n|%x %"
+        );
+
+        formatter
+            .decorations
+            .insert(id, vec!["HEAD -> main".to_string(), "tag: v1".to_string()]);
+        formatter.format = LogFormat::Oneline;
+        assert_eq!(
+            formatter.format(&id, &commit),
+            "abababa (HEAD -> main, tag: v1) Subject line"
+        );
+        formatter.format = LogFormat::parse("%h%d|%D");
+        assert_eq!(
+            formatter.format(&id, &commit),
+            "abababa (HEAD -> main, tag: v1)|HEAD -> main, tag: v1"
+        );
+    }
+
+    #[test]
+    fn test_log_filters() {
+        let (repo, temp_dir) = test_repo("xit_test_log");
+        fs::create_dir_all(temp_dir.join("src")).unwrap();
+
+        fs::write(temp_dir.join("README"), "readme").unwrap();
+        repo.add("README").unwrap();
+        let first = repo.commit("Add readme").unwrap().id;
+        fs::write(temp_dir.join("src/main.rs"), "fn main() {}").unwrap();
+        repo.add("README").unwrap();
+        repo.add("src/main.rs").unwrap();
+        let second = repo.commit("Add main").unwrap().id;
+        fs::write(temp_dir.join("README"), "readme v2").unwrap();
+        repo.add("README").unwrap();
+        repo.add("src/main.rs").unwrap();
+        let third = repo.commit("Update readme").unwrap().id;
+
+        let ids = |options: &LogOptions| -> Vec<ObjectId> {
+            log(&repo, &[third], &[], options)
+                .unwrap()
+                .into_iter()
+                .map(|(id, _)| id)
+                .collect()
+        };
+
+        assert_eq!(ids(&LogOptions::default()), vec![third, second, first]);
+        let options = LogOptions {
+            paths: vec!["src".to_string()],
+            ..LogOptions::default()
+        };
+        assert_eq!(ids(&options), vec![second]);
+        let options = LogOptions {
+            paths: vec!["README".to_string()],
+            reverse: true,
+            ..LogOptions::default()
+        };
+        assert_eq!(ids(&options), vec![first, third]);
+        let options = LogOptions {
+            max_count: Some(1),
+            ..LogOptions::default()
+        };
+        assert_eq!(ids(&options), vec![third]);
+        let options = LogOptions {
+            author: Some("nobody".to_string()),
+            ..LogOptions::default()
+        };
+        assert!(ids(&options).is_empty());
+        let options = LogOptions {
+            until: Some(0),
+            ..LogOptions::default()
+        };
+        assert!(ids(&options).is_empty());
+        assert_eq!(
+            log(&repo, &[third], &[second], &LogOptions::default())
+                .unwrap()
+                .len(),
+            1
+        );
+
+        update::create_tag(repo.git_dir(), "v1", &first).unwrap();
+        update::create_branch(repo.git_dir(), "topic", &third, "branch: Created from HEAD")
+            .unwrap();
+        let decorations = decorations(&repo).unwrap();
+        assert_eq!(decorations[&third], ["HEAD -> main", "topic"]);
+        assert_eq!(decorations[&first], ["tag: v1"]);
+        assert!(!decorations.contains_key(&second));
+
+        fs::remove_dir_all(temp_dir).unwrap();
+    }
+}
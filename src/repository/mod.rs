@@ -1,8 +1,23 @@
 pub mod add;
+pub mod branch;
+pub mod checkout;
 pub mod commit;
 pub mod config;
+pub mod date;
+pub mod diff;
+pub mod graph;
 pub mod index;
+pub mod lockfile;
+pub mod log;
+pub mod packed_refs;
+pub mod reflog;
 pub mod refs;
 pub mod repo;
+pub mod rev_parse;
+pub mod revwalk;
 pub mod status;
+pub mod tag;
+#[cfg(test)]
+pub(crate) mod test_utils;
 pub mod utils;
+pub mod worktree;
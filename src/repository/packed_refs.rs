@@ -0,0 +1,146 @@
+//! The `.xit/packed-refs` file, which holds many refs in one file as git's
+//! `pack-refs` writes it:
+//!
+//! ```text
+//! # pack-refs with: peeled fully-peeled sorted
+//! 1f7a0c... refs/heads/main
+//! 9b2e41... refs/tags/v1.0
+//! ^1f7a0c...
+//! ```
+//!
+//! A `^` line follows an annotated tag and gives the object the tag finally
+//! points to, so readers do not have to open the tag object.
+
+use crate::error::{Error, Result};
+use crate::objects::id::ObjectId;
+use crate::repository::lockfile::LockFile;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const HEADER: &str = "# pack-refs with: peeled fully-peeled sorted \n";
+
+/// One ref of the `packed-refs` file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackedRef {
+    pub name: String,
+    pub id: ObjectId,
+    /// For annotated tags, the object the tag chain ends at.
+    pub peeled: Option<ObjectId>,
+}
+
+/// The content of a `packed-refs` file, sorted by ref name.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PackedRefs {
+    refs: BTreeMap<String, PackedRef>,
+}
+
+impl PackedRefs {
+    /// Reads `.xit/packed-refs`; a missing file holds no refs.
+    pub fn read(git_dir: &Path) -> Result<PackedRefs> {
+        match fs::read_to_string(git_dir.join("packed-refs")) {
+            Ok(content) => PackedRefs::parse(&content),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(PackedRefs::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn parse(content: &str) -> Result<PackedRefs> {
+        let invalid =
+            |line: &str| Error::InvalidRef(format!("Invalid packed-refs line '{}'", line));
+        let mut packed = PackedRefs::default();
+        let mut last: Option<String> = None;
+        for line in content.lines() {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(peeled) = line.strip_prefix('^') {
+                let peeled = ObjectId::from_hex(peeled).map_err(|_| invalid(line))?;
+                let entry = last
+                    .as_ref()
+                    .and_then(|name| packed.refs.get_mut(name))
+                    .ok_or_else(|| invalid(line))?;
+                entry.peeled = Some(peeled);
+                continue;
+            }
+            let (id, name) = line.split_once(' ').ok_or_else(|| invalid(line))?;
+            let id = ObjectId::from_hex(id).map_err(|_| invalid(line))?;
+            packed.insert(PackedRef {
+                name: name.to_string(),
+                id,
+                peeled: None,
+            });
+            last = Some(name.to_string());
+        }
+        Ok(packed)
+    }
+
+    pub fn serialize(&self) -> String {
+        let mut out = HEADER.to_string();
+        for entry in self.refs.values() {
+            out.push_str(&format!("{} {}\n", entry.id, entry.name));
+            if let Some(peeled) = &entry.peeled {
+                out.push_str(&format!("^{}\n", peeled));
+            }
+        }
+        out
+    }
+
+    /// Locks `.xit/packed-refs` and reads it, so that it can be changed and
+    /// written back with [`PackedRefs::commit`] without racing other writers.
+    pub fn lock(git_dir: &Path) -> Result<(PackedRefs, LockFile)> {
+        let lock = LockFile::acquire(&git_dir.join("packed-refs"))?;
+        Ok((PackedRefs::read(git_dir)?, lock))
+    }
+
+    /// Replaces `.xit/packed-refs` through the lock taken by
+    /// [`PackedRefs::lock`], or removes it when no refs are left.
+    pub fn commit(&self, mut lock: LockFile) -> Result<()> {
+        if self.refs.is_empty() {
+            return lock.commit_delete();
+        }
+        lock.write_all(self.serialize().as_bytes())?;
+        lock.commit()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&PackedRef> {
+        self.refs.get(name)
+    }
+
+    pub fn insert(&mut self, entry: PackedRef) {
+        self.refs.insert(entry.name.clone(), entry);
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<PackedRef> {
+        self.refs.remove(name)
+    }
+
+    /// The refs, sorted by name.
+    pub fn iter(&self) -> impl Iterator<Item = &PackedRef> {
+        self.refs.values()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_serialize() {
+        let main = ObjectId::from([1u8; 20]);
+        let tag = ObjectId::from([2u8; 20]);
+        let content = format!(
+            "# pack-refs with: peeled fully-peeled sorted \n{} refs/heads/main\n{} refs/tags/v1.0\n^{}\n",
+            main, tag, main
+        );
+        let packed = PackedRefs::parse(&content).unwrap();
+        assert_eq!(packed.get("refs/heads/main").unwrap().peeled, None);
+        assert_eq!(packed.get("refs/tags/v1.0").unwrap().id, tag);
+        assert_eq!(packed.get("refs/tags/v1.0").unwrap().peeled, Some(main));
+        assert_eq!(packed.serialize(), content);
+
+        assert!(PackedRefs::parse(&format!("^{}\n", main)).is_err());
+        assert!(PackedRefs::parse("nonsense refs/heads/x\n").is_err());
+    }
+}
@@ -0,0 +1,209 @@
+//! Reflogs: the history of the values of a ref, kept in `.xit/logs/<ref>` in
+//! git's format, one line per update, oldest first:
+//!
+//! ```text
+//! <old id> <new id> Name <email> <seconds> <+hhmm>\t<message>
+//! ```
+//!
+//! Like git by default, only HEAD, branches and remote-tracking refs get a
+//! reflog, plus any ref whose log file already exists.
+
+use crate::error::{Error, Result};
+use crate::objects::id::ObjectId;
+use crate::objects::signature::Signature;
+use crate::repository::config;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// One line of a reflog.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReflogEntry {
+    /// The previous value, or the null id when the ref was created.
+    pub old: ObjectId,
+    pub new: ObjectId,
+    /// Who made the change, and when.
+    pub committer: Signature,
+    /// Why the ref changed, such as `commit: Fix typo`.
+    pub message: String,
+}
+
+impl ReflogEntry {
+    fn parse(name: &str, line: &str) -> Result<ReflogEntry> {
+        let invalid = || Error::CorruptObject(format!("Invalid reflog entry for '{}'", name));
+        let (head, message) = line.split_once('\t').unwrap_or((line, ""));
+        let mut parts = head.splitn(3, ' ');
+        let mut id = || {
+            parts
+                .next()
+                .and_then(|hex| ObjectId::from_hex(hex).ok())
+                .ok_or_else(invalid)
+        };
+        let old = id()?;
+        let new = id()?;
+        let committer = parts
+            .next()
+            .and_then(|value| Signature::parse(value).ok())
+            .ok_or_else(invalid)?;
+        Ok(ReflogEntry {
+            old,
+            new,
+            committer,
+            message: message.to_string(),
+        })
+    }
+}
+
+/// The id written for "no value", when a ref is created.
+pub fn null_id() -> ObjectId {
+    ObjectId::from([0u8; 20])
+}
+
+/// Records that `name` changed from `old` (`None` if it did not exist) to
+/// `new`. Refs that do not keep a reflog are skipped. The line is written with
+/// a single append, so concurrent writers never interleave within an entry.
+pub fn append(
+    git_dir: &Path,
+    name: &str,
+    old: Option<ObjectId>,
+    new: ObjectId,
+    message: &str,
+) -> Result<()> {
+    let path = log_path(git_dir, name);
+    if !keeps_log(name) && !path.is_file() {
+        return Ok(());
+    }
+    // An entry is one line, so the message is too.
+    let message: String = message
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    let line = format!(
+        "{} {} {}\t{}\n",
+        old.unwrap_or_else(null_id),
+        new,
+        identity(git_dir),
+        message
+    );
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(line.as_bytes())?;
+    Ok(())
+}
+
+/// The entries of the reflog of `name`, oldest first. A ref without a reflog
+/// has no entries.
+pub fn read(git_dir: &Path, name: &str) -> Result<Vec<ReflogEntry>> {
+    let content = match fs::read_to_string(log_path(git_dir, name)) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    content
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| ReflogEntry::parse(name, line))
+        .collect()
+}
+
+/// Deletes the reflog of a deleted ref.
+pub fn delete(git_dir: &Path, name: &str) -> Result<()> {
+    match fs::remove_file(log_path(git_dir, name)) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Moves the reflog of a renamed ref along with it.
+pub fn rename(git_dir: &Path, old: &str, new: &str) -> Result<()> {
+    let from = log_path(git_dir, old);
+    if !from.is_file() {
+        return Ok(());
+    }
+    let to = log_path(git_dir, new);
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::rename(from, to)?;
+    Ok(())
+}
+
+fn log_path(git_dir: &Path, name: &str) -> PathBuf {
+    git_dir.join("logs").join(name)
+}
+
+fn keeps_log(name: &str) -> bool {
+    name == "HEAD" || name.starts_with("refs/heads/") || name.starts_with("refs/remotes/")
+}
+
+/// The configured user, or the login name when there is none, so that
+/// updating a ref never fails for lack of an identity.
+fn identity(git_dir: &Path) -> Signature {
+    match config::get_user_config(git_dir) {
+        Ok(user) => Signature::now(&user.name, &user.email),
+        Err(_) => {
+            let name = std::env::var("USER").unwrap_or_else(|_| "unknown".to_string());
+            let email = format!("{}@localhost", name);
+            Signature::now(&name, &email)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_append_and_read() {
+        let git_dir = std::env::temp_dir().join("xit_test_reflog");
+        let _ = fs::remove_dir_all(&git_dir);
+        fs::create_dir_all(&git_dir).unwrap();
+        let one = ObjectId::from([1u8; 20]);
+        let two = ObjectId::from([2u8; 20]);
+
+        append(
+            &git_dir,
+            "refs/heads/main",
+            None,
+            one,
+            "commit (initial): One",
+        )
+        .unwrap();
+        append(
+            &git_dir,
+            "refs/heads/main",
+            Some(one),
+            two,
+            "commit: Two\n\nBody",
+        )
+        .unwrap();
+        // Tags keep no reflog.
+        append(&git_dir, "refs/tags/v1", None, one, "").unwrap();
+        assert!(!git_dir.join("logs/refs/tags").exists());
+
+        let entries = read(&git_dir, "refs/heads/main").unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].old, null_id());
+        assert_eq!(entries[0].new, one);
+        assert_eq!(entries[0].message, "commit (initial): One");
+        assert_eq!(entries[1].old, one);
+        assert_eq!(entries[1].new, two);
+        assert_eq!(entries[1].message, "commit: Two Body");
+
+        rename(&git_dir, "refs/heads/main", "refs/heads/feature/main").unwrap();
+        assert_eq!(read(&git_dir, "refs/heads/feature/main").unwrap(), entries);
+        delete(&git_dir, "refs/heads/feature/main").unwrap();
+        assert!(
+            read(&git_dir, "refs/heads/feature/main")
+                .unwrap()
+                .is_empty()
+        );
+
+        fs::remove_dir_all(git_dir).unwrap();
+    }
+}
@@ -1,25 +1,1042 @@
+//! References: names such as `HEAD`, `refs/heads/main` or `refs/tags/v1.0` that
+//! point at an object, either directly or through another ref.
+//!
+//! Refs are stored as loose files under `.xit/` or in `.xit/packed-refs` (see
+//! [`packed_refs`](crate::repository::packed_refs)). A loose file takes
+//! precedence over a packed entry. Every change goes through `<ref>.lock`
+//! files (see [`RefTransaction`]), so readers never see a half-written ref and
+//! concurrent writers cannot overwrite each other's updates unnoticed.
+
+use crate::error::{Error, Result};
+use crate::objects::id::ObjectId;
+use crate::objects::object::Object;
+use crate::objects::read as object_read;
+use crate::repository::lockfile::LockFile;
+use crate::repository::packed_refs::{PackedRef, PackedRefs};
+use crate::repository::reflog;
+use std::collections::BTreeMap;
 use std::fs;
 use std::io;
 use std::path::Path;
 
-/// Reads the HEAD file to find the path to the current branch reference (e.g., "refs/heads/main").
-pub fn get_head_ref_path() -> io::Result<String> {
-    let head_content = fs::read_to_string(".xit/HEAD")?;
-    Ok(head_content
-        .trim()
-        .split_whitespace()
-        .last()
-        .unwrap_or("")
-        .to_string())
+/// How many symbolic refs are followed before giving up.
+const MAX_SYMREF_DEPTH: usize = 5;
+
+/// The content of a ref file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RefValue {
+    /// An object id, as in a branch or a detached HEAD.
+    Direct(ObjectId),
+    /// `ref: <name>`, as in HEAD on a branch.
+    Symbolic(String),
+}
+
+impl RefValue {
+    fn parse(name: &str, content: &str) -> Result<RefValue> {
+        let content = content.trim_end();
+        if let Some(target) = content.strip_prefix("ref: ") {
+            return Ok(RefValue::Symbolic(target.trim().to_string()));
+        }
+        ObjectId::from_hex(content)
+            .map(RefValue::Direct)
+            .map_err(|_| Error::InvalidRef(format!("Invalid object id in reference '{}'", name)))
+    }
+
+    fn serialize(&self) -> String {
+        match self {
+            RefValue::Direct(id) => format!("{}\n", id),
+            RefValue::Symbolic(target) => format!("ref: {}\n", target),
+        }
+    }
+}
+
+/// Reads and writes the refs stored under a `.xit` directory.
+#[derive(Debug, Clone, Copy)]
+pub struct RefStore<'a> {
+    git_dir: &'a Path,
+}
+
+impl<'a> RefStore<'a> {
+    pub fn new(git_dir: &'a Path) -> RefStore<'a> {
+        RefStore { git_dir }
+    }
+
+    /// Reads one ref without following it, or `None` if it does not exist.
+    pub fn read(&self, name: &str) -> Result<Option<RefValue>> {
+        if check_lookup(name).is_err() {
+            return Ok(None);
+        }
+        if let Some(value) = self.read_loose(name)? {
+            return Ok(Some(value));
+        }
+        if is_pseudo_ref(name) {
+            return Ok(None);
+        }
+        Ok(PackedRefs::read(self.git_dir)?
+            .get(name)
+            .map(|entry| RefValue::Direct(entry.id)))
+    }
+
+    fn read_loose(&self, name: &str) -> Result<Option<RefValue>> {
+        let path = self.git_dir.join(name);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        RefValue::parse(name, &fs::read_to_string(path)?).map(Some)
+    }
+
+    /// Follows symbolic refs starting at `name`. Returns the ref that holds (or
+    /// would hold) an object id, and that id unless the ref does not exist yet,
+    /// as for HEAD on a branch without commits.
+    pub fn follow(&self, name: &str) -> Result<(String, Option<ObjectId>)> {
+        let mut chain = vec![name.to_string()];
+        loop {
+            let current = chain.last().expect("chain starts with name");
+            match self.read(current)? {
+                None => return Ok((current.clone(), None)),
+                Some(RefValue::Direct(id)) => return Ok((current.clone(), Some(id))),
+                Some(RefValue::Symbolic(target)) => {
+                    if chain.contains(&target) || chain.len() > MAX_SYMREF_DEPTH {
+                        chain.push(target);
+                        return Err(Error::InvalidRef(format!(
+                            "Symbolic ref loop: {}",
+                            chain.join(" -> ")
+                        )));
+                    }
+                    chain.push(target);
+                }
+            }
+        }
+    }
+
+    /// The object id `name` finally points to, or `None` if there is none.
+    pub fn resolve(&self, name: &str) -> Result<Option<ObjectId>> {
+        Ok(self.follow(name)?.1)
+    }
+
+    /// Returns true if the ref file exists (even if it is symbolic).
+    pub fn exists(&self, name: &str) -> Result<bool> {
+        Ok(self.read(name)?.is_some())
+    }
+
+    /// What HEAD holds.
+    pub fn head(&self) -> Result<RefValue> {
+        self.read("HEAD")?
+            .ok_or_else(|| Error::InvalidRef("HEAD does not exist".to_string()))
+    }
+
+    /// The branch HEAD is on (`main` for `ref: refs/heads/main`), or `None` when
+    /// HEAD is detached. The branch may not have any commits yet.
+    pub fn current_branch(&self) -> Result<Option<String>> {
+        Ok(match self.head()? {
+            RefValue::Symbolic(target) => target.strip_prefix("refs/heads/").map(str::to_string),
+            RefValue::Direct(_) => None,
+        })
+    }
+
+    /// Points `name` at `id`, following symbolic refs: updating `HEAD` moves the
+    /// current branch, or HEAD itself when it is detached. The change is
+    /// recorded with `reason` in the reflog of the updated ref, and in HEAD's
+    /// when the update went through it. Returns the warnings of
+    /// [`RefTransaction::commit`].
+    pub fn update(&self, name: &str, id: &ObjectId, reason: &str) -> Result<Vec<String>> {
+        let mut transaction = self.transaction();
+        transaction.update(name, *id, Expected::Any, reason);
+        transaction.commit()
+    }
+
+    /// Starts a set of ref updates that are applied all together or not at all.
+    pub fn transaction(&self) -> RefTransaction<'a> {
+        RefTransaction {
+            store: *self,
+            updates: Vec::new(),
+        }
+    }
+
+    /// Replaces the content of `name` itself, without following it. This is
+    /// how HEAD is moved to another branch or detached.
+    pub fn write(&self, name: &str, value: &RefValue) -> Result<()> {
+        check_writable(name)?;
+        if let RefValue::Symbolic(target) = value {
+            check_writable(target)?;
+        }
+        self.check_no_conflict(name)?;
+
+        let path = self.git_dir.join(name);
+        self.remove_empty_tree(&path);
+        let mut lock = LockFile::acquire(&path)?;
+        lock.write_all(value.serialize().as_bytes())?;
+        lock.commit()
+    }
+
+    /// Points `name` at another ref: `set_symbolic("HEAD", "refs/heads/main")`.
+    pub fn set_symbolic(&self, name: &str, target: &str) -> Result<()> {
+        self.write(name, &RefValue::Symbolic(target.to_string()))
+    }
+
+    /// Deletes `name` itself (not what it points to), both the loose file and
+    /// the packed entry, along with directories under `refs/` that become empty.
+    pub fn delete(&self, name: &str) -> Result<Vec<String>> {
+        if !self.exists(name)? {
+            return Err(Error::InvalidRef(format!(
+                "Reference '{}' does not exist",
+                name
+            )));
+        }
+        let mut transaction = self.transaction();
+        transaction.delete(name, Expected::Any, "");
+        transaction.commit()
+    }
+
+    /// Renames the ref `old` to `new`, replacing `new` if it exists. The
+    /// reflog moves along and records the rename with `reason`.
+    pub fn rename(&self, old: &str, new: &str, reason: &str) -> Result<Vec<String>> {
+        let Some(RefValue::Direct(id)) = self.read(old)? else {
+            return Err(Error::InvalidRef(format!(
+                "Reference '{}' does not exist",
+                old
+            )));
+        };
+        let mut transaction = self.transaction();
+        transaction
+            .delete(old, Expected::Value(id), "")
+            .update(new, id, Expected::Any, reason);
+
+        // The log moves first so that the rename is appended to it.
+        let moved_log = self.git_dir.join("logs").join(old).is_file();
+        if moved_log {
+            reflog::rename(self.git_dir, old, new)?;
+        }
+        let result = transaction.commit();
+        if result.is_err() && moved_log {
+            reflog::rename(self.git_dir, new, old)?;
+        }
+        result
+    }
+
+    /// The refs whose name starts with `prefix` (such as `refs/heads/`), with
+    /// the ids they resolve to, sorted by name. Names include the prefix.
+    pub fn list(&self, prefix: &str) -> Result<Vec<(String, ObjectId)>> {
+        let mut refs: BTreeMap<String, ObjectId> = PackedRefs::read(self.git_dir)?
+            .iter()
+            .filter(|entry| entry.name.starts_with(prefix))
+            .map(|entry| (entry.name.clone(), entry.id))
+            .collect();
+        for name in self.loose_names(prefix)? {
+            match self.resolve(&name)? {
+                Some(id) => refs.insert(name, id),
+                None => refs.remove(&name),
+            };
+        }
+        Ok(refs.into_iter().collect())
+    }
+
+    /// Moves loose refs into `packed-refs`: tags and refs that are packed
+    /// already, or every ref under `refs/` with `all`. Annotated tags get their
+    /// peeled value. Symbolic refs stay loose. The loose files are removed
+    /// unless `keep_loose` is set. Returns how many refs were packed.
+    pub fn pack(&self, all: bool, keep_loose: bool) -> Result<usize> {
+        let (mut packed, packed_lock) = PackedRefs::lock(self.git_dir)?;
+        let mut packed_loose = Vec::new();
+        for name in self.loose_names("refs/")? {
+            if !all && !name.starts_with("refs/tags/") && packed.get(&name).is_none() {
+                continue;
+            }
+            let Some(RefValue::Direct(id)) = self.read_loose(&name)? else {
+                continue;
+            };
+            packed.insert(PackedRef {
+                peeled: self.peel_tag(id)?,
+                name: name.clone(),
+                id,
+            });
+            packed_loose.push((name, id));
+        }
+        packed.commit(packed_lock)?;
+
+        if !keep_loose {
+            for (name, id) in &packed_loose {
+                // A ref updated since it was packed keeps its loose file.
+                let path = self.git_dir.join(name);
+                let lock = LockFile::acquire(&path)?;
+                if self.read_loose(name)? == Some(RefValue::Direct(*id)) {
+                    lock.commit_delete()?;
+                    self.remove_empty_dirs(&path);
+                }
+            }
+        }
+        Ok(packed_loose.len())
+    }
+
+    /// The loose ref files below `prefix`, as ref names.
+    fn loose_names(&self, prefix: &str) -> Result<Vec<String>> {
+        let dir = self.git_dir.join(prefix);
+        if !dir.is_dir() {
+            return Ok(Vec::new());
+        }
+        let mut names = Vec::new();
+        for entry in walkdir::WalkDir::new(&dir) {
+            let entry = entry.map_err(io::Error::from)?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let relative = entry
+                .path()
+                .strip_prefix(self.git_dir)
+                .unwrap_or(entry.path());
+            let Some(name) = relative.to_str().map(|name| name.replace('\\', "/")) else {
+                continue;
+            };
+            // Lock files and other leftovers are not refs.
+            if check_ref_format(&name).is_ok() {
+                names.push(name);
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    /// For an annotated tag, the object at the end of its chain of tags.
+    fn peel_tag(&self, id: ObjectId) -> Result<Option<ObjectId>> {
+        let mut peeled = None;
+        let mut current = id;
+        while let Object::Tag(tag) = object_read::read_parsed(self.git_dir, &current)? {
+            current = tag.object;
+            peeled = Some(current);
+        }
+        Ok(peeled)
+    }
+
+    /// Removes the directories of a deleted ref file that are now empty, but
+    /// keeps `refs/heads/`, `refs/tags/` and the like.
+    fn remove_empty_dirs(&self, path: &Path) {
+        let refs_dir = self.git_dir.join("refs");
+        let mut dir = path.parent();
+        while let Some(current) = dir {
+            if !current.starts_with(&refs_dir)
+                || current.parent() == Some(refs_dir.as_path())
+                || fs::remove_dir(current).is_err()
+            {
+                break;
+            }
+            dir = current.parent();
+        }
+    }
+
+    /// Removes the directory at `path` if it holds no files, as left behind
+    /// by deleted refs, so that a ref file can take its place.
+    fn remove_empty_tree(&self, path: &Path) {
+        if path.is_dir() {
+            for entry in walkdir::WalkDir::new(path)
+                .contents_first(true)
+                .into_iter()
+                .flatten()
+            {
+                let _ = fs::remove_dir(entry.path());
+            }
+        }
+    }
+
+    /// A ref cannot be created where a file or directory of the same path
+    /// exists: `refs/heads/a` and `refs/heads/a/b` exclude each other.
+    fn check_no_conflict(&self, name: &str) -> Result<()> {
+        let conflict = |other: &str| {
+            Error::Conflict(format!(
+                "Cannot create '{}': '{}' already exists",
+                name, other
+            ))
+        };
+        let components: Vec<&str> = name.split('/').collect();
+        for end in 1..components.len() {
+            let prefix = components[..end].join("/");
+            if self.git_dir.join(&prefix).is_file() {
+                return Err(conflict(&prefix));
+            }
+        }
+        if let Some(other) = self.loose_names(&format!("{}/", name))?.first() {
+            return Err(conflict(other));
+        }
+        if is_pseudo_ref(name) {
+            return Ok(());
+        }
+        let packed = PackedRefs::read(self.git_dir)?;
+        for entry in packed.iter() {
+            if name.starts_with(&format!("{}/", entry.name))
+                || entry.name.starts_with(&format!("{}/", name))
+            {
+                return Err(conflict(&entry.name));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What a command that moved a ref reports: the id the ref now points at (or
+/// held, when it was deleted) and the warnings of the [`RefTransaction`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RefChange {
+    pub id: ObjectId,
+    pub warnings: Vec<String>,
+}
+
+/// What a ref must hold for a transaction to change it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Expected {
+    /// Whatever it holds.
+    Any,
+    /// The ref must not exist yet.
+    Missing,
+    /// The ref must point at this id, as when it was read before the change.
+    Value(ObjectId),
+}
+
+/// A set of ref updates and deletions applied all together or not at all.
+///
+/// Committing locks every ref involved by creating `<ref>.lock`, checks that
+/// each ref still holds what the caller [`Expected`], and only then moves the
+/// new values into place. If any lock is taken by another process or any ref
+/// has changed, nothing is modified.
+///
+/// As in git, the final renames of the lock files happen one ref at a time:
+/// if one of them fails (a full disk, say), the refs renamed before it keep
+/// their new values. The reflogs are written once every ref is in place, and
+/// failing to write them only makes a warning for the caller to report.
+#[derive(Debug)]
+pub struct RefTransaction<'a> {
+    store: RefStore<'a>,
+    updates: Vec<RefUpdate>,
+}
+
+#[derive(Debug)]
+struct RefUpdate {
+    name: String,
+    /// `None` deletes the ref.
+    new: Option<RefValue>,
+    /// Whether a symbolic `name` is followed to the ref that changes.
+    deref: bool,
+    expected: Expected,
+    reason: String,
+}
+
+impl RefTransaction<'_> {
+    /// Points `name` at `new`, following symbolic refs like [`RefStore::update`].
+    pub fn update(
+        &mut self,
+        name: &str,
+        new: ObjectId,
+        expected: Expected,
+        reason: &str,
+    ) -> &mut Self {
+        self.updates.push(RefUpdate {
+            name: name.to_string(),
+            new: Some(RefValue::Direct(new)),
+            deref: true,
+            expected,
+            reason: reason.to_string(),
+        });
+        self
+    }
+
+    /// Replaces the content of `name` itself, without following it, like
+    /// [`RefStore::write`]. This is how HEAD is moved to another branch or
+    /// detached; the reflog of `name` records the commit it now resolves to.
+    pub fn write(
+        &mut self,
+        name: &str,
+        new: RefValue,
+        expected: Expected,
+        reason: &str,
+    ) -> &mut Self {
+        self.updates.push(RefUpdate {
+            name: name.to_string(),
+            new: Some(new),
+            deref: false,
+            expected,
+            reason: reason.to_string(),
+        });
+        self
+    }
+
+    /// Deletes `name` itself, even if it is a symbolic ref.
+    pub fn delete(&mut self, name: &str, expected: Expected, reason: &str) -> &mut Self {
+        self.updates.push(RefUpdate {
+            name: name.to_string(),
+            new: None,
+            deref: false,
+            expected,
+            reason: reason.to_string(),
+        });
+        self
+    }
+
+    /// Applies every update, or none of them if one cannot be applied.
+    ///
+    /// Returns a warning for each reflog that could not be written; the refs
+    /// are updated all the same.
+    pub fn commit(self) -> Result<Vec<String>> {
+        let store = self.store;
+
+        // Symbolic refs are resolved up front: the lock is on the ref that
+        // actually changes. A symbolic ref is itself locked before it is
+        // followed, so that it cannot be pointed elsewhere in the meantime.
+        let mut targets: Vec<String> = Vec::new();
+        let mut symref_locks = Vec::new();
+        for update in &self.updates {
+            let target = match &update.new {
+                Some(_) if update.deref => {
+                    if let Some(RefValue::Symbolic(_)) = store.read(&update.name)? {
+                        symref_locks.push(LockFile::acquire(&store.git_dir.join(&update.name))?);
+                    }
+                    let (target, _) = store.follow(&update.name)?;
+                    check_writable(&target)?;
+                    store.check_no_conflict(&target)?;
+                    target
+                }
+                Some(value) => {
+                    check_writable(&update.name)?;
+                    if let RefValue::Symbolic(target) = value {
+                        check_writable(target)?;
+                    }
+                    store.check_no_conflict(&update.name)?;
+                    update.name.clone()
+                }
+                None => {
+                    check_lookup(&update.name)?;
+                    update.name.clone()
+                }
+            };
+            if targets.contains(&target) {
+                return Err(Error::InvalidInput(format!(
+                    "Multiple updates for ref '{}' are not allowed",
+                    target
+                )));
+            }
+            targets.push(target);
+        }
+
+        let result = self.apply(&targets);
+        drop(symref_locks);
+        if result.is_err() {
+            // Failed locks may leave directories created for them behind.
+            for target in &targets {
+                store.remove_empty_dirs(&store.git_dir.join(target));
+            }
+        }
+        result
+    }
+
+    fn apply(&self, targets: &[String]) -> Result<Vec<String>> {
+        let store = self.store;
+        let git_dir = store.git_dir;
+
+        let mut locks = Vec::new();
+        for target in targets {
+            let path = git_dir.join(target);
+            store.remove_empty_tree(&path);
+            locks.push(LockFile::acquire(&path)?);
+        }
+        let deletes = self.updates.iter().any(|update| update.new.is_none());
+        let packed = if deletes {
+            Some(PackedRefs::lock(git_dir)?)
+        } else {
+            None
+        };
+
+        // With every lock held, nobody else can change the refs any more.
+        let mut olds = Vec::new();
+        for (update, target) in self.updates.iter().zip(targets) {
+            let exists = store.exists(target)?;
+            let old = store.resolve(target)?;
+            let matches = match update.expected {
+                Expected::Any => true,
+                Expected::Missing => !exists,
+                Expected::Value(id) => old == Some(id),
+            };
+            if !matches {
+                let found = match old {
+                    Some(id) => id.to_hex(),
+                    None if exists => "a symbolic ref".to_string(),
+                    None => "missing".to_string(),
+                };
+                let expected = match update.expected {
+                    Expected::Value(id) => id.to_hex(),
+                    _ => "missing".to_string(),
+                };
+                return Err(Error::Conflict(format!(
+                    "Cannot update ref '{}': expected {}, but it is {}",
+                    target, expected, found
+                )));
+            }
+            olds.push(old);
+        }
+
+        for (update, lock) in self.updates.iter().zip(locks.iter_mut()) {
+            if let Some(new) = &update.new {
+                lock.write_all(new.serialize().as_bytes())?;
+            }
+        }
+        // Packed entries go first, so that a deleted loose ref never reveals
+        // an outdated packed value.
+        if let Some((mut packed, lock)) = packed {
+            let mut changed = false;
+            for (update, target) in self.updates.iter().zip(targets) {
+                if update.new.is_none() {
+                    changed |= packed.remove(target).is_some();
+                }
+            }
+            if changed {
+                packed.commit(lock)?;
+            }
+        }
+        // From here on a failure leaves the refs already renamed updated.
+        for (update, lock) in self.updates.iter().zip(locks) {
+            match update.new {
+                Some(_) => lock.commit()?,
+                None => {
+                    let path = lock.path().to_path_buf();
+                    lock.commit_delete()?;
+                    store.remove_empty_dirs(&path);
+                }
+            }
+        }
+
+        // The refs are committed: a reflog that cannot be written is no
+        // reason to report the update as failed.
+        let mut warnings = Vec::new();
+        let mut warn = |name: &str, logged: Result<()>| {
+            if let Err(e) = logged {
+                warnings.push(format!("could not update the reflog of '{}': {}", name, e));
+            }
+        };
+        for ((update, target), old) in self.updates.iter().zip(targets).zip(olds) {
+            let new = match &update.new {
+                Some(RefValue::Direct(id)) => *id,
+                // A symbolic ref to an unborn branch has no commit to log.
+                Some(RefValue::Symbolic(branch)) => match store.resolve(branch) {
+                    Ok(Some(id)) => id,
+                    _ => continue,
+                },
+                None => {
+                    warn(target, reflog::delete(git_dir, target));
+                    continue;
+                }
+            };
+            warn(
+                target,
+                reflog::append(git_dir, target, old, new, &update.reason),
+            );
+            if update.name != *target {
+                warn(
+                    &update.name,
+                    reflog::append(git_dir, &update.name, old, new, &update.reason),
+                );
+            }
+        }
+        Ok(warnings)
+    }
+}
+
+/// Checks a full ref name against git's `check-ref-format` rules: components
+/// are separated by `/` and there must be at least two of them; no component
+/// is empty, starts with `.` or ends with `.lock`; the name contains no `..`,
+/// `@{`, control characters, spaces or any of `~ ^ : ? * [ \`; it does not end
+/// with `.` and is not `@`.
+pub fn check_ref_format(name: &str) -> Result<()> {
+    match format_problem(name) {
+        Some(problem) => Err(Error::InvalidRef(format!(
+            "'{}' is not a valid ref name: {}",
+            name, problem
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Checks the name of a branch (`kind` = "branch", stored as `refs/heads/<name>`)
+/// or a tag (`kind` = "tag", stored as `refs/tags/<name>`). Names may contain
+/// slashes, as in `feature/foo`.
+pub fn check_short_name(kind: &str, name: &str) -> Result<()> {
+    let problem = if name.is_empty() {
+        Some("it is empty")
+    } else if name == "HEAD" {
+        Some("it is reserved")
+    } else if name.starts_with('-') {
+        Some("it starts with '-'")
+    } else {
+        format_problem(&format!("refs/x/{}", name))
+    };
+    match problem {
+        Some(problem) => Err(Error::InvalidRef(format!(
+            "'{}' is not a valid {} name: {}",
+            name, kind, problem
+        ))),
+        None => Ok(()),
+    }
+}
+
+fn format_problem(name: &str) -> Option<&'static str> {
+    if name.is_empty() {
+        return Some("it is empty");
+    }
+    if name == "@" {
+        return Some("it is '@'");
+    }
+    if name.split('/').any(str::is_empty) {
+        return Some("it has an empty component");
+    }
+    if !name.contains('/') {
+        return Some("it has a single component");
+    }
+    if name.ends_with('.') {
+        return Some("it ends with '.'");
+    }
+    if name.contains("..") {
+        return Some("it contains '..'");
+    }
+    if name.contains("@{") {
+        return Some("it contains '@{'");
+    }
+    if name
+        .chars()
+        .any(|c| c.is_ascii_control() || " ~^:?*[\\".contains(c))
+    {
+        return Some("it contains a forbidden character");
+    }
+    for component in name.split('/') {
+        if component.starts_with('.') {
+            return Some("a component starts with '.'");
+        }
+        if component.ends_with(".lock") {
+            return Some("a component ends with '.lock'");
+        }
+    }
+    None
+}
+
+/// Top-level refs such as HEAD or ORIG_HEAD: all caps, outside of `refs/`.
+fn is_pseudo_ref(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_uppercase() || c == '_')
+}
+
+/// Refs can only be written as pseudo refs or below `refs/`.
+fn check_writable(name: &str) -> Result<()> {
+    if is_pseudo_ref(name) {
+        return Ok(());
+    }
+    check_ref_format(name)?;
+    if !name.starts_with("refs/") {
+        return Err(Error::InvalidRef(format!(
+            "'{}' is not a valid ref name: it is outside of refs/",
+            name
+        )));
+    }
+    Ok(())
+}
+
+/// Names that are looked up must not lead outside of the `.xit` directory.
+fn check_lookup(name: &str) -> Result<()> {
+    if is_pseudo_ref(name) || (name.starts_with("refs/") && format_problem(name).is_none()) {
+        Ok(())
+    } else {
+        Err(Error::InvalidRef(format!(
+            "'{}' is not a valid ref name",
+            name
+        )))
+    }
+}
+
+/// Reads the HEAD file to find the reference new commits go to: the current
+/// branch (e.g., "refs/heads/main"), or "HEAD" itself when HEAD is detached.
+pub fn get_head_ref_path(git_dir: &Path) -> Result<String> {
+    Ok(RefStore::new(git_dir).follow("HEAD")?.0)
 }
 
 /// Reads the branch reference file to get the commit's hash.
-pub fn get_commit_hash(ref_path: &str) -> io::Result<String> {
-    if ref_path.is_empty() {
-        return Err(io::Error::new(
-            io::ErrorKind::NotFound,
-            "HEAD is detached or no commits yet",
+pub fn get_commit_hash(git_dir: &Path, ref_path: &str) -> Result<ObjectId> {
+    RefStore::new(git_dir)
+        .resolve(ref_path)?
+        .ok_or_else(|| Error::InvalidRef(format!("Reference '{}' does not exist", ref_path)))
+}
+
+/// The branch HEAD is on (`main` for `ref: refs/heads/main`), or `None` when
+/// HEAD is detached. The branch may not have any commits yet.
+pub fn current_branch(git_dir: &Path) -> Result<Option<String>> {
+    RefStore::new(git_dir).current_branch()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_ref_format() {
+        for name in [
+            "refs/heads/main",
+            "refs/heads/feature/foo",
+            "refs/tags/v1.0",
+        ] {
+            assert!(check_ref_format(name).is_ok(), "{}", name);
+        }
+        for name in [
+            "main",
+            "refs/heads/",
+            "refs//heads",
+            "refs/heads/a..b",
+            "refs/heads/.hidden",
+            "refs/heads/x.lock",
+            "refs/heads/end.",
+            "refs/heads/a b",
+            "refs/heads/a~1",
+            "refs/heads/a^",
+            "refs/heads/a:b",
+            "refs/heads/a?",
+            "refs/heads/a*",
+            "refs/heads/a[",
+            "refs/heads/a\\b",
+            "refs/heads/a@{1}",
+            "refs/heads/tab\t",
+            "@",
+        ] {
+            assert!(check_ref_format(name).is_err(), "{}", name);
+        }
+        assert!(check_short_name("branch", "feature/foo").is_ok());
+        assert!(check_short_name("branch", "HEAD").is_err());
+        assert!(check_short_name("branch", "-x").is_err());
+        assert!(check_short_name("tag", "").is_err());
+    }
+
+    #[test]
+    fn test_symbolic_refs_and_detached_head() {
+        let git_dir = std::env::temp_dir().join("xit_test_ref_store");
+        let _ = fs::remove_dir_all(&git_dir);
+        fs::create_dir_all(&git_dir).unwrap();
+        let store = RefStore::new(&git_dir);
+        let one = ObjectId::from([1u8; 20]);
+        let two = ObjectId::from([2u8; 20]);
+
+        // HEAD on a branch without commits.
+        store.set_symbolic("HEAD", "refs/heads/main").unwrap();
+        assert_eq!(
+            store.follow("HEAD").unwrap(),
+            ("refs/heads/main".to_string(), None)
+        );
+        assert_eq!(store.current_branch().unwrap().as_deref(), Some("main"));
+
+        // Updating HEAD moves the branch and keeps HEAD symbolic.
+        store.update("HEAD", &one, "").unwrap();
+        assert_eq!(store.resolve("refs/heads/main").unwrap(), Some(one));
+        assert_eq!(
+            fs::read_to_string(git_dir.join("HEAD")).unwrap(),
+            "ref: refs/heads/main\n"
+        );
+
+        store.update("refs/heads/feature/foo", &two, "").unwrap();
+        store
+            .set_symbolic("refs/heads/alias", "refs/heads/feature/foo")
+            .unwrap();
+        assert_eq!(store.resolve("refs/heads/alias").unwrap(), Some(two));
+        assert_eq!(
+            store.list("refs/heads/").unwrap(),
+            vec![
+                ("refs/heads/alias".to_string(), two),
+                ("refs/heads/feature/foo".to_string(), two),
+                ("refs/heads/main".to_string(), one),
+            ]
+        );
+        assert!(matches!(
+            store.update("refs/heads/feature", &one, ""),
+            Err(Error::Conflict(_))
+        ));
+        assert!(matches!(
+            store.update("refs/heads/main/sub", &one, ""),
+            Err(Error::Conflict(_))
+        ));
+        assert!(store.update("refs/heads/bad name", &one, "").is_err());
+        assert!(store.update("config", &one, "").is_err());
+        assert_eq!(store.read("../config").unwrap(), None);
+
+        // Detached HEAD: updating it no longer touches any branch.
+        store.write("HEAD", &RefValue::Direct(two)).unwrap();
+        assert_eq!(store.current_branch().unwrap(), None);
+        store.update("HEAD", &one, "").unwrap();
+        assert_eq!(store.head().unwrap(), RefValue::Direct(one));
+        assert_eq!(store.resolve("refs/heads/feature/foo").unwrap(), Some(two));
+
+        store.set_symbolic("refs/heads/a", "refs/heads/b").unwrap();
+        store.set_symbolic("refs/heads/b", "refs/heads/a").unwrap();
+        assert!(store.resolve("refs/heads/a").is_err());
+
+        store.delete("refs/heads/feature/foo").unwrap();
+        assert!(!git_dir.join("refs/heads/feature").exists());
+        assert!(store.delete("refs/heads/feature/foo").is_err());
+
+        fs::remove_dir_all(git_dir).unwrap();
+    }
+
+    #[test]
+    fn test_packed_refs() {
+        use crate::objects::object::write_object;
+        use crate::objects::tag::Tag;
+
+        let git_dir = std::env::temp_dir().join("xit_test_packed_refs");
+        let _ = fs::remove_dir_all(&git_dir);
+        fs::create_dir_all(&git_dir).unwrap();
+        let store = RefStore::new(&git_dir);
+        let blob = write_object(&git_dir, "blob", b"content").unwrap();
+        let tag = Object::Tag(Tag {
+            object: blob,
+            obj_type: "blob".to_string(),
+            tag: "v1".to_string(),
+            tagger: None,
+            extra_headers: Vec::new(),
+            message: "One\n".to_string(),
+        })
+        .write(&git_dir)
+        .unwrap();
+
+        store.set_symbolic("HEAD", "refs/heads/main").unwrap();
+        store.update("HEAD", &blob, "").unwrap();
+        store.update("refs/heads/feature/foo", &blob, "").unwrap();
+        store.update("refs/tags/v1", &tag, "").unwrap();
+
+        // By default only tags are packed.
+        assert_eq!(store.pack(false, false).unwrap(), 1);
+        assert!(!git_dir.join("refs/tags/v1").exists());
+        let packed = PackedRefs::read(&git_dir).unwrap();
+        assert_eq!(packed.get("refs/tags/v1").unwrap().peeled, Some(blob));
+        assert!(packed.get("refs/heads/main").is_none());
+
+        assert_eq!(store.pack(true, false).unwrap(), 2);
+        assert!(!git_dir.join("refs/heads/feature").exists());
+        assert_eq!(store.resolve("HEAD").unwrap(), Some(blob));
+        assert!(store.exists("refs/heads/feature/foo").unwrap());
+        assert_eq!(
+            store.list("refs/").unwrap(),
+            vec![
+                ("refs/heads/feature/foo".to_string(), blob),
+                ("refs/heads/main".to_string(), blob),
+                ("refs/tags/v1".to_string(), tag),
+            ]
+        );
+
+        // A loose ref shadows its packed entry; deleting removes both.
+        store.update("refs/heads/main", &tag, "").unwrap();
+        assert_eq!(store.resolve("refs/heads/main").unwrap(), Some(tag));
+        assert!(matches!(
+            store.update("refs/heads/feature", &blob, ""),
+            Err(Error::Conflict(_))
         ));
+        store.delete("refs/heads/main").unwrap();
+        assert_eq!(store.resolve("refs/heads/main").unwrap(), None);
+        store.delete("refs/heads/feature/foo").unwrap();
+        store.delete("refs/tags/v1").unwrap();
+        assert!(!git_dir.join("packed-refs").exists());
+        assert!(store.delete("refs/tags/v1").is_err());
+
+        fs::remove_dir_all(git_dir).unwrap();
+    }
+
+    #[test]
+    fn test_transactions() {
+        let git_dir = std::env::temp_dir().join("xit_test_ref_transaction");
+        let _ = fs::remove_dir_all(&git_dir);
+        fs::create_dir_all(&git_dir).unwrap();
+        let store = RefStore::new(&git_dir);
+        let one = ObjectId::from([1u8; 20]);
+        let two = ObjectId::from([2u8; 20]);
+        store.set_symbolic("HEAD", "refs/heads/main").unwrap();
+
+        let mut transaction = store.transaction();
+        transaction
+            .update("HEAD", one, Expected::Missing, "commit (initial): One")
+            .update(
+                "refs/heads/topic",
+                one,
+                Expected::Missing,
+                "branch: Created",
+            );
+        transaction.commit().unwrap();
+        assert_eq!(store.resolve("refs/heads/main").unwrap(), Some(one));
+        assert_eq!(store.resolve("refs/heads/topic").unwrap(), Some(one));
+        assert_eq!(reflog::read(&git_dir, "HEAD").unwrap().len(), 1);
+
+        // One stale expectation and nothing changes.
+        let mut transaction = store.transaction();
+        transaction
+            .update("refs/heads/main", two, Expected::Value(one), "")
+            .update("refs/heads/topic", two, Expected::Value(two), "");
+        assert!(matches!(transaction.commit(), Err(Error::Conflict(_))));
+        assert_eq!(store.resolve("refs/heads/main").unwrap(), Some(one));
+        assert!(!git_dir.join("refs/heads/main.lock").exists());
+        assert!(!git_dir.join("refs/heads/topic.lock").exists());
+
+        // Neither while another process holds a lock.
+        fs::write(git_dir.join("refs/heads/topic.lock"), "").unwrap();
+        let mut transaction = store.transaction();
+        transaction
+            .update("refs/heads/main", two, Expected::Any, "")
+            .update("refs/heads/topic", two, Expected::Any, "");
+        assert!(transaction.commit().is_err());
+        assert_eq!(store.resolve("refs/heads/main").unwrap(), Some(one));
+        assert!(git_dir.join("refs/heads/topic.lock").exists());
+        fs::remove_file(git_dir.join("refs/heads/topic.lock")).unwrap();
+
+        let mut transaction = store.transaction();
+        transaction
+            .update("refs/heads/main", two, Expected::Any, "")
+            .update("HEAD", two, Expected::Any, "");
+        assert!(matches!(transaction.commit(), Err(Error::InvalidInput(_))));
+
+        let mut transaction = store.transaction();
+        transaction
+            .delete("refs/heads/topic", Expected::Value(one), "")
+            .update("refs/heads/topic/sub", two, Expected::Missing, "");
+        assert!(transaction.commit().is_err());
+        let mut transaction = store.transaction();
+        transaction
+            .delete("refs/heads/topic", Expected::Value(one), "")
+            .update("HEAD", two, Expected::Value(one), "commit: Two");
+        transaction.commit().unwrap();
+        assert!(!store.exists("refs/heads/topic").unwrap());
+        assert_eq!(store.resolve("HEAD").unwrap(), Some(two));
+        store.update("refs/heads/topic/sub", &two, "").unwrap();
+
+        // HEAD itself is replaced, and its reflog records the commit it now
+        // resolves to.
+        let mut transaction = store.transaction();
+        transaction.write("HEAD", RefValue::Direct(one), Expected::Value(one), "");
+        assert!(matches!(transaction.commit(), Err(Error::Conflict(_))));
+        let mut transaction = store.transaction();
+        transaction.write(
+            "HEAD",
+            RefValue::Symbolic("refs/heads/topic/sub".to_string()),
+            Expected::Value(two),
+            "checkout: moving from main to topic/sub",
+        );
+        transaction.commit().unwrap();
+        assert_eq!(
+            store.current_branch().unwrap().as_deref(),
+            Some("topic/sub")
+        );
+        assert_eq!(store.resolve("refs/heads/main").unwrap(), Some(two));
+        let entries = reflog::read(&git_dir, "HEAD").unwrap();
+        assert_eq!(entries.last().unwrap().new, two);
+        store.set_symbolic("HEAD", "refs/heads/main").unwrap();
+
+        // HEAD is locked before it is followed to the branch.
+        fs::write(git_dir.join("HEAD.lock"), "").unwrap();
+        assert!(store.update("HEAD", &one, "").is_err());
+        assert_eq!(store.resolve("refs/heads/main").unwrap(), Some(two));
+        fs::remove_file(git_dir.join("HEAD.lock")).unwrap();
+
+        // A reflog that cannot be written does not undo a committed update.
+        fs::remove_file(git_dir.join("logs/HEAD")).unwrap();
+        fs::create_dir_all(git_dir.join("logs/HEAD")).unwrap();
+        let warnings = store.update("HEAD", &one, "reset").unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("'HEAD'"));
+        assert_eq!(store.resolve("refs/heads/main").unwrap(), Some(one));
+        assert!(!git_dir.join("HEAD.lock").exists());
+
+        fs::remove_dir_all(git_dir).unwrap();
     }
-    fs::read_to_string(Path::new(".xit").join(ref_path)).map(|s| s.trim().to_string())
-}
\ No newline at end of file
+}
@@ -1,27 +1,281 @@
-use std::{fs, path::Path};
+use crate::error::{Error, Result};
+use crate::objects::commit::Commit;
+use crate::objects::id::ObjectId;
+use crate::objects::object::Object;
+use crate::objects::read as object_read;
+use crate::repository::refs::{RefChange, RefStore};
+use crate::repository::{add, commit, status, utils};
+use std::env;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
 
-// init repository
-pub fn create_repository() {
-    let folder_name = ".xit";
+/// Name of the directory holding the repository metadata inside a work tree.
+pub const XIT_DIR: &str = ".xit";
 
-    let git_dir = Path::new(folder_name);
+/// Environment variable overriding the location of the `.xit` directory.
+pub const XIT_DIR_ENV: &str = "XIT_DIR";
 
-    if git_dir.is_dir() {
-        println!("Xit Exists");
-        return;
+/// Environment variable overriding the location of the working tree.
+pub const XIT_WORK_TREE_ENV: &str = "XIT_WORK_TREE";
+
+/// A handle to a xit repository on disk.
+///
+/// The handle owns the paths of the `.xit` directory and of the working tree,
+/// so every operation is independent of the process working directory. It is
+/// cheap to clone and can be shared between threads.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Repository {
+    git_dir: PathBuf,
+    work_tree: PathBuf,
+}
+
+impl Repository {
+    /// Creates a new repository in `work_tree` and returns a handle to it.
+    pub fn init<P: AsRef<Path>>(work_tree: P) -> Result<Repository> {
+        let work_tree = work_tree.as_ref();
+        let git_dir = work_tree.join(XIT_DIR);
+
+        if git_dir.is_dir() {
+            return Err(Error::Conflict(format!(
+                "Xit repository already exists in {}",
+                git_dir.display()
+            )));
+        }
+        fs::create_dir_all(git_dir.join("objects"))?;
+        fs::create_dir_all(git_dir.join("refs/heads"))?;
+        fs::create_dir_all(git_dir.join("refs/tags"))?;
+
+        // create HEAD file
+        let head_content = "ref: refs/heads/main\n";
+        fs::write(git_dir.join("HEAD"), head_content)?;
+
+        // Create config file
+        let config_content =
+            "[core]\n\trepositoryformatversion = 0\n\tfilemode = true\n\tbare = false\n";
+        fs::write(git_dir.join("config"), config_content)?;
+
+        Repository::open(work_tree)
+    }
+
+    /// Opens the repository whose working tree is `work_tree`.
+    pub fn open<P: AsRef<Path>>(work_tree: P) -> Result<Repository> {
+        let work_tree = work_tree.as_ref();
+        let git_dir = work_tree.join(XIT_DIR);
+        if !git_dir.is_dir() {
+            return Err(Error::NotARepository(work_tree.to_path_buf()));
+        }
+        Ok(Repository {
+            git_dir,
+            work_tree: work_tree.to_path_buf(),
+        })
+    }
+
+    /// Creates a handle from an explicit `.xit` directory and working tree.
+    pub fn with_paths<P: AsRef<Path>, Q: AsRef<Path>>(
+        git_dir: P,
+        work_tree: Q,
+    ) -> Result<Repository> {
+        let git_dir = git_dir.as_ref();
+        if !git_dir.is_dir() {
+            return Err(Error::NotARepository(git_dir.to_path_buf()));
+        }
+        Ok(Repository {
+            git_dir: git_dir.to_path_buf(),
+            work_tree: work_tree.as_ref().to_path_buf(),
+        })
+    }
+
+    /// Finds the repository containing `start` by walking up its parent directories
+    /// until a `.xit` directory is found.
+    pub fn discover<P: AsRef<Path>>(start: P) -> Result<Repository> {
+        let start = absolute(start.as_ref())?;
+        for dir in start.ancestors() {
+            if dir.join(XIT_DIR).is_dir() {
+                return Repository::open(dir);
+            }
+        }
+        Err(Error::NotARepository(start))
+    }
+
+    /// Like [`Repository::discover`], but honours the `XIT_DIR` and `XIT_WORK_TREE`
+    /// environment variables.
+    pub fn discover_from_env<P: AsRef<Path>>(start: P) -> Result<Repository> {
+        let git_dir = env::var_os(XIT_DIR_ENV).map(PathBuf::from);
+        let work_tree = env::var_os(XIT_WORK_TREE_ENV).map(PathBuf::from);
+        Repository::discover_with_overrides(start, git_dir.as_deref(), work_tree.as_deref())
+    }
+
+    /// Discovery with explicit overrides, resolved relative to `start`.
+    ///
+    /// When `git_dir` is given no walking takes place and the working tree
+    /// defaults to `start`. When only `work_tree` is given the `.xit` directory is
+    /// discovered as usual and the working tree is replaced.
+    pub fn discover_with_overrides<P: AsRef<Path>>(
+        start: P,
+        git_dir: Option<&Path>,
+        work_tree: Option<&Path>,
+    ) -> Result<Repository> {
+        let start = absolute(start.as_ref())?;
+        let work_tree = work_tree
+            .map(|path| absolute(&start.join(path)))
+            .transpose()?;
+
+        match git_dir {
+            Some(git_dir) => {
+                let git_dir = absolute(&start.join(git_dir))?;
+                Repository::with_paths(git_dir, work_tree.unwrap_or(start))
+            }
+            None => {
+                let mut repo = Repository::discover(&start)?;
+                if let Some(work_tree) = work_tree {
+                    repo.work_tree = work_tree;
+                }
+                Ok(repo)
+            }
+        }
+    }
+
+    /// Resolves a pathspec given relative to `cwd` into the `/`-separated,
+    /// work-tree relative form used by the index.
+    pub fn pathspec<P: AsRef<Path>>(&self, cwd: P, spec: &str) -> Result<String> {
+        let full_path = normalize(&absolute(cwd.as_ref())?.join(spec));
+        let relative = full_path
+            .strip_prefix(&self.work_tree)
+            .map_err(|_| Error::InvalidInput(format!("'{}' is outside repository", spec)))?;
+        utils::path_to_slash(relative)
+    }
+
+    /// Path of the `.xit` directory.
+    pub fn git_dir(&self) -> &Path {
+        &self.git_dir
+    }
+
+    /// Path of the working tree.
+    pub fn work_tree(&self) -> &Path {
+        &self.work_tree
+    }
+
+    /// The refs of this repository.
+    pub fn refs(&self) -> RefStore<'_> {
+        RefStore::new(&self.git_dir)
+    }
+
+    /// Path of the index file.
+    pub fn index_path(&self) -> PathBuf {
+        self.git_dir.join("index")
     }
-    fs::create_dir_all(git_dir.join("objects")).expect("Failed to create objects directory");
-    fs::create_dir_all(git_dir.join("refs/heads")).expect("Failed to create refs/heads directory");
-    fs::create_dir_all(git_dir.join("refs/tags")).expect("Failed to create refs/tags directory");
 
-    // create HEAD file
-    let head_content = "ref: refs/heads/main\n";
-    fs::write(git_dir.join("HEAD"), head_content).expect("Failed to write HEAD file");
+    /// Stages a file, given relative to the working tree.
+    pub fn add(&self, file_path: &str) -> Result<()> {
+        add::add(self, file_path)
+    }
+
+    /// Commits the staged changes and returns the new commit hash, with the
+    /// warnings of the HEAD update.
+    pub fn commit(&self, message: &str) -> Result<RefChange> {
+        commit::commit(self, message)
+    }
+
+    /// Compares HEAD, the index and the working tree.
+    pub fn status(&self) -> Result<status::StatusResult> {
+        status::status(self)
+    }
+
+    /// Reads an object from the object store, returning its type and content.
+    pub fn read_object(&self, hash: &ObjectId) -> Result<(String, Vec<u8>)> {
+        utils::read_object(&self.git_dir, hash)
+    }
+
+    /// Reads an object and parses it into its typed representation.
+    pub fn read_parsed(&self, hash: &ObjectId) -> Result<Object> {
+        object_read::read_parsed(&self.git_dir, hash)
+    }
+
+    /// Reads and parses a commit object.
+    pub fn read_commit(&self, hash: &ObjectId) -> Result<Commit> {
+        object_read::read_commit(&self.git_dir, hash)
+    }
+}
+
+/// Makes `path` absolute and resolves symlinks when the path exists, so that
+/// paths computed from different starting points share a common prefix.
+fn absolute(path: &Path) -> Result<PathBuf> {
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        env::current_dir()?.join(path)
+    };
+    Ok(fs::canonicalize(&path).unwrap_or_else(|_| normalize(&path)))
+}
 
-    // Create config file
-    let config_content =
-        "[core]\n\trepositoryformatversion = 0\n\tfilemode = true\n\tbare = false\n";
-    fs::write(git_dir.join("config"), config_content).expect("Failed to write config file");
+/// Lexically removes `.` and `..` components from a path.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other.as_os_str()),
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::test_utils::test_repo;
+
+    fn setup(name: &str) -> PathBuf {
+        let (_, temp_dir) = test_repo(name);
+        fs::create_dir_all(temp_dir.join("src/nested")).unwrap();
+        fs::canonicalize(temp_dir).unwrap()
+    }
+
+    #[test]
+    fn test_discover_walks_up_parents() {
+        let temp_dir = setup("xit_test_discover");
+
+        let repo = Repository::discover(temp_dir.join("src/nested")).unwrap();
+        assert_eq!(repo.work_tree(), temp_dir);
+        assert_eq!(repo.git_dir(), temp_dir.join(".xit"));
 
-    //    println!("Initialized empty Git repository in {}", git_dir.display());
+        let spec = repo
+            .pathspec(temp_dir.join("src/nested"), "../main.rs")
+            .unwrap();
+        assert_eq!(spec, "src/main.rs");
+        assert!(
+            repo.pathspec(temp_dir.join("src"), "../../outside.txt")
+                .is_err()
+        );
+
+        fs::remove_dir_all(temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_discover_with_overrides() {
+        let temp_dir = setup("xit_test_discover_overrides");
+        let elsewhere = temp_dir.join("src");
+
+        // An explicit git dir skips discovery and uses the start as work tree.
+        let repo =
+            Repository::discover_with_overrides(&elsewhere, Some(Path::new("../.xit")), None)
+                .unwrap();
+        assert_eq!(repo.git_dir(), temp_dir.join(".xit"));
+        assert_eq!(repo.work_tree(), elsewhere);
+
+        // An explicit work tree replaces the discovered one.
+        let repo = Repository::discover_with_overrides(
+            temp_dir.join("src/nested"),
+            None,
+            Some(Path::new("..")),
+        )
+        .unwrap();
+        assert_eq!(repo.git_dir(), temp_dir.join(".xit"));
+        assert_eq!(repo.work_tree(), elsewhere);
+
+        fs::remove_dir_all(temp_dir).unwrap();
+    }
 }
@@ -0,0 +1,396 @@
+//! Turns revision expressions such as `main~2`, `v1.0^{tree}`, `a1b2c3d` or
+//! `HEAD@{1}` into object ids, following the syntax of `git rev-parse`.
+
+use crate::error::{Error, Result};
+use crate::objects::id::ObjectId;
+use crate::objects::object::Object;
+use crate::repository::refs::RefValue;
+use crate::repository::repo::Repository;
+use crate::repository::{reflog, revwalk};
+use std::fs;
+
+/// Abbreviated object ids shorter than this are never looked up.
+const MIN_ABBREV: usize = 4;
+
+/// A set of commits as given to `xit log`: everything reachable from `include`
+/// that is not reachable from `exclude`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RevRange {
+    pub include: Vec<ObjectId>,
+    pub exclude: Vec<ObjectId>,
+}
+
+/// Resolves a single revision to the object it names, which may be any kind of
+/// object (`v1.0` is the tag object itself, `v1.0^{}` what it points at).
+///
+/// A revision is a name followed by any number of suffixes:
+/// * `HEAD` or `@`, a branch, a tag, a full ref such as `refs/heads/main`, or
+///   an object id abbreviated to at least 4 hex digits;
+/// * `@{N}` right after the name: the value the ref had N changes ago, taken from
+///   its reflog (`@{N}` alone uses the current branch);
+/// * `~N`: the N-th first-parent ancestor (`~` is `~1`);
+/// * `^N`: the N-th parent (`^` is `^1`, `^0` the commit itself);
+/// * `^{commit}`, `^{tree}`, `^{}`: the object peeled to a commit, a tree, or
+///   through any tags.
+pub fn resolve(repo: &Repository, spec: &str) -> Result<ObjectId> {
+    let unknown = || Error::InvalidRef(format!("Unknown revision '{}'", spec));
+    let (base, mut rest) = spec.split_at(spec.find(['~', '^']).unwrap_or(spec.len()));
+
+    let mut id = match base.find("@{") {
+        Some(at) => {
+            let count = base[at + 2..]
+                .strip_suffix('}')
+                .and_then(|count| count.parse().ok())
+                .ok_or_else(unknown)?;
+            reflog_entry(repo, &base[..at], count)?
+        }
+        None => resolve_name(repo, base)?.ok_or_else(unknown)?,
+    };
+
+    while !rest.is_empty() {
+        if let Some(peel_suffix) = rest.strip_prefix("^{") {
+            let (kind, after) = peel_suffix.split_once('}').ok_or_else(unknown)?;
+            id = peel(repo, id, kind)?;
+            rest = after;
+            continue;
+        }
+
+        let operator = rest.as_bytes()[0];
+        rest = &rest[1..];
+        let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        let count: usize = match digits {
+            0 => 1,
+            _ => rest[..digits].parse().map_err(|_| unknown())?,
+        };
+        rest = &rest[digits..];
+
+        let commit = peel(repo, id, "commit")?;
+        id = match operator {
+            b'~' => {
+                let mut ancestor = commit;
+                for _ in 0..count {
+                    ancestor = *repo
+                        .read_commit(&ancestor)?
+                        .parents
+                        .first()
+                        .ok_or_else(unknown)?;
+                }
+                ancestor
+            }
+            b'^' if count == 0 => commit,
+            b'^' => *repo
+                .read_commit(&commit)?
+                .parents
+                .get(count - 1)
+                .ok_or_else(unknown)?,
+            _ => return Err(unknown()),
+        };
+    }
+    Ok(id)
+}
+
+/// Resolves a revision that must name a commit, peeling tags on the way.
+pub fn resolve_commit(repo: &Repository, spec: &str) -> Result<ObjectId> {
+    let id = resolve(repo, spec)?;
+    peel(repo, id, "commit")
+}
+
+/// Resolves a revision or a range of commits:
+/// * `A..B`: commits reachable from B but not from A;
+/// * `A...B`: commits reachable from either side but not from both;
+/// * `^A`: excludes A and its ancestors;
+/// * anything else is a single commit, see [`resolve`].
+///
+/// A side left empty in `A..` or `..B` stands for `HEAD`.
+pub fn resolve_range(repo: &Repository, spec: &str) -> Result<RevRange> {
+    let commit = |side: &str| resolve_commit(repo, if side.is_empty() { "HEAD" } else { side });
+
+    if let Some((left, right)) = spec.split_once("...") {
+        let (left, right) = (commit(left)?, commit(right)?);
+        return Ok(RevRange {
+            include: vec![left, right],
+            exclude: revwalk::merge_bases(repo, left, right)?,
+        });
+    }
+    if let Some((left, right)) = spec.split_once("..") {
+        return Ok(RevRange {
+            include: vec![commit(right)?],
+            exclude: vec![commit(left)?],
+        });
+    }
+    if let Some(excluded) = spec.strip_prefix('^') {
+        return Ok(RevRange {
+            include: Vec::new(),
+            exclude: vec![resolve_commit(repo, excluded)?],
+        });
+    }
+    Ok(RevRange {
+        include: vec![resolve_commit(repo, spec)?],
+        exclude: Vec::new(),
+    })
+}
+
+/// Resolves a name without suffixes, trying full ids, refs and then
+/// abbreviated ids like git does.
+fn resolve_name(repo: &Repository, name: &str) -> Result<Option<ObjectId>> {
+    let name = if name == "@" { "HEAD" } else { name };
+    if name.is_empty() {
+        return Ok(None);
+    }
+    if let Ok(id) = ObjectId::from_hex(name) {
+        return Ok(Some(id));
+    }
+    for candidate in ref_candidates(name) {
+        if let Some(id) = repo.refs().resolve(&candidate)? {
+            return Ok(Some(id));
+        }
+    }
+    if name.len() >= MIN_ABBREV && name.chars().all(|c| c.is_ascii_hexdigit()) {
+        return resolve_abbreviated(repo, name);
+    }
+    Ok(None)
+}
+
+/// The refs a short name may stand for, in the order they are tried.
+fn ref_candidates(name: &str) -> Vec<String> {
+    // Never look outside of the refs (`../config`, `/etc/passwd`).
+    if name.starts_with('/')
+        || name
+            .split('/')
+            .any(|part| part.is_empty() || part.starts_with('.'))
+    {
+        return Vec::new();
+    }
+    let mut candidates = Vec::new();
+    // Only all-caps names such as HEAD or ORIG_HEAD live at the top level.
+    if name.chars().all(|c| c.is_ascii_uppercase() || c == '_') || name.starts_with("refs/") {
+        candidates.push(name.to_string());
+    }
+    for prefix in ["refs/", "refs/tags/", "refs/heads/", "refs/remotes/"] {
+        candidates.push(format!("{}{}", prefix, name));
+    }
+    candidates
+}
+
+/// Looks up the loose objects whose id starts with `prefix`.
+fn resolve_abbreviated(repo: &Repository, prefix: &str) -> Result<Option<ObjectId>> {
+    let prefix = prefix.to_ascii_lowercase();
+    let dir = repo.git_dir().join("objects").join(&prefix[..2]);
+    let mut matches = Vec::new();
+    if dir.is_dir() {
+        for entry in fs::read_dir(dir)? {
+            let hex = format!("{}{}", &prefix[..2], entry?.file_name().to_string_lossy());
+            if hex.starts_with(&prefix)
+                && let Ok(id) = ObjectId::from_hex(&hex)
+            {
+                matches.push(id);
+            }
+        }
+    }
+    match matches.len() {
+        0 => Ok(None),
+        1 => Ok(Some(matches[0])),
+        _ => {
+            matches.sort();
+            let candidates = matches
+                .iter()
+                .map(|id| {
+                    let (kind, _) = repo.read_object(id)?;
+                    Ok(format!("  {} {}", id.short(), kind))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            Err(Error::InvalidRef(format!(
+                "short object ID {} is ambiguous; the candidates are:\n{}",
+                prefix,
+                candidates.join("\n")
+            )))
+        }
+    }
+}
+
+/// The full name of the ref whose reflog `<name>@{n}` reads: the current
+/// branch for an empty name, HEAD for `@`, otherwise the ref `name` stands for.
+pub fn reflog_ref(repo: &Repository, name: &str) -> Result<String> {
+    match name {
+        "" => match repo.refs().head()? {
+            RefValue::Symbolic(target) => Ok(target),
+            RefValue::Direct(_) => Err(Error::InvalidRef("HEAD is not on a branch".to_string())),
+        },
+        "@" => Ok("HEAD".to_string()),
+        name => ref_candidates(name)
+            .into_iter()
+            .find(|candidate| repo.refs().exists(candidate).unwrap_or(false))
+            .ok_or_else(|| Error::InvalidRef(format!("Unknown revision '{}'", name))),
+    }
+}
+
+/// The value `name` had `count` changes ago, from `.xit/logs/<ref>`.
+fn reflog_entry(repo: &Repository, name: &str, count: usize) -> Result<ObjectId> {
+    let ref_path = reflog_ref(repo, name)?;
+    let entries = reflog::read(repo.git_dir(), &ref_path)?;
+    entries
+        .len()
+        .checked_sub(count + 1)
+        .map(|i| entries[i].new)
+        .ok_or_else(|| {
+            Error::InvalidRef(format!(
+                "Log for '{}' only has {} entries",
+                ref_path,
+                entries.len()
+            ))
+        })
+}
+
+/// Follows `id` until it reaches an object of the given kind: `commit`, `tree`,
+/// or `""` for anything that is not a tag.
+fn peel(repo: &Repository, mut id: ObjectId, kind: &str) -> Result<ObjectId> {
+    loop {
+        let object = repo.read_parsed(&id)?;
+        if object.obj_type() == kind {
+            return Ok(id);
+        }
+        match object {
+            Object::Tag(tag) => id = tag.object,
+            Object::Commit(commit) if kind == "tree" => return Ok(commit.tree),
+            _ if kind.is_empty() => return Ok(id),
+            object => {
+                return Err(Error::InvalidRef(format!(
+                    "{} is a {}, not a {}",
+                    id.short(),
+                    object.obj_type(),
+                    kind
+                )));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::commit::Commit;
+    use crate::objects::object::write_object;
+    use crate::objects::signature::Signature;
+    use crate::objects::tag::Tag;
+    use crate::objects::tree::create_tree;
+    use crate::objects::update;
+    use crate::repository::test_utils::test_repo;
+
+    #[test]
+    fn test_resolve_revisions() {
+        let (repo, temp_dir) = test_repo("xit_test_rev_parse");
+        let git_dir = repo.git_dir();
+
+        let tree = create_tree(git_dir, Vec::new()).unwrap();
+        let signature = Signature::parse("A <a@example.com> 1700000000 +0000").unwrap();
+        let commit = |parents: &[ObjectId], message: &str| {
+            Object::Commit(Commit {
+                tree,
+                parents: parents.to_vec(),
+                author: signature.clone(),
+                committer: signature.clone(),
+                extra_headers: Vec::new(),
+                message: format!("{}\n", message),
+            })
+            .write(git_dir)
+            .unwrap()
+        };
+
+        //   root - a - b - merge (main)
+        //           \- side -/
+        let root = commit(&[], "root");
+        let a = commit(&[root], "a");
+        let b = commit(&[a], "b");
+        let side = commit(&[a], "side");
+        let merge = commit(&[b, side], "merge");
+        update::update_branch(git_dir, "main", &merge, "").unwrap();
+        update::update_branch(git_dir, "side", &side, "").unwrap();
+        let tag = Object::Tag(Tag {
+            object: a,
+            obj_type: "commit".to_string(),
+            tag: "v1".to_string(),
+            tagger: Some(signature.clone()),
+            extra_headers: Vec::new(),
+            message: "Version 1\n".to_string(),
+        })
+        .write(git_dir)
+        .unwrap();
+        update::update_tag(git_dir, "v1", &tag).unwrap();
+
+        let rev = |spec: &str| resolve(&repo, spec).unwrap();
+        assert_eq!(rev("HEAD"), merge);
+        assert_eq!(rev("@"), merge);
+        assert_eq!(rev("main"), merge);
+        assert_eq!(rev("refs/heads/side"), side);
+        assert_eq!(rev(&merge.to_hex()), merge);
+        assert_eq!(rev(&merge.abbrev(7)), merge);
+        assert_eq!(rev("HEAD~"), b);
+        assert_eq!(rev("HEAD~2"), a);
+        assert_eq!(rev("main^2"), side);
+        assert_eq!(rev("main^2~1^0"), a);
+        assert_eq!(rev("HEAD^^^"), root);
+        assert_eq!(rev("v1"), tag);
+        assert_eq!(rev("v1^{}"), a);
+        assert_eq!(rev("v1~1"), root);
+        assert_eq!(rev("main^{tree}"), tree);
+        assert_eq!(resolve_commit(&repo, "v1").unwrap(), a);
+
+        assert!(resolve(&repo, "nope").is_err());
+        assert!(resolve(&repo, "root~5").is_err());
+        assert!(resolve(&repo, &format!("{}^2", root)).is_err());
+        assert!(resolve(&repo, "../config").is_err());
+        assert!(resolve(&repo, "main^{tree}^{commit}").is_err());
+
+        // Write blobs until two of them share their first four hex digits.
+        let mut prefixes = std::collections::HashMap::new();
+        let (first, second) = (0..)
+            .find_map(|i: u32| {
+                let id = write_object(git_dir, "blob", &i.to_le_bytes()).unwrap();
+                prefixes
+                    .insert(id.abbrev(MIN_ABBREV), id)
+                    .map(|other| (other, id))
+            })
+            .unwrap();
+        let error = resolve(&repo, &first.abbrev(MIN_ABBREV)).unwrap_err();
+        assert!(error.to_string().contains("ambiguous"));
+        let common = (MIN_ABBREV..40)
+            .find(|&len| first.abbrev(len) != second.abbrev(len))
+            .unwrap();
+        assert_eq!(rev(&second.abbrev(common)), second);
+
+        fs::create_dir_all(git_dir.join("logs/refs/heads")).unwrap();
+        fs::write(
+            git_dir.join("logs/refs/heads/main"),
+            format!(
+                "{} {} A <a@example.com> 1700000000 +0000\tcommit\n{} {} A <a@example.com> 1700000000 +0000\tmerge\n",
+                b, side, side, merge
+            ),
+        )
+        .unwrap();
+        assert_eq!(rev("main@{0}"), merge);
+        assert_eq!(rev("@{1}"), side);
+        assert_eq!(rev("main@{1}~1"), a);
+        assert!(resolve(&repo, "main@{2}").is_err());
+
+        let range = |spec: &str| resolve_range(&repo, spec).unwrap();
+        assert_eq!(
+            range("side..main"),
+            RevRange {
+                include: vec![merge],
+                exclude: vec![side]
+            }
+        );
+        assert_eq!(
+            range("main~1...side"),
+            RevRange {
+                include: vec![b, side],
+                exclude: vec![a]
+            }
+        );
+        assert_eq!(range("side.."), range("side..HEAD"));
+        assert_eq!(range("^v1").exclude, vec![a]);
+        assert_eq!(range("main~1").include, vec![b]);
+
+        fs::remove_dir_all(temp_dir).unwrap();
+    }
+}
@@ -0,0 +1,275 @@
+use crate::error::Result;
+use crate::objects::commit::Commit;
+use crate::objects::id::ObjectId;
+use crate::repository::repo::Repository;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+
+/// Walks commit history through parent links, newest commit first.
+///
+/// Commits are yielded in decreasing committer date, like `git log`; commits with
+/// the same date come out in the order they were discovered. Each commit is
+/// yielded once even when it is reachable along several paths. With
+/// [`RevWalk::topo_order`] no commit is yielded before all of its children.
+pub struct RevWalk<'a> {
+    repo: &'a Repository,
+    /// (committer time, discovery order reversed so earlier wins ties, commit)
+    queue: BinaryHeap<(i64, Reverse<u64>, ObjectId)>,
+    seen: HashSet<ObjectId>,
+    hidden: HashSet<ObjectId>,
+    first_parent: bool,
+    topo_order: bool,
+    /// The sorted commits once a topological walk has been computed.
+    sorted: Option<VecDeque<(ObjectId, Commit)>>,
+    counter: u64,
+}
+
+impl<'a> RevWalk<'a> {
+    pub fn new(repo: &'a Repository) -> RevWalk<'a> {
+        RevWalk {
+            repo,
+            queue: BinaryHeap::new(),
+            seen: HashSet::new(),
+            hidden: HashSet::new(),
+            first_parent: false,
+            topo_order: false,
+            sorted: None,
+            counter: 0,
+        }
+    }
+
+    /// Never show a parent before its children, even when commit dates are
+    /// skewed. The whole history has to be read before the first commit comes out.
+    pub fn topo_order(mut self, topo_order: bool) -> RevWalk<'a> {
+        self.topo_order = topo_order;
+        self
+    }
+
+    /// Only follow the first parent of merge commits.
+    pub fn first_parent(mut self, first_parent: bool) -> RevWalk<'a> {
+        self.first_parent = first_parent;
+        self
+    }
+
+    /// Starts the walk (also) from `id`.
+    pub fn push(&mut self, id: ObjectId) -> Result<()> {
+        self.enqueue(id)
+    }
+
+    /// Excludes `id` and all of its ancestors from the walk, as in `^id` or `id..`.
+    pub fn hide(&mut self, id: ObjectId) -> Result<()> {
+        let mut pending = vec![id];
+        while let Some(id) = pending.pop() {
+            if self.hidden.insert(id) {
+                pending.extend(self.repo.read_commit(&id)?.parents);
+            }
+        }
+        Ok(())
+    }
+
+    fn enqueue(&mut self, id: ObjectId) -> Result<()> {
+        if self.seen.insert(id) {
+            let commit = self.repo.read_commit(&id)?;
+            self.queue
+                .push((commit.committer.time, Reverse(self.counter), id));
+            self.counter += 1;
+        }
+        Ok(())
+    }
+
+    fn next_commit(&mut self) -> Result<Option<(ObjectId, Commit)>> {
+        while let Some((_, _, id)) = self.queue.pop() {
+            let commit = self.repo.read_commit(&id)?;
+            for parent in self.followed_parents(&commit).to_vec() {
+                self.enqueue(parent)?;
+            }
+            if !self.hidden.contains(&id) {
+                return Ok(Some((id, commit)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Reads the whole walk and orders it so that children come before their
+    /// parents, taking the most recent commit whenever there is a choice.
+    fn sort_topologically(&mut self) -> Result<VecDeque<(ObjectId, Commit)>> {
+        let mut commits = HashMap::new();
+        let mut order = HashMap::new();
+        while let Some((id, commit)) = self.next_commit()? {
+            order.insert(id, order.len());
+            commits.insert(id, commit);
+        }
+
+        let mut children: HashMap<ObjectId, usize> = HashMap::new();
+        for commit in commits.values() {
+            for parent in self.followed_parents(commit) {
+                if commits.contains_key(parent) {
+                    *children.entry(*parent).or_default() += 1;
+                }
+            }
+        }
+
+        let mut ready: BinaryHeap<(i64, Reverse<usize>, ObjectId)> = commits
+            .iter()
+            .filter(|(id, _)| !children.contains_key(*id))
+            .map(|(id, commit)| (commit.committer.time, Reverse(order[id]), *id))
+            .collect();
+        let mut sorted = VecDeque::with_capacity(commits.len());
+        while let Some((_, _, id)) = ready.pop() {
+            let commit = commits.remove(&id).expect("each commit is ready once");
+            for parent in self.followed_parents(&commit) {
+                if let Some(count) = children.get_mut(parent) {
+                    *count -= 1;
+                    if *count == 0 {
+                        let time = commits[parent].committer.time;
+                        ready.push((time, Reverse(order[parent]), *parent));
+                    }
+                }
+            }
+            sorted.push_back((id, commit));
+        }
+        Ok(sorted)
+    }
+
+    fn followed_parents<'c>(&self, commit: &'c Commit) -> &'c [ObjectId] {
+        if self.first_parent {
+            &commit.parents[..commit.parents.len().min(1)]
+        } else {
+            &commit.parents
+        }
+    }
+}
+
+/// All commits reachable from `id`, including itself.
+pub fn ancestors(repo: &Repository, id: ObjectId) -> Result<HashSet<ObjectId>> {
+    let mut seen = HashSet::new();
+    let mut pending = vec![id];
+    while let Some(id) = pending.pop() {
+        if seen.insert(id) {
+            pending.extend(repo.read_commit(&id)?.parents);
+        }
+    }
+    Ok(seen)
+}
+
+/// The best common ancestors of two commits: the common ancestors that are not
+/// an ancestor of another common ancestor, newest first.
+pub fn merge_bases(repo: &Repository, a: ObjectId, b: ObjectId) -> Result<Vec<ObjectId>> {
+    let of_a = ancestors(repo, a)?;
+    let common: Vec<ObjectId> = ancestors(repo, b)?
+        .into_iter()
+        .filter(|id| of_a.contains(id))
+        .collect();
+
+    // Anything below a common ancestor is a worse answer than that ancestor.
+    let mut below = HashSet::new();
+    let mut pending = Vec::new();
+    for id in &common {
+        pending.extend(repo.read_commit(id)?.parents);
+    }
+    while let Some(id) = pending.pop() {
+        if below.insert(id) {
+            pending.extend(repo.read_commit(&id)?.parents);
+        }
+    }
+
+    let mut bases = Vec::new();
+    for id in common.into_iter().filter(|id| !below.contains(id)) {
+        bases.push((repo.read_commit(&id)?.committer.time, id));
+    }
+    bases.sort_by(|a, b| b.cmp(a));
+    Ok(bases.into_iter().map(|(_, id)| id).collect())
+}
+
+/// Returns true if `ancestor` is reachable from `id` (or is `id`).
+pub fn is_ancestor(repo: &Repository, ancestor: ObjectId, id: ObjectId) -> Result<bool> {
+    Ok(ancestors(repo, id)?.contains(&ancestor))
+}
+
+impl Iterator for RevWalk<'_> {
+    type Item = Result<(ObjectId, Commit)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.topo_order {
+            return self.next_commit().transpose();
+        }
+        if self.sorted.is_none() {
+            match self.sort_topologically() {
+                Ok(sorted) => self.sorted = Some(sorted),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        self.sorted.as_mut()?.pop_front().map(Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::object::Object;
+    use crate::objects::signature::Signature;
+    use crate::objects::tree::create_tree;
+    use crate::repository::test_utils::test_repo;
+    use std::fs;
+
+    #[test]
+    fn test_walk_follows_parents_by_date() {
+        let (repo, temp_dir) = test_repo("xit_test_revwalk");
+        let git_dir = repo.git_dir();
+
+        let tree = create_tree(git_dir, Vec::new()).unwrap();
+        let commit = |parents: &[ObjectId], time: i64, message: &str| {
+            let signature = Signature {
+                time,
+                ..Signature::now("A", "a@example.com")
+            };
+            Object::Commit(Commit {
+                tree,
+                parents: parents.to_vec(),
+                author: signature.clone(),
+                committer: signature,
+                extra_headers: Vec::new(),
+                message: format!("{}\n", message),
+            })
+            .write(git_dir)
+            .unwrap()
+        };
+
+        //   root(1) - a(2) - b(4) - merge(5)
+        //                \- side(3) -/
+        let root = commit(&[], 1, "root");
+        let a = commit(&[root], 2, "a");
+        let side = commit(&[a], 3, "side");
+        let b = commit(&[a], 4, "b");
+        let merge = commit(&[b, side], 5, "merge");
+
+        let ids = |walk: RevWalk| -> Vec<ObjectId> { walk.map(|item| item.unwrap().0).collect() };
+
+        let mut walk = RevWalk::new(&repo);
+        walk.push(merge).unwrap();
+        assert_eq!(ids(walk), vec![merge, b, side, a, root]);
+
+        let mut walk = RevWalk::new(&repo).first_parent(true);
+        walk.push(merge).unwrap();
+        assert_eq!(ids(walk), vec![merge, b, a, root]);
+
+        let mut walk = RevWalk::new(&repo);
+        walk.push(merge).unwrap();
+        walk.hide(b).unwrap();
+        assert_eq!(ids(walk), vec![merge, side]);
+
+        // A side commit dated before its parent still comes out first.
+        let skewed = commit(&[b], 0, "skewed clock");
+        let mut walk = RevWalk::new(&repo).topo_order(true);
+        walk.push(skewed).unwrap();
+        walk.push(side).unwrap();
+        assert_eq!(ids(walk), vec![side, skewed, b, a, root]);
+
+        assert_eq!(merge_bases(&repo, skewed, side).unwrap(), vec![a]);
+        assert_eq!(merge_bases(&repo, merge, b).unwrap(), vec![b]);
+        assert!(is_ancestor(&repo, root, side).unwrap());
+        assert!(!is_ancestor(&repo, side, b).unwrap());
+
+        fs::remove_dir_all(temp_dir).unwrap();
+    }
+}
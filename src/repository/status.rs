@@ -1,66 +1,102 @@
-use crate::objects::blob;
+use crate::error::Result;
+use crate::objects::id::ObjectId;
+use crate::objects::object;
 use crate::objects::read as object_read;
-use crate::repository::{index, refs};
+use crate::objects::tree::FileMode;
+use crate::repository::index::{self, Index, StatData};
+use crate::repository::repo::Repository;
+use crate::repository::{refs, worktree};
 use std::fs;
 use std::{
     collections::{HashMap, HashSet},
-    io,
-    path::{Path, PathBuf},
+    path::Path,
 };
 use term_colr::{green, red};
 
 /// Represents the result of a status check, categorized into staged, unstaged, and untracked.
-struct StatusResult {
-    staged: HashMap<String, String>,
-    unstaged: HashMap<String, String>,
-    untracked: HashSet<String>,
+#[derive(Debug, Default)]
+pub struct StatusResult {
+    pub staged: HashMap<String, String>,
+    pub unstaged: HashMap<String, String>,
+    pub untracked: HashSet<String>,
+}
+
+impl StatusResult {
+    /// Returns true when there is nothing to commit and no untracked files.
+    pub fn is_clean(&self) -> bool {
+        self.staged.is_empty() && self.unstaged.is_empty() && self.untracked.is_empty()
+    }
 }
 
 /// Main function to check the status of the repository.
 /// It compares HEAD, the index, and the working directory, then prints the status.
-pub fn check_status() -> io::Result<()> {
-    let index_entries = get_index_entries()?;
-    let head_tree_entries = get_head_tree_entries()?;
-    let ignore_patterns = read_ignore_file(".xitignore")?;
+pub fn check_status(repo: &Repository) -> Result<()> {
+    let status_result = status(repo)?;
+    print_status(&status_result);
+    Ok(())
+}
+
+/// Compares HEAD, the index, and the working directory without printing anything.
+///
+/// Tracked files whose stat data still matches the index are not read. Files
+/// that had to be read but turned out unchanged get their stat data refreshed
+/// in the index, so the next status can skip them.
+pub fn status(repo: &Repository) -> Result<StatusResult> {
+    let index_path = repo.index_path();
+    let head_tree_entries = get_head_tree_entries(repo.git_dir())?;
+    let (stat_cache, index_mtime, index_entries) = if index_path.exists() {
+        let index_mtime = StatData::from_metadata(&fs::metadata(&index_path)?);
+        let index = Index::read(&index_path)?;
+        let entries = index.to_map();
+        (index, index_mtime, entries)
+    } else {
+        // Without an index file HEAD is the snapshot, and with no stat data
+        // every file is compared.
+        (
+            Index::default(),
+            StatData::default(),
+            head_tree_entries.clone(),
+        )
+    };
+    let ignore_patterns = worktree::read_ignore_file(&repo.work_tree().join(".xitignore"))?;
 
-    let (unstaged_changes, untracked_files) =
-        get_unstaged_and_untracked(&index_entries, &ignore_patterns)?;
+    let worktree = get_unstaged_and_untracked(
+        repo.work_tree(),
+        &index_entries,
+        &stat_cache,
+        &index_mtime,
+        &ignore_patterns,
+        worktree::configured_threads(repo)?,
+    )?;
+    if !worktree.refreshed.is_empty() {
+        // Best effort, as in git: a locked or read-only index only means the
+        // files are read again next time.
+        let _ = refresh_index(&index_path, worktree.refreshed);
+    }
 
     let mut status_result = StatusResult {
         staged: get_staged_changes(&index_entries, &head_tree_entries),
-        unstaged: unstaged_changes,
-        untracked: untracked_files,
+        unstaged: worktree.unstaged,
+        untracked: worktree.untracked,
     };
 
     // Exclude files that are staged for addition from the untracked list.
     for path in status_result.staged.keys() {
-        if status_result.untracked.contains(path) {
-            status_result.untracked.remove(path);
-        }
+        status_result.untracked.remove(path);
     }
 
-    print_status(&status_result);
-    Ok(())
-}
-
-/// Reads the index file and returns its entries.
-fn get_index_entries() -> io::Result<HashMap<String, String>> {
-    let index_path = Path::new(".xit").join("index");
-    if index_path.exists() {
-        index::read_index(&index_path)
-    } else {
-        Ok(HashMap::new())
-    }
+    Ok(status_result)
 }
 
 /// Reads the HEAD commit's tree and returns its file entries.
-fn get_head_tree_entries() -> io::Result<HashMap<String, String>> {
-    if let Ok(head_ref_path) = refs::get_head_ref_path() {
-        if let Ok(head_commit_hash) = refs::get_commit_hash(&head_ref_path) {
-            if let Ok(tree_hash) = object_read::get_commit_tree_hash(&head_commit_hash) {
-                return object_read::list_files_in_tree(&tree_hash);
-            }
-        }
+pub(crate) fn get_head_tree_entries(
+    git_dir: &Path,
+) -> Result<HashMap<String, (ObjectId, FileMode)>> {
+    if let Ok(head_ref_path) = refs::get_head_ref_path(git_dir)
+        && let Ok(head_commit_hash) = refs::get_commit_hash(git_dir, &head_ref_path)
+        && let Ok(tree_hash) = object_read::get_commit_tree_hash(git_dir, &head_commit_hash)
+    {
+        return object_read::list_files_in_tree(git_dir, &tree_hash);
     }
     // No HEAD commit exists yet or other error
     Ok(HashMap::new())
@@ -68,16 +104,17 @@ fn get_head_tree_entries() -> io::Result<HashMap<String, String>> {
 
 /// Compares HEAD and the index to find staged changes.
 fn get_staged_changes(
-    index_entries: &HashMap<String, String>,
-    head_tree_entries: &HashMap<String, String>,
+    index_entries: &HashMap<String, (ObjectId, FileMode)>,
+    head_tree_entries: &HashMap<String, (ObjectId, FileMode)>,
 ) -> HashMap<String, String> {
     let mut staged_changes = HashMap::new();
 
     // Check for new files and modifications
-    for (path, index_hash) in index_entries {
+    for (path, index_entry) in index_entries {
         match head_tree_entries.get(path) {
-            Some(head_hash) if head_hash != index_hash => {
-                staged_changes.insert(path.clone(), "modified".to_string());
+            Some(head_entry) if head_entry != index_entry => {
+                let change = change_kind(head_entry.1, index_entry.1);
+                staged_changes.insert(path.clone(), change.to_string());
             }
             None => {
                 staged_changes.insert(path.clone(), "new file".to_string());
@@ -87,7 +124,7 @@ fn get_staged_changes(
     }
 
     // Check for deletions
-    for (path, _head_hash) in head_tree_entries {
+    for path in head_tree_entries.keys() {
         if !index_entries.contains_key(path) {
             staged_changes.insert(path.clone(), "deleted".to_string());
         }
@@ -96,51 +133,111 @@ fn get_staged_changes(
     staged_changes
 }
 
+/// How a file with different content or mode changed: git tells a file
+/// replaced by a symbolic link, or the other way round, from other changes.
+fn change_kind(old: FileMode, new: FileMode) -> &'static str {
+    if (old == FileMode::Symlink) != (new == FileMode::Symlink) {
+        "typechange"
+    } else {
+        "modified"
+    }
+}
+
+/// What the working tree walk found.
+struct WorktreeChanges {
+    unstaged: HashMap<String, String>,
+    untracked: HashSet<String>,
+    /// Unchanged files whose stat data in the index is out of date.
+    refreshed: Vec<(String, ObjectId, StatData)>,
+}
+
 /// Compares the index and working directory for unstaged changes and untracked files.
+///
+/// `stat_cache` is the index file with the stat data of the staged files, and
+/// `index_mtime` the time it was written, below which matching stat data can
+/// be trusted.
 fn get_unstaged_and_untracked(
-    index_entries: &HashMap<String, String>,
+    work_tree: &Path,
+    index_entries: &HashMap<String, (ObjectId, FileMode)>,
+    stat_cache: &Index,
+    index_mtime: &StatData,
     ignore_patterns: &HashSet<String>,
-) -> io::Result<(HashMap<String, String>, HashSet<String>)> {
+    threads: usize,
+) -> Result<WorktreeChanges> {
     let mut unstaged_changes = HashMap::new();
     let mut untracked_files = HashSet::new();
-    let mut working_dir_files = HashSet::new();
+    let mut refreshed = Vec::new();
 
-    for entry in walkdir::WalkDir::new(".")
-        .into_iter()
-        .filter_entry(|e| !is_ignored(e, ignore_patterns))
-        .filter_map(|e| e.ok())
-    {
-        let path = entry.path();
-        if path.is_file() {
-            let relative_path = path_to_string(path)?;
-            working_dir_files.insert(relative_path.clone());
-
-            if let Some(index_hash) = index_entries.get(&relative_path) {
-                // File is tracked, check for modifications.
-                let content_bytes = fs::read(path)?;
-                // Normalize line endings (CRLF -> LF) before hashing to prevent platform issues.
-                let content_str = String::from_utf8_lossy(&content_bytes);
-                let normalized_content = content_str.replace("\r\n", "\n");
-                let wd_hash = blob::hash_to_hex(&blob::compute_sha1(normalized_content.as_bytes()));
-
-                if &wd_hash != index_hash {
-                    unstaged_changes.insert(relative_path, "modified".to_string());
-                }
-            } else {
-                // File is not tracked.
-                untracked_files.insert(relative_path);
+    let files = worktree::walk(work_tree, work_tree, ignore_patterns, threads)?;
+    let mut to_hash = Vec::new();
+    for file in &files {
+        if let Some((index_hash, index_mode)) = index_entries.get(&file.path) {
+            // File is tracked, check for modifications. A new mode (chmod, or
+            // a file replaced by a symbolic link) is a change whatever the
+            // content, and does not always show in the stat data.
+            let mode = index::file_mode(&file.metadata);
+            if mode != *index_mode {
+                let change = change_kind(*index_mode, mode);
+                unstaged_changes.insert(file.path.clone(), change.to_string());
+                continue;
             }
+            // The stat data was taken before reading, so a write in between
+            // leaves it stale.
+            let stat = StatData::from_metadata(&file.metadata);
+            let cached = stat_cache.get(&file.path);
+            if let Some(entry) = cached
+                && entry.id == *index_hash
+                && entry.stat.matches(&stat)
+                && !entry.stat.is_racy(index_mtime)
+            {
+                continue;
+            }
+            let refresh = cached.is_some_and(|entry| entry.id == *index_hash);
+            to_hash.push((file.path.as_str(), *index_hash, stat, refresh));
+        } else {
+            // File is not tracked.
+            untracked_files.insert(file.path.clone());
+        }
+    }
+
+    // Hashed exactly as `xit add` stores the file, so an unchanged file gets
+    // the id it has in the index.
+    let hashes = worktree::map_parallel(&to_hash, threads, |(path, ..)| -> Result<ObjectId> {
+        let content = worktree::read_content(&work_tree.join(path))?;
+        Ok(object::hash_object("blob", &content))
+    });
+    for ((path, index_hash, stat, refresh), wd_hash) in to_hash.into_iter().zip(hashes) {
+        if wd_hash? != index_hash {
+            unstaged_changes.insert(path.to_string(), "modified".to_string());
+        } else if refresh {
+            refreshed.push((path.to_string(), index_hash, stat));
         }
     }
 
     // Check for deleted files (in index but not in working dir)
+    let working_dir_files: HashSet<&str> = files.iter().map(|file| file.path.as_str()).collect();
     for path in index_entries.keys() {
-        if !working_dir_files.contains(path) {
+        if !working_dir_files.contains(path.as_str()) {
             unstaged_changes.insert(path.clone(), "deleted".to_string());
         }
     }
 
-    Ok((unstaged_changes, untracked_files))
+    Ok(WorktreeChanges {
+        unstaged: unstaged_changes,
+        untracked: untracked_files,
+        refreshed,
+    })
+}
+
+/// Stores fresh stat data for files found unchanged. The index is read again
+/// under its lock, and entries staged anew in the meantime are left alone.
+fn refresh_index(index_path: &Path, refreshed: Vec<(String, ObjectId, StatData)>) -> Result<()> {
+    let (mut index, lock) = Index::lock(index_path)?;
+    let mut changed = false;
+    for (path, id, stat) in refreshed {
+        changed |= index.update_stat(&path, &id, stat);
+    }
+    if changed { index.commit(lock) } else { Ok(()) }
 }
 
 /// Prints the final status output to the console with colors.
@@ -171,7 +268,7 @@ fn print_status(result: &StatusResult) {
         println!();
     }
 
-    if result.staged.is_empty() && result.unstaged.is_empty() && result.untracked.is_empty() {
+    if result.is_clean() {
         println!("nothing to commit, working tree clean");
     }
 }
@@ -190,40 +287,141 @@ fn print_changes(changes: &HashMap<String, String>, color: &str) {
     }
 }
 
-/// Reads a .xitignore file and returns a set of patterns.
-fn read_ignore_file(file_name: &str) -> io::Result<HashSet<String>> {
-    let mut patterns = HashSet::new();
-    patterns.insert(".xit".to_string()); // Always ignore the .xit directory
-    patterns.insert(".git".to_string()); // Also ignore .git
-    patterns.insert("target".to_string()); // Ignore rust build directory
-
-    if let Ok(content) = fs::read_to_string(file_name) {
-        for line in content.lines() {
-            if !line.trim().is_empty() && !line.starts_with('#') {
-                patterns.insert(line.trim().to_string());
-            }
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::test_utils::test_repo;
+    use std::time::{Duration, SystemTime};
+
+    fn set_mtime(path: &Path, time: SystemTime) {
+        fs::File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(time)
+            .unwrap();
     }
-    Ok(patterns)
-}
 
-/// Checks if a directory entry should be ignored.
-fn is_ignored(entry: &walkdir::DirEntry, ignore_patterns: &HashSet<String>) -> bool {
-    entry
-        .path()
-        .components()
-        .any(|component| match component.as_os_str().to_str() {
-            Some(s) => ignore_patterns.contains(s),
-            None => false,
-        })
-}
+    #[test]
+    fn test_stat_cache_and_racy_entries() {
+        let (repo, temp_dir) = test_repo("xit_test_status_stat_cache");
+        let file = temp_dir.join("a.txt");
 
-/// Converts a Path to a String, ensuring it's a valid relative path.
-fn path_to_string(path: &Path) -> io::Result<String> {
-    path.strip_prefix("./")
-        .unwrap_or(path)
-        .to_str()
-        .map(|s| s.to_string())
-        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Path contains invalid UTF-8"))
-}
+        // Staged long before the index was written: matching stat data is
+        // trusted, so a same-size rewrite that keeps the mtime goes unseen,
+        // as in git.
+        let past = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        fs::write(&file, "one").unwrap();
+        set_mtime(&file, past);
+        repo.add("a.txt").unwrap();
+        fs::write(&file, "two").unwrap();
+        set_mtime(&file, past);
+        assert!(repo.status().unwrap().unstaged.is_empty());
+
+        // A changed mtime makes status look at the content.
+        set_mtime(&file, past + Duration::from_secs(1));
+        assert!(repo.status().unwrap().unstaged.contains_key("a.txt"));
+
+        // Modified no earlier than the index was written: racy, so the
+        // cached size is smudged and the content is compared.
+        let future = SystemTime::now() + Duration::from_secs(3600);
+        fs::write(&file, "one").unwrap();
+        set_mtime(&file, future);
+        repo.add("a.txt").unwrap();
+        fs::write(&file, "two").unwrap();
+        set_mtime(&file, future);
+        let entry = Index::read(&repo.index_path()).unwrap().entries()[0].clone();
+        assert_eq!(entry.stat.size, 0);
+        assert!(repo.status().unwrap().unstaged.contains_key("a.txt"));
+
+        fs::remove_dir_all(temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_racy_entries_stay_smudged_after_rewrite() {
+        let (repo, temp_dir) = test_repo("xit_test_status_racy_rewrite");
+        let file = temp_dir.join("a.txt");
 
+        // A same-size edit within the timestamp the file was staged at.
+        let staged = SystemTime::now() + Duration::from_secs(3600);
+        fs::write(&file, "one").unwrap();
+        set_mtime(&file, staged);
+        repo.add("a.txt").unwrap();
+        fs::write(&file, "two").unwrap();
+        set_mtime(&file, staged);
+
+        // Another index write, after which the index is newer than the file:
+        // the entry is no longer racy, but its smudged size still differs.
+        fs::write(temp_dir.join("b.txt"), "b").unwrap();
+        repo.add("b.txt").unwrap();
+        set_mtime(&repo.index_path(), staged + Duration::from_secs(1));
+        let status = repo.status().unwrap();
+        assert_eq!(
+            status.unstaged.get("a.txt").map(String::as_str),
+            Some("modified")
+        );
+
+        fs::remove_dir_all(temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_unchanged_files_are_clean() {
+        let (repo, temp_dir) = test_repo("xit_test_status_clean");
+        fs::create_dir_all(temp_dir.join("src")).unwrap();
+
+        // Line endings are hashed as stored, so CRLF files are clean too.
+        let file = temp_dir.join("a.txt");
+        fs::write(&file, "one\r\ntwo\r\n").unwrap();
+        fs::write(temp_dir.join("src/lib.rs"), "").unwrap();
+        repo.add(".").unwrap();
+        repo.commit("First").unwrap();
+        assert!(repo.status().unwrap().is_clean());
+
+        // A touched but unchanged file is read, found clean, and its new stat
+        // data is stored so the next status can skip it.
+        let past = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        set_mtime(&file, past);
+        assert!(repo.status().unwrap().is_clean());
+        let index = Index::read(&repo.index_path()).unwrap();
+        let current = StatData::from_metadata(&fs::metadata(&file).unwrap());
+        assert!(index.get("a.txt").unwrap().stat.matches(&current));
+
+        fs::write(&file, "one\ntwo\n").unwrap();
+        let status = repo.status().unwrap();
+        assert_eq!(status.unstaged.len(), 1);
+        assert_eq!(status.unstaged["a.txt"], "modified");
+
+        fs::remove_dir_all(temp_dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_mode_changes_are_reported() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let (repo, temp_dir) = test_repo("xit_test_status_modes");
+
+        fs::write(temp_dir.join("run.sh"), "#!/bin/sh\n").unwrap();
+        fs::write(temp_dir.join("target.txt"), "target").unwrap();
+        fs::write(temp_dir.join("link"), "target.txt").unwrap();
+        repo.add(".").unwrap();
+        repo.commit("First").unwrap();
+
+        // Same content, new mode: neither changes the mtime or the size.
+        fs::set_permissions(temp_dir.join("run.sh"), fs::Permissions::from_mode(0o755)).unwrap();
+        fs::remove_file(temp_dir.join("link")).unwrap();
+        std::os::unix::fs::symlink("target.txt", temp_dir.join("link")).unwrap();
+        let status = repo.status().unwrap();
+        assert_eq!(status.unstaged.len(), 2);
+        assert_eq!(status.unstaged["run.sh"], "modified");
+        assert_eq!(status.unstaged["link"], "typechange");
+
+        repo.add(".").unwrap();
+        let status = repo.status().unwrap();
+        assert!(status.unstaged.is_empty());
+        assert_eq!(status.staged["run.sh"], "modified");
+        assert_eq!(status.staged["link"], "typechange");
+
+        fs::remove_dir_all(temp_dir).unwrap();
+    }
+}
@@ -0,0 +1,166 @@
+//! Lightweight and annotated tags (`xit tag`).
+
+use crate::error::{Error, Result};
+use crate::objects::object::Object;
+use crate::objects::signature::Signature;
+use crate::objects::tag::Tag;
+use crate::objects::update;
+use crate::repository::refs::{Expected, RefChange};
+use crate::repository::repo::Repository;
+use crate::repository::{config, date, rev_parse};
+use term_colr::yellow;
+
+/// Tags `target` (any revision, see [`rev_parse::resolve`]) and returns the id
+/// the tag ref points to.
+///
+/// With a `message` this creates an annotated tag: a tag object recording the
+/// tagged object, the tagger and the message. Otherwise the ref points straight
+/// at the target. An existing tag is only replaced with `force`.
+pub fn create(
+    repo: &Repository,
+    name: &str,
+    target: &str,
+    message: Option<&str>,
+    force: bool,
+) -> Result<RefChange> {
+    let git_dir = repo.git_dir();
+    if !force && update::reference_exists(git_dir, &format!("refs/tags/{}", name)) {
+        return Err(Error::Conflict(format!("Tag '{}' already exists", name)));
+    }
+
+    let object = rev_parse::resolve(repo, target)?;
+    let id = match message {
+        Some(message) => {
+            let (obj_type, _) = repo.read_object(&object)?;
+            let user = config::get_user_config(git_dir)?;
+            let mut message = message.trim_end().to_string();
+            message.push('\n');
+            Object::Tag(Tag {
+                object,
+                obj_type,
+                tag: name.to_string(),
+                tagger: Some(Signature::now(&user.name, &user.email)),
+                extra_headers: Vec::new(),
+                message,
+            })
+            .write(git_dir)?
+        }
+        None => object,
+    };
+    let warnings = if force {
+        update::update_tag(git_dir, name, &id)?
+    } else {
+        // Fails if the tag was created since the check above.
+        update::create_tag(git_dir, name, &id)?
+    };
+    Ok(RefChange { id, warnings })
+}
+
+/// Deletes a tag and returns the id its ref pointed to.
+pub fn delete(repo: &Repository, name: &str) -> Result<RefChange> {
+    let git_dir = repo.git_dir();
+    let id = update::read_reference(git_dir, &format!("refs/tags/{}", name))
+        .map_err(|_| Error::InvalidRef(format!("Tag '{}' not found", name)))?;
+    let warnings = update::delete_tag(git_dir, name, Expected::Value(id))?;
+    Ok(RefChange { id, warnings })
+}
+
+/// The tag names matching any of `patterns` (all tags when there are none),
+/// sorted. Patterns are shell globs: `*` matches any run of characters and `?`
+/// a single one.
+pub fn list(repo: &Repository, patterns: &[String]) -> Result<Vec<String>> {
+    let mut tags = update::list_tags(repo.git_dir())?;
+    if !patterns.is_empty() {
+        tags.retain(|tag| patterns.iter().any(|pattern| glob_match(pattern, tag)));
+    }
+    Ok(tags)
+}
+
+/// Renders an annotated tag like `git show`, without a final newline.
+pub fn format_tag(tag: &Tag, color: bool) -> String {
+    let title = format!("tag {}", tag.tag);
+    let mut out = if color { yellow!("{}", title) } else { title };
+    out.push('\n');
+    if let Some(tagger) = &tag.tagger {
+        out.push_str(&format!(
+            "Tagger: {} <{}>\nDate:   {}\n",
+            tagger.name,
+            tagger.email,
+            date::format_date(tagger.time, tagger.offset_minutes)
+        ));
+    }
+    out.push('\n');
+    out.push_str(tag.message.trim_end());
+    out
+}
+
+fn glob_match(pattern: &str, name: &str) -> bool {
+    fn matches(pattern: &[u8], name: &[u8]) -> bool {
+        match pattern.split_first() {
+            None => name.is_empty(),
+            Some((b'*', rest)) => (0..=name.len()).any(|skip| matches(rest, &name[skip..])),
+            Some((b'?', rest)) => !name.is_empty() && matches(rest, &name[1..]),
+            Some((c, rest)) => name.first() == Some(c) && matches(rest, &name[1..]),
+        }
+    }
+    matches(pattern.as_bytes(), name.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::test_utils::test_repo;
+    use std::fs;
+
+    #[test]
+    fn test_annotated_and_lightweight_tags() {
+        let (repo, temp_dir) = test_repo("xit_test_tag");
+        fs::write(temp_dir.join("a.txt"), "one").unwrap();
+        repo.add("a.txt").unwrap();
+        let commit = repo.commit("First").unwrap().id;
+
+        let light = create(&repo, "v1.0-rc1", "HEAD", None, false).unwrap().id;
+        assert_eq!(light, commit);
+
+        let annotated = create(&repo, "v1.0", "main", Some("Release 1.0\n\n"), false)
+            .unwrap()
+            .id;
+        let Object::Tag(tag) = repo.read_parsed(&annotated).unwrap() else {
+            panic!("expected a tag object");
+        };
+        assert_eq!(tag.object, commit);
+        assert_eq!(tag.obj_type, "commit");
+        assert_eq!(tag.tag, "v1.0");
+        assert_eq!(tag.tagger.as_ref().unwrap().name, "Test User");
+        assert_eq!(tag.message, "Release 1.0\n");
+        assert_eq!(rev_parse::resolve_commit(&repo, "v1.0").unwrap(), commit);
+        assert!(
+            format_tag(&tag, false).starts_with("tag v1.0\nTagger: Test User <test@example.com>\n")
+        );
+        assert!(format_tag(&tag, false).ends_with("\n\nRelease 1.0"));
+
+        assert!(create(&repo, "v1.0", "HEAD", None, false).is_err());
+        assert_eq!(
+            create(&repo, "v1.0", "HEAD", None, true).unwrap().id,
+            commit
+        );
+        create(&repo, "v2.0", "HEAD", None, false).unwrap();
+
+        assert_eq!(list(&repo, &[]).unwrap(), ["v1.0", "v1.0-rc1", "v2.0"]);
+        let patterns = ["v1.*".to_string(), "v?.0".to_string()];
+        assert_eq!(
+            list(&repo, &patterns).unwrap(),
+            ["v1.0", "v1.0-rc1", "v2.0"]
+        );
+        assert_eq!(list(&repo, &["*rc?".to_string()]).unwrap(), ["v1.0-rc1"]);
+        assert_eq!(
+            list(&repo, &["v3*".to_string()]).unwrap(),
+            Vec::<String>::new()
+        );
+
+        assert_eq!(delete(&repo, "v2.0").unwrap().id, commit);
+        assert!(delete(&repo, "v2.0").is_err());
+
+        fs::remove_dir_all(temp_dir).unwrap();
+    }
+}
@@ -0,0 +1,25 @@
+//! Fixtures shared by the tests.
+
+use crate::repository::repo::Repository;
+use std::fs;
+use std::path::PathBuf;
+
+/// Creates a repository in a fresh directory `name` under the system's
+/// temporary directory, with a user set so that it can commit. Returns the
+/// repository and its working tree.
+pub fn test_repo(name: &str) -> (Repository, PathBuf) {
+    let temp_dir = std::env::temp_dir().join(name);
+    let _ = fs::remove_dir_all(&temp_dir);
+    fs::create_dir_all(&temp_dir).unwrap();
+    let repo = Repository::init(&temp_dir).unwrap();
+    set_test_user(&repo);
+    (repo, temp_dir)
+}
+
+/// Sets the user that commits are made by in the repository's config.
+pub fn set_test_user(repo: &Repository) {
+    let config_path = repo.git_dir().join("config");
+    let mut config = fs::read_to_string(&config_path).unwrap();
+    config.push_str("[user]\n    name = Test User\n    email = test@example.com\n");
+    fs::write(config_path, config).unwrap();
+}
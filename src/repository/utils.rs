@@ -1,38 +1,53 @@
+use crate::error::{Error, Result};
+use crate::objects::id::ObjectId;
 use std::io;
+use std::path::Path;
 
-pub fn hex_to_bytes(hex: &str) -> std::result::Result<Vec<u8>, std::num::ParseIntError> {
-    (0..hex.len())
-        .step_by(2)
-        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
-        .collect()
-}
-
-pub fn read_object(hash: &str) -> io::Result<(String, Vec<u8>)> {
+pub fn read_object(git_dir: &Path, hash: &ObjectId) -> Result<(String, Vec<u8>)> {
     use flate2::read::ZlibDecoder;
     use std::fs::File;
     use std::io::Read;
 
-    let path = format!(".xit/objects/{}/{}", &hash[..2], &hash[2..]);
-    let file = File::open(path)?;
+    let (dir_name, file_name) = hash.loose_path_parts();
+    let path = git_dir.join("objects").join(dir_name).join(file_name);
+    let file = File::open(path).map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => Error::ObjectNotFound(*hash),
+        _ => Error::Io(e),
+    })?;
     let mut decoder = ZlibDecoder::new(file);
     let mut buffer = Vec::new();
-    decoder.read_to_end(&mut buffer)?;
+    decoder
+        .read_to_end(&mut buffer)
+        .map_err(|_| Error::CorruptObject(format!("{}: failed to decompress", hash)))?;
 
     let null_byte_pos = buffer
         .iter()
         .position(|&b| b == 0)
-        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid object format"))?;
+        .ok_or_else(|| Error::CorruptObject(format!("{}: invalid object format", hash)))?;
 
     let header = String::from_utf8_lossy(&buffer[..null_byte_pos]);
     let content = buffer[null_byte_pos + 1..].to_vec();
 
     let parts: Vec<&str> = header.split_whitespace().collect();
     if parts.len() != 2 {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidData,
-            "Invalid object header",
-        ));
+        return Err(Error::CorruptObject(format!(
+            "{}: invalid object header",
+            hash
+        )));
     }
 
     Ok((parts[0].to_string(), content))
-}
\ No newline at end of file
+}
+
+/// Converts a relative path to the `/`-separated form used in the index and in trees.
+pub fn path_to_slash(path: &Path) -> Result<String> {
+    let parts = path
+        .components()
+        .map(|component| {
+            component.as_os_str().to_str().ok_or_else(|| {
+                Error::InvalidInput(format!("Path contains invalid UTF-8: {}", path.display()))
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(parts.join("/"))
+}
@@ -0,0 +1,277 @@
+//! Scanning the working tree: listing its files and hashing them, spread over
+//! several threads. Results always come back in the same order, whatever the
+//! number of threads, so output never depends on scheduling.
+//!
+//! The number of threads is `core.threads`; 0 or no value means one per core.
+
+use crate::error::{Error, Result};
+use crate::repository::repo::Repository;
+use crate::repository::{config, utils};
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+
+/// A file found in the working tree.
+#[derive(Debug)]
+pub struct WorktreeFile {
+    /// `/`-separated path relative to the working tree.
+    pub path: String,
+    /// Taken while walking, before the file is read. For a symbolic link this
+    /// describes the link itself.
+    pub metadata: fs::Metadata,
+}
+
+/// The number of threads configured with `core.threads`, or the number of
+/// cores when it is 0 or not set.
+pub fn configured_threads(repo: &Repository) -> Result<usize> {
+    let configured = match config::get_config_value(repo.git_dir(), "core", "threads")? {
+        Some(value) => value
+            .parse::<usize>()
+            .map_err(|_| Error::Config(format!("Invalid core.threads '{}'", value)))?,
+        None => 0,
+    };
+    Ok(match configured {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    })
+}
+
+/// Lists the files below `start`, a directory of the working tree, sorted by
+/// path. Directories whose name is in `ignore_patterns` are not entered and
+/// such files are left out; entries that cannot be read are skipped.
+/// Symbolic links are never followed: each one is listed as a file, whatever
+/// it points to, and even when it points nowhere.
+pub fn walk(
+    work_tree: &Path,
+    start: &Path,
+    ignore_patterns: &HashSet<String>,
+    threads: usize,
+) -> Result<Vec<WorktreeFile>> {
+    struct Queue {
+        dirs: Vec<PathBuf>,
+        /// Threads reading a directory, which may queue more.
+        busy: usize,
+    }
+    let queue = Mutex::new(Queue {
+        dirs: vec![start.to_path_buf()],
+        busy: 0,
+    });
+    let changed = Condvar::new();
+
+    let worker = || {
+        let mut found = Vec::new();
+        loop {
+            let dir = {
+                let mut queue = queue.lock().expect("walk queue poisoned");
+                loop {
+                    if let Some(dir) = queue.dirs.pop() {
+                        queue.busy += 1;
+                        break Some(dir);
+                    }
+                    if queue.busy == 0 {
+                        break None;
+                    }
+                    queue = changed.wait(queue).expect("walk queue poisoned");
+                }
+            };
+            let Some(dir) = dir else {
+                changed.notify_all();
+                return found;
+            };
+            let subdirs = scan_dir(work_tree, &dir, ignore_patterns, &mut found);
+            let mut queue = queue.lock().expect("walk queue poisoned");
+            queue.dirs.extend(subdirs);
+            queue.busy -= 1;
+            changed.notify_all();
+        }
+    };
+
+    let found: Vec<Result<WorktreeFile>> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads.max(1)).map(|_| scope.spawn(worker)).collect();
+        workers
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("walk thread panicked"))
+            .collect()
+    });
+    let mut files = found.into_iter().collect::<Result<Vec<_>>>()?;
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(files)
+}
+
+/// Reads one directory, adding its files to `found` and returning the
+/// subdirectories to walk.
+fn scan_dir(
+    work_tree: &Path,
+    dir: &Path,
+    ignore_patterns: &HashSet<String>,
+    found: &mut Vec<Result<WorktreeFile>>,
+) -> Vec<PathBuf> {
+    let mut subdirs = Vec::new();
+    let Ok(entries) = fs::read_dir(dir) else {
+        return subdirs;
+    };
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        if is_ignored(&entry.file_name(), ignore_patterns) {
+            continue;
+        }
+        // The entry's own metadata: a symbolic link is not followed.
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        let path = entry.path();
+        if metadata.is_dir() {
+            subdirs.push(path);
+            continue;
+        }
+        if metadata.is_file() || metadata.file_type().is_symlink() {
+            let relative = path.strip_prefix(work_tree).unwrap_or(&path);
+            found.push(utils::path_to_slash(relative).map(|path| WorktreeFile { path, metadata }));
+        }
+    }
+    subdirs
+}
+
+/// Reads what git stores for a file of the working tree: its content, or for
+/// a symbolic link the path it points to.
+pub fn read_content(path: &Path) -> io::Result<Vec<u8>> {
+    if !fs::symlink_metadata(path)?.file_type().is_symlink() {
+        return fs::read(path);
+    }
+    let target = fs::read_link(path)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        Ok(target.as_os_str().as_bytes().to_vec())
+    }
+    #[cfg(not(unix))]
+    {
+        Ok(target.to_string_lossy().replace('\\', "/").into_bytes())
+    }
+}
+
+/// Applies `f` to every item on up to `threads` threads and returns the
+/// results in the order of the items.
+pub fn map_parallel<T, R, F>(items: &[T], threads: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let threads = threads.clamp(1, items.len().max(1));
+    if threads == 1 {
+        return items.iter().map(f).collect();
+    }
+    let next = AtomicUsize::new(0);
+    let worker = || {
+        let mut done = Vec::new();
+        loop {
+            let i = next.fetch_add(1, Ordering::Relaxed);
+            let Some(item) = items.get(i) else {
+                return done;
+            };
+            done.push((i, f(item)));
+        }
+    };
+    let mut results: Vec<(usize, R)> = Vec::with_capacity(items.len());
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads).map(|_| scope.spawn(worker)).collect();
+        for handle in workers {
+            results.extend(handle.join().expect("worker thread panicked"));
+        }
+    });
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Reads a .xitignore file and returns a set of patterns.
+pub fn read_ignore_file(file_name: &Path) -> Result<HashSet<String>> {
+    let mut patterns = HashSet::new();
+    patterns.insert(".xit".to_string()); // Always ignore the .xit directory
+    patterns.insert(".git".to_string()); // Also ignore .git
+    patterns.insert("target".to_string()); // Ignore rust build directory
+
+    if let Ok(content) = fs::read_to_string(file_name) {
+        for line in content.lines() {
+            if !line.trim().is_empty() && !line.starts_with('#') {
+                patterns.insert(line.trim().to_string());
+            }
+        }
+    }
+    Ok(patterns)
+}
+
+/// Checks if a file or directory name matches one of the ignore patterns.
+fn is_ignored(name: &std::ffi::OsStr, ignore_patterns: &HashSet<String>) -> bool {
+    match name.to_str() {
+        Some(s) => ignore_patterns.contains(s),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_walk_is_sorted_and_skips_ignored() {
+        let dir = std::env::temp_dir().join("xit_test_worktree_walk");
+        let _ = fs::remove_dir_all(&dir);
+        for sub in ["a/b/c", "z", "target/debug", ".xit/objects"] {
+            fs::create_dir_all(dir.join(sub)).unwrap();
+        }
+        for file in [
+            "a/b/c/deep.txt",
+            "a/b.txt",
+            "a/b/one",
+            "z/last",
+            "top",
+            "target/debug/x",
+            ".xit/HEAD",
+        ] {
+            fs::write(dir.join(file), file).unwrap();
+        }
+        let patterns = read_ignore_file(&dir.join(".xitignore")).unwrap();
+
+        let expected = ["a/b.txt", "a/b/c/deep.txt", "a/b/one", "top", "z/last"];
+        for threads in [1, 2, 8] {
+            let files = walk(&dir, &dir, &patterns, threads).unwrap();
+            let paths: Vec<&str> = files.iter().map(|file| file.path.as_str()).collect();
+            assert_eq!(paths, expected, "{} threads", threads);
+        }
+        let files = walk(&dir, &dir.join("a/b"), &patterns, 4).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].path, "a/b/c/deep.txt");
+
+        // Symbolic links are listed as they are, wherever they point.
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink("a", dir.join("to_dir")).unwrap();
+            std::os::unix::fs::symlink("nowhere", dir.join("dangling")).unwrap();
+            let files = walk(&dir, &dir, &patterns, 2).unwrap();
+            let links: Vec<&str> = files
+                .iter()
+                .filter(|file| file.metadata.file_type().is_symlink())
+                .map(|file| file.path.as_str())
+                .collect();
+            assert_eq!(links, ["dangling", "to_dir"]);
+            assert_eq!(files.len(), expected.len() + 2);
+            assert_eq!(read_content(&dir.join("to_dir")).unwrap(), b"a");
+        }
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_map_parallel_keeps_order() {
+        let items: Vec<u64> = (0..1000).collect();
+        for threads in [1, 3, 16] {
+            let squares = map_parallel(&items, threads, |n| n * n);
+            assert_eq!(squares, items.iter().map(|n| n * n).collect::<Vec<_>>());
+        }
+        assert!(map_parallel(&[] as &[u8], 4, |b| *b).is_empty());
+    }
+}